@@ -0,0 +1,254 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The typed Code Dx operations, as a trait that `ApiClient` implements.
+//!
+//! Code that only needs these operations can take a `&CodeDxApi` (or be generic over `A: CodeDxApi`)
+//! instead of an `ApiClient`, so that its tests can pass a fake instead of needing a server:
+//!
+//! ```
+//! # use codedx_client::prelude::*;
+//! /// A server with one project, and nothing in it.
+//! struct OneProject;
+//!
+//! impl CodeDxApi for OneProject {
+//!     fn get_projects(&self) -> ApiResult<Vec<ApiProject>> {
+//!         Ok(vec![ApiProject { id: 1, name: "WebGoat".to_string(), parent_id: None }])
+//!     }
+//!     fn query_projects<'a>(&self, _: &'a ApiProjectFilter) -> ApiResult<Vec<ApiProject>> { self.get_projects() }
+//!     // everything else answers as such a server would
+//! #   fn create_project(&self, name: &str, parent_id: Option<u32>) -> ApiResult<ApiProject> {
+//! #       Ok(ApiProject { id: 2, name: name.to_string(), parent_id })
+//! #   }
+//! #   fn update_project(&self, project_id: u32, _: Option<&str>, _: Option<Option<u32>>) -> ApiResult<()> { found(project_id) }
+//! #   fn get_branches(&self, project_id: u32) -> ApiResult<Vec<ApiBranch>> { found(project_id).map(|_| Vec::new()) }
+//! #   fn get_analyses(&self, project_id: u32) -> ApiResult<Vec<ApiAnalysis>> { found(project_id).map(|_| Vec::new()) }
+//! #   fn get_analysis(&self, project_id: u32, _: u32) -> ApiResult<ApiAnalysis> {
+//! #       found(project_id).and(Err(ApiError::NonSuccess { status: StatusCode::NotFound, message: ApiErrorMessage::Nice("no such analysis".to_string()), request: None }))
+//! #   }
+//! #   fn start_analysis_with_inputs<'p>(&self, project_id: u32, _: Vec<UploadInput<'p>>) -> ApiResult<ApiAnalysisJobResponse> { Err(ApiError::ProjectNotFound(project_id)) }
+//! #   fn set_analysis_name(&self, project_id: u32, _: u32, _: &str) -> ApiResult<()> { Err(ApiError::ProjectNotFound(project_id)) }
+//! #   fn get_job_status(&self, job_id: &str) -> ApiResult<JobStatus> { Err(ApiError::JobNotFound(job_id.to_string())) }
+//! #   fn get_job_status_detailed(&self, job_id: &str) -> ApiResult<JobStatusResponse> { Err(ApiError::JobNotFound(job_id.to_string())) }
+//! #   fn cancel_job(&self, job_id: &str) -> ApiResult<()> { Err(ApiError::JobNotFound(job_id.to_string())) }
+//! #   fn get_system_info(&self) -> ApiResult<SystemInfo> { Ok(SystemInfo { version: "2023.4.2".to_string(), date: None }) }
+//! #   fn check_connection(&self) -> ApiResult<ConnectionReport> {
+//! #       let base_url = Url::parse("https://codedx.example.com/codedx").unwrap();
+//! #       Ok(ConnectionReport { base_url, server_version: Some("2023.4.2".to_string()), elapsed: ::std::time::Duration::from_millis(1) })
+//! #   }
+//! #   fn get_finding_statuses(&self, project_id: u32) -> ApiResult<Vec<FindingStatus>> { found(project_id).map(|_| Vec::new()) }
+//! #   fn query_findings(&self, project_id: u32, _: &FindingQuery) -> ApiResult<Vec<ApiFinding>> { found(project_id).map(|_| Vec::new()) }
+//! }
+//! # fn found(project_id: u32) -> ApiResult<()> {
+//! #     if project_id == 1 { Ok(()) } else { Err(ApiError::ProjectNotFound(project_id)) }
+//! # }
+//!
+//! fn count_projects(api: &CodeDxApi) -> ApiResult<usize> {
+//!     api.get_projects().map(|projects| projects.len())
+//! }
+//!
+//! assert_eq!(count_projects(&OneProject).unwrap(), 1);
+//! ```
+//!
+//! To test against a real `ApiClient` instead, point it at a `test_server::TestServer`, as the tests in the
+//! `tests` directory do.
+//!
+//! `ApiClient`'s own methods of the same names are still there, so calling them doesn't need this trait in scope.
+//! (`query_projects` here still takes the borrowed `ApiProjectFilter`, so that existing implementations keep
+//! compiling; `ApiClient::query_projects` also takes the owned `ProjectFilter`.)
+//! Everything that waits or polls (e.g. `poll_job_completion`) stays on `ApiClient`, being built out of these.
+
+use client::*;
+use upload::{UploadFile, UploadInput};
+
+/// The operations that `ApiClient` offers for each part of the Code Dx API; see `ApiClient` for what each one does.
+#[allow(deprecated)]
+pub trait CodeDxApi {
+    fn get_projects(&self) -> ApiResult<Vec<ApiProject>>;
+    fn query_projects<'a>(&self, filter: &'a ApiProjectFilter) -> ApiResult<Vec<ApiProject>>;
+    fn create_project(&self, name: &str, parent_id: Option<u32>) -> ApiResult<ApiProject>;
+    fn update_project(&self, project_id: u32, name: Option<&str>, parent_id: Option<Option<u32>>) -> ApiResult<()>;
+    fn get_branches(&self, project_id: u32) -> ApiResult<Vec<ApiBranch>>;
+
+    fn get_analyses(&self, project_id: u32) -> ApiResult<Vec<ApiAnalysis>>;
+    fn get_analysis(&self, project_id: u32, analysis_id: u32) -> ApiResult<ApiAnalysis>;
+    fn start_analysis_with_inputs<'p>(&self, project_id: u32, inputs: Vec<UploadInput<'p>>) -> ApiResult<ApiAnalysisJobResponse>;
+    fn set_analysis_name(&self, project_id: u32, analysis_id: u32, name: &str) -> ApiResult<()>;
+
+    /// Start an analysis of some files; the same as `start_analysis_with_inputs`, with every input a file.
+    fn start_analysis<'p, F: Into<UploadFile<'p>>>(&self, project_id: u32, files: Vec<F>) -> ApiResult<ApiAnalysisJobResponse>
+        where Self: Sized
+    {
+        let inputs = files.into_iter().map(|file| UploadInput::File(file.into())).collect();
+        self.start_analysis_with_inputs(project_id, inputs)
+    }
+
+    fn get_job_status(&self, job_id: &str) -> ApiResult<JobStatus>;
+    fn get_job_status_detailed(&self, job_id: &str) -> ApiResult<JobStatusResponse>;
+    fn cancel_job(&self, job_id: &str) -> ApiResult<()>;
+
+    fn get_system_info(&self) -> ApiResult<SystemInfo>;
+    fn check_connection(&self) -> ApiResult<ConnectionReport>;
+
+    fn get_finding_statuses(&self, project_id: u32) -> ApiResult<Vec<FindingStatus>>;
+    fn query_findings(&self, project_id: u32, query: &FindingQuery) -> ApiResult<Vec<ApiFinding>>;
+}
+
+#[allow(deprecated)]
+impl CodeDxApi for ApiClient {
+    fn get_projects(&self) -> ApiResult<Vec<ApiProject>> {
+        ApiClient::get_projects(self)
+    }
+    fn query_projects<'a>(&self, filter: &'a ApiProjectFilter) -> ApiResult<Vec<ApiProject>> {
+        ApiClient::query_projects(self, filter)
+    }
+    fn create_project(&self, name: &str, parent_id: Option<u32>) -> ApiResult<ApiProject> {
+        ApiClient::create_project(self, name, parent_id)
+    }
+    fn update_project(&self, project_id: u32, name: Option<&str>, parent_id: Option<Option<u32>>) -> ApiResult<()> {
+        ApiClient::update_project(self, project_id, name, parent_id)
+    }
+    fn get_branches(&self, project_id: u32) -> ApiResult<Vec<ApiBranch>> {
+        ApiClient::get_branches(self, project_id)
+    }
+    fn get_analyses(&self, project_id: u32) -> ApiResult<Vec<ApiAnalysis>> {
+        ApiClient::get_analyses(self, project_id)
+    }
+    fn get_analysis(&self, project_id: u32, analysis_id: u32) -> ApiResult<ApiAnalysis> {
+        ApiClient::get_analysis(self, project_id, analysis_id)
+    }
+    fn start_analysis_with_inputs<'p>(&self, project_id: u32, inputs: Vec<UploadInput<'p>>) -> ApiResult<ApiAnalysisJobResponse> {
+        ApiClient::start_analysis_with_inputs(self, project_id, inputs)
+    }
+    fn set_analysis_name(&self, project_id: u32, analysis_id: u32, name: &str) -> ApiResult<()> {
+        ApiClient::set_analysis_name(self, project_id, analysis_id, name)
+    }
+    fn get_job_status(&self, job_id: &str) -> ApiResult<JobStatus> {
+        ApiClient::get_job_status(self, job_id)
+    }
+    fn get_job_status_detailed(&self, job_id: &str) -> ApiResult<JobStatusResponse> {
+        ApiClient::get_job_status_detailed(self, job_id)
+    }
+    fn cancel_job(&self, job_id: &str) -> ApiResult<()> {
+        ApiClient::cancel_job(self, job_id)
+    }
+    fn get_system_info(&self) -> ApiResult<SystemInfo> {
+        ApiClient::get_system_info(self)
+    }
+    fn check_connection(&self) -> ApiResult<ConnectionReport> {
+        ApiClient::check_connection(self)
+    }
+    fn get_finding_statuses(&self, project_id: u32) -> ApiResult<Vec<FindingStatus>> {
+        ApiClient::get_finding_statuses(self, project_id)
+    }
+    fn query_findings(&self, project_id: u32, query: &FindingQuery) -> ApiResult<Vec<ApiFinding>> {
+        ApiClient::query_findings(self, project_id, query)
+    }
+}
+
+/// An operation called through the trait, with a body that the server might answer it with,
+/// and whether it reads a JSON body (as opposed to only looking at the status).
+#[cfg(test)]
+struct TestOperation {
+    name: &'static str,
+    success_body: ::serde_json::Value,
+    reads_json: bool,
+    call: Box<Fn(&CodeDxApi) -> ApiResult<()>>,
+}
+
+#[cfg(test)]
+fn operation<F>(name: &'static str, success_body: ::serde_json::Value, reads_json: bool, call: F) -> TestOperation
+    where F: Fn(&CodeDxApi) -> ApiResult<()> + 'static
+{
+    TestOperation { name, success_body, reads_json, call: Box::new(call) }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+fn test_operations() -> Vec<TestOperation> {
+    use std::io::Cursor;
+    vec![
+        operation("get_projects", json!([{ "id": 5, "name": "WebGoat", "parentId": null }]), true, |api| api.get_projects().map(|_| ())),
+        operation("query_projects", json!([]), true, |api| api.query_projects(&ApiProjectFilter { name: Some("Web"), metadata: None }).map(|_| ())),
+        operation("create_project", json!({ "id": 6, "name": "New", "parentId": null }), true, |api| api.create_project("New", None).map(|_| ())),
+        operation("update_project", json!({}), false, |api| api.update_project(5, Some("Renamed"), None)),
+        operation("get_branches", json!([{ "id": 1, "name": "main", "isDefault": true }]), true, |api| api.get_branches(5).map(|_| ())),
+        operation("get_analyses", json!([{ "id": 81 }]), true, |api| api.get_analyses(5).map(|_| ())),
+        operation("get_analysis", json!({ "id": 81, "state": "complete" }), true, |api| api.get_analysis(5, 81).map(|_| ())),
+        operation("start_analysis_with_inputs", json!({ "analysisId": 81, "jobId": "j1" }), true, |api| {
+            let input = UploadInput::Stream { name: "scan.xml".to_string(), content: Box::new(Cursor::new(b"<report/>".to_vec())) };
+            api.start_analysis_with_inputs(5, vec![input]).map(|_| ())
+        }),
+        operation("set_analysis_name", json!({}), false, |api| api.set_analysis_name(5, 81, "nightly")),
+        operation("get_job_status", json!({ "jobId": "j1", "status": "completed" }), true, |api| api.get_job_status("j1").map(|_| ())),
+        operation("get_job_status_detailed", json!({ "jobId": "j1", "status": "running" }), true, |api| api.get_job_status_detailed("j1").map(|_| ())),
+        operation("cancel_job", json!({}), false, |api| api.cancel_job("j1")),
+        operation("get_system_info", json!({ "version": "2023.4.2" }), true, |api| api.get_system_info().map(|_| ())),
+        operation("check_connection", json!([]), true, |api| api.check_connection().map(|_| ())),
+        operation("get_finding_statuses", json!({ "new": { "display": "New" } }), true, |api| api.get_finding_statuses(5).map(|_| ())),
+        operation("query_findings", json!([{ "id": 1 }]), true, |api| api.query_findings(5, &FindingQuery::default()).map(|_| ())),
+    ]
+}
+
+#[test]
+fn test_operations_succeed(){
+    use test_server::*;
+    for op in test_operations() {
+        let body = op.success_body.clone();
+        let server = TestServer::start(move |_| TestResponse::json(200, body.clone()));
+        if let Err(e) = (op.call)(&server.client()) {
+            panic!("{} failed: {:?}", op.name, e);
+        }
+    }
+}
+
+#[test]
+fn test_operations_report_nice_errors(){
+    use test_server::*;
+    use StatusCode;
+    for op in test_operations() {
+        let server = TestServer::start(|_| TestResponse::json(400, json!({ "error": "Project name is invalid" })));
+        match (op.call)(&server.client()) {
+            Err(ApiError::NonSuccess { status: StatusCode::BadRequest, message: ApiErrorMessage::Nice(ref message), .. }) => assert_eq!(message, "Project name is invalid", "{}", op.name),
+            other => panic!("{}: expected the 400's message, got {:?}", op.name, other),
+        }
+    }
+}
+
+#[test]
+fn test_operations_keep_raw_error_bodies(){
+    use test_server::*;
+    use StatusCode;
+    for op in test_operations() {
+        let server = TestServer::start(|_| TestResponse::status(500).body("java.lang.NullPointerException\n\tat Foo.bar"));
+        match (op.call)(&server.client()) {
+            Err(ApiError::NonSuccess { status: StatusCode::InternalServerError, message: ApiErrorMessage::Raw(ref body), .. }) => assert!(body.starts_with("java.lang.NullPointerException"), "{}", op.name),
+            other => panic!("{}: expected the 500's body, got {:?}", op.name, other),
+        }
+    }
+}
+
+#[test]
+fn test_operations_reject_malformed_json(){
+    use test_server::*;
+    for op in test_operations().into_iter().filter(|op| op.reads_json) {
+        let server = TestServer::start(|_| TestResponse::status(200).header("Content-Type", "application/json").body("{\"id\": 5, \"na"));
+        match (op.call)(&server.client()) {
+            Err(ApiError::UnexpectedBody { ref snippet, .. }) => assert_eq!(snippet, "{\"id\": 5, \"na", "{}", op.name),
+            other => panic!("{}: expected an unexpected body error, got {:?}", op.name, other),
+        }
+    }
+}
@@ -0,0 +1,776 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A futures-based client, for async code (with the `async` cargo feature).
+//!
+//! `AsyncApiClient` has the typed operations of `ApiClient`, each returning a `Future` (from `futures` 0.1)
+//! right away instead of waiting for the server. Its requests are sent with reqwest's async client, on the
+//! tokio-core event loop whose `Handle` the client was made with, and a poll such as `poll_job_completion`
+//! waits between checks with a timer on the same loop, so no thread is ever blocked on the network.
+//! The client and its futures belong to the loop's thread (they aren't `Send`).
+//!
+//! The models, `ApiError` and `PollingStrategy` are the ones the blocking client uses, and so is most of what it
+//! does for a request: authentication, `--header`s, proxy credentials, `--dry-run`, the run log, retries (see the
+//! `retry` module), redirects (see the `redirect` module), and the fallback from the `api/` namespace to `x/`.
+//! What it doesn't do is keep to `max_concurrent_requests` (a program can keep as many of the futures going
+//! as it likes, e.g. with `Stream::buffer_unordered`), or keep a session alive during an upload.
+//!
+//! An upload's multipart form is read into memory before it's sent, rather than streamed from disk like
+//! `ApiClient::start_analysis` does it: reqwest's async client only takes a body that's already in memory.
+//! The files are read on the loop's thread, as the operation starts.
+//!
+//! ```no_run
+//! extern crate codedx_client;
+//! extern crate futures;
+//! extern crate tokio_core;
+//!
+//! use codedx_client::prelude::*;
+//! use futures::Future;
+//! use futures::future::join_all;
+//! use std::path::PathBuf;
+//! use std::time::Duration;
+//! use tokio_core::reactor::Core;
+//!
+//! fn main() {
+//!     let mut core = Core::new().unwrap();
+//!     let base_url = Url::parse("https://localhost/codedx").unwrap();
+//!     let auth = ClientAuth::ApiKey("8e218b38-fcdd-453d-8f78-185f7d1d9fa7".to_string());
+//!     let client = AsyncApiClient::new(ClientConfig::new(base_url, auth), &core.handle()).expect("couldn't set up the client");
+//!
+//!     // start an analysis of the same file in projects 5, 6 and 7 at once, then wait for all of them
+//!     let analyses = join_all((5..8).map(|project_id| {
+//!         let client = client.clone();
+//!         client.start_analysis(project_id, vec![PathBuf::from("webgoat.zip")])
+//!             .and_then(move |started| client.poll_job_completion(started.job_id, Duration::from_secs(5)))
+//!     }));
+//!     println!("{:?}", core.run(analyses));
+//! }
+//! ```
+
+use check;
+use client::*;
+use config::{ApiKey, ClientAuth, ClientConfig, Verbosity};
+use futures::{future, stream, Future, Stream};
+use futures::future::{Either, Loop};
+use hyper::{Method, StatusCode};
+use polling::PollInterval;
+use proxy::{self, ProxyCredentials};
+use redact::redact_url;
+use redirect;
+use reqwest;
+use reqwest::unstable::async as http;
+use runlog::RunLog;
+use serde::de::DeserializeOwned;
+use serde_json;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::{Handle, Timeout};
+use upload::UploadInput;
+use url::Url;
+
+/// The result of an `AsyncApiClient` operation, once it's done.
+pub type ApiFuture<T> = Box<Future<Item = T, Error = ApiError>>;
+
+/// Sends the typed operations' requests with reqwest's async client, returning futures of their results.
+///
+/// Cloning an `AsyncApiClient` is cheap, and the clones share the same connections and state.
+#[derive(Clone)]
+pub struct AsyncApiClient {
+    inner: Rc<Shared>,
+}
+
+struct Shared {
+    config: Arc<ClientConfig>,
+    client: http::Client,
+    handle: Handle,
+    log: RunLog,
+    /// Which namespace ("api" or "x") worked for each endpoint used with `with_stable_fallback`.
+    namespaces: RefCell<HashMap<&'static str, &'static str>>,
+    /// What to put in the `Proxy-Authorization` header of requests forwarded by the proxy.
+    proxy_credentials: Option<ProxyCredentials>,
+}
+
+/// A request's body, kept whole so that the request can be sent again after a retry or a redirect.
+#[derive(Clone)]
+enum Payload {
+    Json(serde_json::Value),
+    /// A multipart form that has been read into memory, with its `Content-Type`.
+    Form(String, Vec<u8>),
+    None,
+}
+impl Payload {
+    /// Whether the request may be sent again with this body; like a streamed one, an upload never is,
+    /// in case the server got it the first time.
+    fn can_replay(&self) -> bool {
+        match *self {
+            Payload::Form(..) => false,
+            _ => true,
+        }
+    }
+}
+
+/// One try at sending a request, in `AsyncApiClient::send`'s loop.
+struct Attempt {
+    method: Method,
+    url: Url,
+    payload: Payload,
+    /// Which retry this is (starting at 1 for the first try), and how many redirects led to it.
+    retry_number: u32,
+    redirects: u32,
+}
+
+/// A response to a request, with its whole body.
+struct Received {
+    request: String,
+    meta: ResponseMeta,
+    body: Vec<u8>,
+}
+impl Received {
+    /// The response, if its status is a success, and otherwise an `ApiError::NonSuccess` with the server's message.
+    fn expect_success(self) -> ApiResult<Received> {
+        if self.meta.status.is_success() {
+            return Ok(self);
+        }
+        let message = ApiErrorMessage::from_text(self.meta.header("Content-Type"), self.meta.header("Server"), &String::from_utf8_lossy(&self.body));
+        Err(ApiError::NonSuccess { status: self.meta.status, message, request: Some(self.request) })
+    }
+
+    /// Deserialize the body as a `T`, like `ApiResponse::expect_json`.
+    fn into_json<T: DeserializeOwned>(self) -> ApiResult<T> {
+        decode_response(&self.request, &self.meta, &String::from_utf8_lossy(&self.body))
+    }
+}
+
+impl AsyncApiClient {
+    /// Set up a client for the server described by `config`, that sends its requests on the event loop of `handle`.
+    ///
+    /// Fails like `ApiClient::new`; no requests are made.
+    pub fn new<C: Into<Arc<ClientConfig>>>(config: C, handle: &Handle) -> ApiResult<AsyncApiClient> {
+        let config = config.into();
+        let proxy_decision = proxy::decide(&config.proxy, &config.base_url);
+        let client = build_async_client(&config, &proxy_decision, handle)?;
+        let (log, proxy_credentials) = client_setup(&config, &proxy_decision)?;
+        Ok(AsyncApiClient {
+            inner: Rc::new(Shared { config, client, handle: handle.clone(), log, namespaces: RefCell::new(HashMap::new()), proxy_credentials }),
+        })
+    }
+
+    pub fn get_config(&self) -> &ClientConfig {
+        self.inner.config.as_ref()
+    }
+
+    pub fn get_projects(&self) -> ApiFuture<Vec<ApiProject>> {
+        self.query_all_projects(None)
+    }
+
+    /// Get every project matching `filter`, like `ApiClient::query_projects`.
+    pub fn query_projects(&self, filter: ProjectFilter) -> ApiFuture<Vec<ApiProject>> {
+        self.query_all_projects(Some(filter))
+    }
+
+    /// Get the project with the given id; the error for one that doesn't exist is an `ApiError::ProjectNotFound`.
+    pub fn get_project(&self, project_id: u32) -> ApiFuture<ApiProject> {
+        let found = self.with_stable_fallback("project", move |client, namespace| {
+            client.get_json::<ProjectResponse>(&[namespace, "projects", &project_id.to_string()])
+        });
+        Box::new(found.then(move |found| match found {
+            Ok(found) => Ok(found.into_project()),
+            Err(ApiError::NonSuccess { status: StatusCode::NotFound, .. }) => Err(ApiError::ProjectNotFound(project_id)),
+            Err(e) => Err(e),
+        }))
+    }
+
+    pub fn create_project(&self, name: String, parent_id: Option<u32>) -> ApiFuture<ApiProject> {
+        let mut body = json!({ "name": name });
+        if let Some(parent_id) = parent_id {
+            body["parentId"] = json!(parent_id);
+        }
+        self.json_request(Method::Post, &["api", "projects"], body, false)
+    }
+
+    pub fn get_branches(&self, project_id: u32) -> ApiFuture<Vec<ApiBranch>> {
+        self.with_stable_fallback("project-branches", move |client, namespace| {
+            client.get_json(&[namespace, "projects", &project_id.to_string(), "branches"])
+        })
+    }
+
+    pub fn get_analyses(&self, project_id: u32) -> ApiFuture<Vec<ApiAnalysis>> {
+        self.with_stable_fallback("analyses", move |client, namespace| {
+            client.get_json(&[namespace, "projects", &project_id.to_string(), "analyses"])
+        })
+    }
+
+    pub fn get_analysis(&self, project_id: u32, analysis_id: u32) -> ApiFuture<ApiAnalysis> {
+        self.with_stable_fallback("analysis", move |client, namespace| {
+            client.get_json(&[namespace, "projects", &project_id.to_string(), "analyses", &analysis_id.to_string()])
+        })
+    }
+
+    /// Start an analysis of some files. Unlike `ApiClient::start_analysis`, the files are read into memory
+    /// before they're sent (see the module docs).
+    pub fn start_analysis(&self, project_id: u32, files: Vec<PathBuf>) -> ApiFuture<ApiAnalysisJobResponse> {
+        let inputs: Vec<UploadInput> = files.iter().map(|path| UploadInput::from(path.as_path())).collect();
+        self.upload_analysis(project_id, inputs)
+    }
+
+    /// Start an analysis of some inputs, e.g. an `UploadInput::Stream` of content that isn't in a file.
+    pub fn start_analysis_with_inputs(&self, project_id: u32, inputs: Vec<UploadInput<'static>>) -> ApiFuture<ApiAnalysisJobResponse> {
+        self.upload_analysis(project_id, inputs)
+    }
+
+    pub fn set_analysis_name(&self, project_id: u32, analysis_id: u32, name: String) -> ApiFuture<()> {
+        let body = json!({ "name": name });
+        let renamed = self.with_stable_fallback("analysis", move |client, namespace| {
+            client.request(Method::Put, &[namespace, "projects", &project_id.to_string(), "analyses", &analysis_id.to_string()], Payload::Json(body.clone()), false)
+        });
+        Box::new(renamed.map(|_| ()))
+    }
+
+    pub fn get_job_status(&self, job_id: String) -> ApiFuture<JobStatus> {
+        Box::new(self.get_job_status_detailed(job_id).map(|response| response.status))
+    }
+
+    /// Like `get_job_status`, but including the job's progress, failure reason, and what it's waiting for.
+    ///
+    /// If the server doesn't know about the job, the error is an `ApiError::JobNotFound`.
+    pub fn get_job_status_detailed(&self, job_id: String) -> ApiFuture<JobStatusResponse> {
+        let found = self.send(Method::Get, &["api", "jobs", &job_id], Payload::None, true);
+        Box::new(found.and_then(move |received| {
+            if received.meta.status == StatusCode::NotFound {
+                Err(ApiError::JobNotFound(job_id))
+            } else {
+                received.expect_success()?.into_json::<JobStatusResponse>()
+            }
+        }))
+    }
+
+    /// Wait for a job to be done, like `ApiClient::poll_job_completion`, checking its status
+    /// after each of the `polling_strategy`'s waits (on the event loop's timer).
+    pub fn poll_job_completion<P>(&self, job_id: String, polling_strategy: P) -> ApiFuture<JobStatus>
+        where P: PollingStrategy<JobStatus> + 'static
+    {
+        let client = self.clone();
+        let polling_strategy = Rc::new(polling_strategy);
+        Box::new(future::loop_fn((1, None), move |(iteration_number, last_status): (usize, Option<JobStatus>)| {
+            let client = client.clone();
+            let job_id = job_id.clone();
+            let polling_strategy = polling_strategy.clone();
+            client.get_job_status_detailed(job_id.clone()).and_then(move |response| {
+                let status = response.status;
+                if last_status != Some(status) {
+                    client.inner.log.event("job-status", json!({ "jobId": job_id, "status": status, "reason": response.reason, "poll": iteration_number }));
+                    if status == JobStatus::Unknown {
+                        warn_for(&client.inner.config, &format!("job {} has a status this client doesn't recognize; assuming it isn't finished yet", job_id));
+                    }
+                }
+                if status.is_ready() {
+                    return Either::A(future::ok(Loop::Break(status)));
+                }
+                match polling_strategy.next_wait(iteration_number, &status) {
+                    Some(wait_dur) => Either::B(client.wait(wait_dur).map(move |_| Loop::Continue((iteration_number + 1, Some(status))))),
+                    None => Either::A(future::ok(Loop::Break(status))),
+                }
+            })
+        }))
+    }
+
+    /// Like `poll_job_completion`, checking as often as the client's config says (see `ClientConfig::poll_interval`).
+    pub fn wait_for_job(&self, job_id: String) -> ApiFuture<JobStatus> {
+        let interval: PollInterval = self.inner.config.poll_interval.unwrap_or(PollInterval::Fixed(Duration::from_secs(2)));
+        self.poll_job_completion(job_id, interval)
+    }
+
+    /// Ask the server to cancel a job, like `ApiClient::cancel_job`: a job that has already finished counts as cancelled.
+    pub fn cancel_job(&self, job_id: String) -> ApiFuture<()> {
+        let client = self.clone();
+        let cancelled = self.request(Method::Delete, &["api", "jobs", &job_id], Payload::None, false);
+        Box::new(cancelled.then(move |result| match result {
+            Ok(_) => {
+                client.inner.log.event("job-cancelled", json!({ "jobId": job_id }));
+                Either::A(future::ok(()))
+            },
+            Err(ref e) if e.is_not_found() => Either::A(future::err(ApiError::JobNotFound(job_id))),
+            Err(e @ ApiError::NonSuccess { .. }) => Either::B(client.get_job_status(job_id).then(move |status| match status {
+                Ok(status) if status.is_ready() => Ok(()),
+                _ => Err(e),
+            })),
+            Err(e) => Either::A(future::err(e)),
+        }))
+    }
+
+    pub fn get_system_info(&self) -> ApiFuture<SystemInfo> {
+        self.with_stable_fallback("system-info", |client, namespace| {
+            client.get_json(&[namespace, "system-info"])
+        })
+    }
+
+    /// Make one cheap authenticated request, like `ApiClient::check_connection`.
+    pub fn check_connection(&self) -> ApiFuture<ConnectionReport> {
+        let started = Instant::now();
+        let client = self.clone();
+        let queried = self.with_stable_fallback("projects-query", |client, namespace| {
+            client.request(Method::Post, &[namespace, "projects", "query"], Payload::Json(json!({ "filter": {}, "offset": 0, "limit": 1 })), true)
+        });
+        Box::new(queried.and_then(move |received| {
+            let elapsed = started.elapsed();
+            if check::looks_like_html(&received.body) {
+                return Either::A(future::err(ApiError::NotCodeDx { request: received.request }));
+            }
+            if let Err(e) = decode_json::<serde_json::Value>(&received.request, &String::from_utf8_lossy(&received.body)) {
+                return Either::A(future::err(e));
+            }
+            // the version is nice to have, but older servers don't report it
+            Either::B(client.get_system_info().then(move |info| {
+                let server_version = info.ok().map(|info| info.version).filter(|version| !version.is_empty());
+                client.inner.log.event("check", json!({ "elapsedMs": duration_millis(elapsed), "serverVersion": server_version }));
+                Ok(ConnectionReport { base_url: client.inner.config.base_url.clone(), server_version, elapsed })
+            }))
+        }))
+    }
+
+    /// Get the statuses that a project's findings can have, including any custom ones.
+    pub fn get_finding_statuses(&self, project_id: u32) -> ApiFuture<Vec<FindingStatus>> {
+        let body = self.get_json::<serde_json::Value>(&["api", "projects", &project_id.to_string(), "statuses"]);
+        Box::new(body.map(|body| parse_statuses(&body)))
+    }
+
+    pub fn query_findings(&self, project_id: u32, query: FindingQuery) -> ApiFuture<Vec<ApiFinding>> {
+        let body = serde_json::to_value(&query).unwrap();
+        self.json_request(Method::Post, &["api", "projects", &project_id.to_string(), "findings", "table"], body, true)
+    }
+
+    /// Get the definitions of the metadata fields that projects can have.
+    pub fn get_project_fields(&self) -> ApiFuture<Vec<ApiProjectField>> {
+        self.with_stable_fallback("project-fields", |client, namespace| {
+            client.get_json(&[namespace, "projects", "fields"])
+        })
+    }
+
+    fn get_project_metadata_values(&self, project_id: u32) -> ApiFuture<HashMap<String, serde_json::Value>> {
+        self.with_stable_fallback("project-metadata", move |client, namespace| {
+            client.get_json(&[namespace, "projects", &project_id.to_string(), "metadata"])
+        })
+    }
+
+    fn upload_analysis(&self, project_id: u32, inputs: Vec<UploadInput>) -> ApiFuture<ApiAnalysisJobResponse> {
+        let payload = analysis_form(inputs, None).and_then(|form| {
+            let content_type = form.content_type();
+            let mut body = Vec::new();
+            form.into_reader().read_to_end(&mut body)?;
+            Ok(Payload::Form(content_type, body))
+        });
+        match payload {
+            Ok(payload) => self.json_request_with(Method::Post, &["api", "projects", &project_id.to_string(), "analysis"], payload, false),
+            Err(e) => Box::new(future::err(e)),
+        }
+    }
+
+    fn query_all_projects(&self, filter: Option<ProjectFilter>) -> ApiFuture<Vec<ApiProject>> {
+        let collected = match filter {
+            Some(filter) => if filter.is_single_query() { self.collect_pages(Some(filter)) } else { self.collect_filtered(filter) },
+            None => self.collect_pages(None),
+        };
+        let client = self.clone();
+        Box::new(collected.map(move |(projects, maybe_truncated)| {
+            if maybe_truncated {
+                warn_for(&client.inner.config, &format!("the server returned exactly {} projects and seems to ignore paging; some projects may be missing", projects.len()));
+                client.inner.log.event("projects-maybe-truncated", json!({ "count": projects.len() }));
+            }
+            projects
+        }))
+    }
+
+    /// Like `ApiClient::collect_filtered`: runs each of the filter's `server_queries` in turn, keeping the first copy
+    /// of each project, then checks the rest of the filter against each project's metadata.
+    fn collect_filtered(&self, filter: ProjectFilter) -> ApiFuture<(Vec<ApiProject>, bool)> {
+        let needs_metadata = filter.metadata.iter().flat_map(|metadata| metadata.values()).any(|value| value.exact_values().is_none());
+        let client = self.clone();
+        let collected = stream::iter_ok::<_, ApiError>(filter.server_queries()).fold((Vec::new(), false), move |(mut projects, mut maybe_truncated): (Vec<ApiProject>, bool), query| {
+            client.collect_pages(Some(query)).map(move |(batch, truncated)| {
+                maybe_truncated |= truncated;
+                for project in batch {
+                    if !projects.iter().any(|p| p.id == project.id) {
+                        projects.push(project);
+                    }
+                }
+                (projects, maybe_truncated)
+            })
+        });
+        let client = self.clone();
+        Box::new(collected.and_then(move |(projects, maybe_truncated)| {
+            if !needs_metadata || projects.is_empty() {
+                return Either::A(future::ok((projects, maybe_truncated)));
+            }
+            let filter = Rc::new(filter);
+            let values_client = client.clone();
+            let matching = client.get_project_fields().and_then(move |fields| {
+                stream::iter_ok::<_, ApiError>(projects).fold(Vec::new(), move |mut matching: Vec<ApiProject>, project: ApiProject| {
+                    let fields = fields.clone();
+                    let filter = filter.clone();
+                    values_client.get_project_metadata_values(project.id).map(move |values| {
+                        let metadata = combine_metadata(fields, &values).into_iter()
+                            .filter_map(|field| field.value.map(|value| (field.name, value)))
+                            .collect();
+                        if filter.matches_unqueried_metadata(&metadata) {
+                            matching.push(project);
+                        }
+                        matching
+                    })
+                })
+            });
+            Either::B(matching.map(move |matching| (matching, maybe_truncated)))
+        }))
+    }
+
+    /// Like `ApiClient::collect_pages`: load pages of projects until a short page, returning the projects
+    /// and whether the list looks truncated.
+    fn collect_pages(&self, filter: Option<ProjectFilter>) -> ApiFuture<(Vec<ApiProject>, bool)> {
+        let client = self.clone();
+        let page_size = PROJECT_PAGE_SIZE;
+        Box::new(future::loop_fn((Vec::new(), 0), move |(mut projects, offset): (Vec<ApiProject>, u32)| {
+            client.query_page(filter.as_ref(), offset, page_size).map(move |batch| {
+                if batch.len() > page_size as usize {
+                    // the server ignored the limit, and presumably sent everything
+                    return Loop::Break((batch, false));
+                }
+                let is_repeat = match (projects.first(), batch.first()) {
+                    (Some(first), Some(next)) => first.id == next.id,
+                    _ => false,
+                };
+                if is_repeat {
+                    let maybe_truncated = projects.len() == page_size as usize;
+                    return Loop::Break((projects, maybe_truncated));
+                }
+                let is_last_page = batch.len() < page_size as usize;
+                projects.extend(batch);
+                if is_last_page {
+                    Loop::Break((projects, false))
+                } else {
+                    Loop::Continue((projects, offset + page_size))
+                }
+            })
+        }))
+    }
+
+    fn query_page(&self, filter: Option<&ProjectFilter>, offset: u32, limit: u32) -> ApiFuture<Vec<ApiProject>> {
+        let filter = match filter {
+            Some(filter) => json!(filter),
+            None => json!({}),
+        };
+        let body = json!({ "filter": filter, "offset": offset, "limit": limit });
+        self.with_stable_fallback("projects-query", move |client, namespace| {
+            client.json_request(Method::Post, &[namespace, "projects", "query"], body.clone(), true)
+        })
+    }
+
+    /// Like `ApiClient::with_stable_fallback`: `request` is called with `"api"` first, and if the server
+    /// responds with a 404, with `"x"`; the namespace that works is remembered for the endpoint.
+    fn with_stable_fallback<T, F>(&self, endpoint: &'static str, request: F) -> ApiFuture<T>
+        where T: 'static, F: Fn(&AsyncApiClient, &'static str) -> ApiFuture<T> + 'static
+    {
+        let known = self.inner.namespaces.borrow().get(endpoint).cloned();
+        if let Some(namespace) = known {
+            return request(self, namespace);
+        }
+
+        let client = self.clone();
+        let tried = request(self, "api").then(move |result| match result {
+            Err(ApiError::NonSuccess { status: StatusCode::NotFound, .. }) => Either::A(request(&client, "x").then(|result| Ok::<_, ApiError>(("x", result)))),
+            other => Either::B(future::ok(("api", other))),
+        });
+        let client = self.clone();
+        Box::new(tried.and_then(move |(namespace, result)| {
+            if namespace_works(&result) {
+                client.inner.namespaces.borrow_mut().insert(endpoint, namespace);
+                client.inner.log.event("namespace", json!({ "endpoint": endpoint, "namespace": namespace }));
+            }
+            result
+        }))
+    }
+
+    fn get_json<T: DeserializeOwned + 'static>(&self, path_segments: &[&str]) -> ApiFuture<T> {
+        self.json_request_with(Method::Get, path_segments, Payload::None, true)
+    }
+
+    fn json_request<T: DeserializeOwned + 'static>(&self, method: Method, path_segments: &[&str], body: serde_json::Value, reads: bool) -> ApiFuture<T> {
+        self.json_request_with(method, path_segments, Payload::Json(body), reads)
+    }
+
+    /// Send a request, expecting a successful response with a body that's a `T`.
+    fn json_request_with<T: DeserializeOwned + 'static>(&self, method: Method, path_segments: &[&str], payload: Payload, reads: bool) -> ApiFuture<T> {
+        Box::new(self.request(method, path_segments, payload, reads).and_then(Received::into_json::<T>))
+    }
+
+    /// Send a request, expecting a successful response (see `Received::expect_success`).
+    fn request(&self, method: Method, path_segments: &[&str], payload: Payload, reads: bool) -> ApiFuture<Received> {
+        Box::new(self.send(method, path_segments, payload, reads).and_then(Received::expect_success))
+    }
+
+    /// Send a request to the API as `ApiClient::api_request` does, retrying it and following redirects,
+    /// except that `reads` says whether it's safe to send during a dry run.
+    fn send(&self, method: Method, path_segments: &[&str], payload: Payload, reads: bool) -> ApiFuture<Received> {
+        let url = self.inner.config.api_url(path_segments);
+        let request_desc = format!("{} {}", method, url.path());
+        let reads = reads || method == Method::Get || method == Method::Head;
+        if self.inner.config.dry_run && !reads {
+            eprintln!("# dry run: not sending {} {}", method, redact_url(&url));
+            self.inner.log.event("dry-run", json!({ "request": request_desc }));
+            return Box::new(future::err(ApiError::DryRun { request: request_desc }));
+        }
+        let client = self.clone();
+        let first = Attempt { method, url, payload, retry_number: 1, redirects: 0 };
+        Box::new(future::loop_fn(first, move |attempt| {
+            let client = client.clone();
+            let request_desc = request_desc.clone();
+            client.send_once(&attempt, &request_desc).then(move |result| client.after_attempt(attempt, request_desc, result))
+        }))
+    }
+
+    /// Decide what comes after an attempt at sending a request: following a redirect, a retry
+    /// (after a wait), or the attempt's result.
+    fn after_attempt(&self, attempt: Attempt, request_desc: String, result: ApiResult<Received>) -> ApiFuture<Loop<Received, Attempt>> {
+        let config = &self.inner.config;
+        if let Ok(ref received) = result {
+            let status = received.meta.status;
+            if redirect::is_redirect(status) {
+                let location = redirect::location_in(&attempt.url, &received.meta.headers);
+                let followed = match config.redirects.follow(attempt.redirects, &attempt.method, &attempt.url, status, location.as_ref()) {
+                    Ok(_) if !attempt.payload.can_replay() => Err("the request's file upload can't be sent again"),
+                    other => other,
+                };
+                let next: ApiResult<Loop<Received, Attempt>> = match followed {
+                    Ok((method, url)) => {
+                        self.inner.log.event("redirect", json!({ "request": request_desc, "location": redact_url(&url), "method": method.to_string() }));
+                        debug!("following the redirect from {} to {} {}", request_desc, method, redact_url(&url));
+                        // a 303 turns the request into a GET, which has no body
+                        let payload = if method == attempt.method { attempt.payload } else { Payload::None };
+                        Ok(Loop::Continue(Attempt { method, url, payload, retry_number: attempt.retry_number, redirects: attempt.redirects + 1 }))
+                    },
+                    Err(reason) => {
+                        let location = location.map(|location| redact_url(&location));
+                        Err(ApiError::Redirected { request: request_desc, status, location, reason })
+                    },
+                };
+                return Box::new(future::result(next));
+            }
+        }
+        let policy = config.retry;
+        let retryable = policy.allows(&attempt.method) && attempt.retry_number <= policy.max_retries && attempt.payload.can_replay();
+        let wait = match result {
+            Ok(ref received) if retryable => policy.wait_after_status(attempt.retry_number, received.meta.status, &received.meta.headers)
+                .map(|wait| (wait, received.meta.status.to_string())),
+            Err(ApiError::Protocol { cause: ref e, .. }) if retryable => policy.wait_after_error(attempt.retry_number, e).map(|wait| (wait, e.to_string())),
+            _ => None,
+        };
+        match wait {
+            Some((wait, reason)) => {
+                self.inner.log.event("retry", json!({ "request": request_desc, "retry": attempt.retry_number, "reason": reason, "waitMs": duration_millis(wait) }));
+                if config.verbosity() >= Verbosity::Normal {
+                    eprintln!("# {} failed ({}); retrying in {:.1}s ({} of {})",
+                        request_desc, reason, duration_millis(wait) as f64 / 1000.0, attempt.retry_number, policy.max_retries);
+                }
+                Box::new(self.wait(wait).map(move |_| -> Loop<Received, Attempt> {
+                    Loop::Continue(Attempt { retry_number: attempt.retry_number + 1, ..attempt })
+                }))
+            },
+            None => {
+                let done: ApiResult<Loop<Received, Attempt>> = result.map(Loop::Break);
+                Box::new(future::result(done))
+            },
+        }
+    }
+
+    /// Send a request once, reading the whole response, and log how it went.
+    fn send_once(&self, attempt: &Attempt, request_desc: &str) -> ApiFuture<Received> {
+        let config = &self.inner.config;
+        let has_body = match attempt.payload {
+            Payload::None => false,
+            _ => true,
+        };
+        let mut request_builder = self.inner.client.request(attempt.method.clone(), attempt.url.clone());
+        request_builder.header(reqwest::header::UserAgent::new(config.user_agent()));
+        match config.auth_info {
+            ClientAuth::Basic { ref username, ref password } => {
+                request_builder.basic_auth(username.clone(), Some(password.clone()));
+            },
+            ClientAuth::ApiKey(ref key) => {
+                request_builder.header(ApiKey(key.clone()));
+            },
+        }
+        // the client's own headers win over the same ones given with `--header` (which `new` warns about)
+        let mut extra_headers = reqwest::header::Headers::new();
+        for &&(ref name, ref value) in config.extra_headers_for(has_body).iter() {
+            extra_headers.set_raw(name.clone(), value.clone());
+        }
+        request_builder.headers(extra_headers);
+        if let (Some(credentials), "http") = (self.inner.proxy_credentials.as_ref(), attempt.url.scheme()) {
+            request_builder.header(credentials.header());
+        }
+        match attempt.payload {
+            Payload::Json(ref json) => {
+                request_builder.json(json);
+            },
+            Payload::Form(ref content_type, ref body) => {
+                request_builder.header(reqwest::header::ContentType(content_type.parse().unwrap()));
+                request_builder.body(body.clone());
+            },
+            Payload::None => (),
+        }
+
+        let received = request_builder.send().and_then(|response| {
+            let meta = ResponseMeta { status: response.status(), headers: response.headers().clone() };
+            response.into_body().concat2().map(move |body| (meta, body.to_vec()))
+        }).map_err(ApiError::from);
+        // reqwest's async client doesn't time requests out itself; uploads aren't timed, like `ApiClient`'s
+        let received: ApiFuture<(ResponseMeta, Vec<u8>)> = match config.timeout {
+            Some(timeout) if attempt.payload.can_replay() => self.within(timeout, Box::new(received)),
+            _ => Box::new(received),
+        };
+
+        let client = self.clone();
+        let request_desc = request_desc.to_string();
+        let shown_request = format!("{} {}", attempt.method, redact_url(&attempt.url));
+        let started = Instant::now();
+        Box::new(received.then(move |result| {
+            let elapsed_ms = duration_millis(started.elapsed());
+            let outcome = match result {
+                Ok((ref meta, _)) => {
+                    debug!("{} -> {} ({} ms)", shown_request, meta.status, elapsed_ms);
+                    json!({ "request": request_desc, "status": meta.status.as_u16(), "elapsedMs": elapsed_ms })
+                },
+                Err(ref e) => {
+                    debug!("{} -> {} ({} ms)", shown_request, e, elapsed_ms);
+                    json!({ "request": request_desc, "error": format!("{:?}", e), "elapsedMs": elapsed_ms })
+                },
+            };
+            client.inner.log.event("http", outcome);
+            match result {
+                Ok((meta, body)) => Ok(Received { request: request_desc, meta, body }),
+                Err(e) => Err(e.with_request(&request_desc)),
+            }
+        }))
+    }
+
+    /// A future that's done after `duration`, on the event loop's timer.
+    fn wait(&self, duration: Duration) -> ApiFuture<()> {
+        match Timeout::new(duration, &self.inner.handle) {
+            Ok(timeout) => Box::new(timeout.map_err(ApiError::from)),
+            Err(e) => Box::new(future::err(ApiError::from(e))),
+        }
+    }
+
+    /// `work`, unless it isn't done within `limit`, in which case it's dropped and the result is a timeout error.
+    fn within<T: 'static>(&self, limit: Duration, work: ApiFuture<T>) -> ApiFuture<T> {
+        let timed_out = self.wait(limit).and_then(move |_| -> ApiResult<T> {
+            Err(ApiError::from(io::Error::new(io::ErrorKind::TimedOut, format!("no response within {}ms (see --timeout)", duration_millis(limit)))))
+        });
+        Box::new(work.select(timed_out).map(|(item, _)| item).map_err(|(e, _)| e))
+    }
+}
+
+/// Like `ApiClient`'s HTTP client, but async: TLS, the proxy and redirects are set up the same way.
+fn build_async_client(config: &ClientConfig, proxy_decision: &proxy::ProxyDecision, handle: &Handle) -> ApiResult<http::Client> {
+    let setup_error = |problem: String| move |e: reqwest::Error| ApiError::ClientSetup(problem, Some(e));
+    let mut client_builder = http::Client::builder();
+    if config.allows_insecure() {
+        client_builder.danger_disable_hostname_verification();
+    }
+    for cert in config.ca_certs.iter() {
+        let cert = cert.to_reqwest().map_err(setup_error(format!("invalid CA certificate from {}", cert.source.display())))?;
+        client_builder.add_root_certificate(cert);
+    }
+    if let Some(ref identity) = config.client_identity {
+        let identity = identity.to_reqwest().map_err(setup_error(format!("invalid client certificate from {}", identity.source.display())))?;
+        client_builder.identity(identity);
+    }
+    if let Some(proxy_url) = proxy_decision.proxy_url() {
+        if proxy_url.scheme() != "http" && proxy_url.scheme() != "https" {
+            return Err(ApiError::ClientSetup(format!("unsupported proxy url {} (only http and https proxies are supported)", proxy::without_credentials(proxy_url)), None));
+        }
+        let proxy_url = proxy::without_credentials(proxy_url);
+        let proxy = reqwest::Proxy::all(proxy_url.clone()).map_err(setup_error(format!("invalid proxy url {}", proxy_url)))?;
+        client_builder.proxy(proxy);
+    }
+    // redirects are followed by `AsyncApiClient::send`, which keeps the credentials
+    client_builder.redirect(reqwest::RedirectPolicy::none());
+    client_builder.build(handle).map_err(setup_error("couldn't initialize TLS".to_string()))
+}
+
+#[test]
+fn test_async_client(){
+    use futures::future::join_all;
+    use test_server::*;
+    use tokio_core::reactor::Core;
+
+    let server = TestServer::start(|req| match req.path.as_str() {
+        "/codedx/api/jobs/j1" => TestResponse::json(200, json!({ "jobId": "j1", "status": "completed" })),
+        "/codedx/api/projects/query" => TestResponse::json(200, json!([{ "id": 1, "name": "webgoat", "parentId": null }])),
+        "/codedx/x/system-info" => TestResponse::json(200, json!({ "version": "2023.4.2" })),
+        _ => TestResponse::status(404),
+    });
+    let mut core = Core::new().unwrap();
+    let client = AsyncApiClient::new(server.config(), &core.handle()).unwrap();
+
+    let projects = core.run(join_all((0..6).map(|_| client.get_projects()))).unwrap();
+    assert_eq!(projects.len(), 6);
+    assert!(projects.iter().all(|projects| projects.len() == 1 && projects[0].name == "webgoat"));
+
+    let poll = client.poll_job_completion("j1".to_string(), Duration::from_millis(1));
+    assert_eq!(core.run(poll).unwrap(), JobStatus::Completed);
+    match core.run(client.get_job_status("gone".to_string())) {
+        Err(ApiError::JobNotFound(ref job_id)) => assert_eq!(job_id, "gone"),
+        other => panic!("expected JobNotFound, got {:?}", other),
+    }
+
+    // the fallback to `x/` is remembered, so the second call only makes one request
+    let before = server.requests().len();
+    assert_eq!(core.run(client.get_system_info()).unwrap().version, "2023.4.2");
+    assert_eq!(core.run(client.get_system_info()).unwrap().version, "2023.4.2");
+    let paths: Vec<String> = server.requests()[before..].iter().map(|req| req.path.clone()).collect();
+    assert_eq!(paths, vec!["/codedx/api/system-info", "/codedx/x/system-info", "/codedx/x/system-info"]);
+}
+
+#[test]
+fn test_async_poll_waits_on_the_timer(){
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use test_server::*;
+    use tokio_core::reactor::Core;
+
+    let checks = Arc::new(AtomicUsize::new(0));
+    let server_checks = checks.clone();
+    let server = TestServer::start(move |_req| {
+        let status = if server_checks.fetch_add(1, Ordering::SeqCst) < 2 { "running" } else { "completed" };
+        TestResponse::json(200, json!({ "jobId": "j1", "status": status }))
+    });
+    let mut core = Core::new().unwrap();
+    let client = AsyncApiClient::new(server.config(), &core.handle()).unwrap();
+
+    let started = Instant::now();
+    let poll = client.poll_job_completion("j1".to_string(), Duration::from_millis(50));
+    assert_eq!(core.run(poll).unwrap(), JobStatus::Completed);
+    assert_eq!(checks.load(Ordering::SeqCst), 3);
+    assert!(started.elapsed() >= Duration::from_millis(100));
+
+    // a strategy that gives up ends the poll with the last status
+    checks.store(0, Ordering::SeqCst);
+    let poll = client.poll_job_completion("j1".to_string(), |_: usize, _: &JobStatus| -> Option<Duration> { None });
+    assert_eq!(core.run(poll).unwrap(), JobStatus::Running);
+}
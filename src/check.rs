@@ -0,0 +1,174 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Telling apart the ways that reaching a Code Dx server can fail (the `check` command).
+//!
+//! `ApiClient::check_connection` makes one cheap authenticated request; if it fails,
+//! `ConnectionProblem::of` says which step went wrong (finding the host, connecting to it,
+//! TLS, the credentials, or the base url), so that a pipeline can stop before a long upload
+//! with an exit code and a message that point at the fix.
+
+use client::*;
+use proxy;
+use reqwest;
+use retry;
+use std::io;
+use StatusCode;
+
+/// The ways a connection check can fail, from the first step to the last.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConnectionProblem {
+    /// The base url's host name doesn't resolve.
+    Dns,
+    /// Nothing accepted the connection, or it timed out.
+    Connect,
+    /// The TLS handshake failed, usually because the server's certificate isn't trusted.
+    Tls,
+    /// The server rejected the credentials (401).
+    Unauthorized,
+    /// The credentials are valid, but aren't allowed to use the API (403).
+    Forbidden,
+    /// The server answered, but not like Code Dx would: a web page, or a 404 for the API.
+    /// This usually means the base url is missing Code Dx's context path (e.g. `/codedx`).
+    NotCodeDx,
+    /// Anything else, e.g. a 500.
+    Other,
+}
+
+impl ConnectionProblem {
+    /// Work out which step a failed check went wrong at.
+    pub fn of(error: &ApiError) -> ConnectionProblem {
+        match *error {
+            ApiError::Protocol { cause: ref e, .. } => classify_protocol_error(e),
+            ApiError::IO(ref e) => classify_io_error(e),
+            ApiError::NonSuccess { status: StatusCode::Unauthorized, .. } => ConnectionProblem::Unauthorized,
+            ApiError::NonSuccess { status: StatusCode::Forbidden, .. } => ConnectionProblem::Forbidden,
+            ApiError::NonSuccess { status: StatusCode::NotFound, .. } => ConnectionProblem::NotCodeDx,
+            ApiError::NotCodeDx { .. } | ApiError::Json { .. } | ApiError::UnexpectedBody { .. } => ConnectionProblem::NotCodeDx,
+            _ => ConnectionProblem::Other,
+        }
+    }
+
+    /// The `check` command's exit code for this problem, so that CI scripts can branch on it.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ConnectionProblem::Other => 1,
+            ConnectionProblem::Dns => 10,
+            ConnectionProblem::Connect => 11,
+            ConnectionProblem::Tls => 12,
+            ConnectionProblem::Unauthorized => 13,
+            ConnectionProblem::Forbidden => 14,
+            ConnectionProblem::NotCodeDx => 15,
+        }
+    }
+
+    /// A short description of the problem, and what usually fixes it.
+    pub fn diagnosis(&self) -> &'static str {
+        match *self {
+            ConnectionProblem::Dns => "the server's host name couldn't be resolved; check the base url for typos",
+            ConnectionProblem::Connect => "couldn't connect to the server; check the base url's port, and whether a proxy is needed (see --proxy)",
+            ConnectionProblem::Tls => "the TLS handshake failed; if the server's certificate is self-signed or from an internal CA, trust it with --cacert",
+            ConnectionProblem::Unauthorized => "the server rejected the credentials; check the username and password, or the API key",
+            ConnectionProblem::Forbidden => "the credentials were accepted, but aren't allowed to use the API; ask a Code Dx admin for access",
+            ConnectionProblem::NotCodeDx => "the server didn't answer like Code Dx; the base url is probably missing the context path, e.g. https://example.com/codedx",
+            ConnectionProblem::Other => "the server couldn't handle the request",
+        }
+    }
+}
+
+fn classify_protocol_error(error: &reqwest::Error) -> ConnectionProblem {
+    if retry::is_timeout(error) || proxy::is_tunnel_refused(error) {
+        return ConnectionProblem::Connect;
+    }
+    if let Some(kind) = retry::io_error_kind(error) {
+        if kind == io::ErrorKind::ConnectionRefused {
+            return ConnectionProblem::Connect;
+        }
+    }
+    classify_message(&error.to_string())
+}
+
+fn classify_io_error(error: &io::Error) -> ConnectionProblem {
+    match error.kind() {
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::TimedOut => ConnectionProblem::Connect,
+        _ => classify_message(&error.to_string()),
+    }
+}
+
+/// The resolver and TLS errors aren't exposed by type, so these go by their wording.
+fn classify_message(message: &str) -> ConnectionProblem {
+    let message = message.to_lowercase();
+    let is_dns = ["failed to lookup address", "name or service not known", "no such host", "nodename nor servname"]
+        .iter().any(|phrase| message.contains(phrase));
+    let is_tls = ["certificate", "ssl", "tls", "handshake"]
+        .iter().any(|phrase| message.contains(phrase));
+    if is_dns {
+        ConnectionProblem::Dns
+    } else if is_tls {
+        ConnectionProblem::Tls
+    } else if message.contains("connection refused") {
+        ConnectionProblem::Connect
+    } else {
+        ConnectionProblem::Other
+    }
+}
+
+/// Whether a response body is a web page rather than JSON.
+pub fn looks_like_html(body: &[u8]) -> bool {
+    body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'<')
+}
+
+#[test]
+fn test_classify_messages(){
+    assert_eq!(classify_message("failed to lookup address information: Name or service not known"), ConnectionProblem::Dns);
+    assert_eq!(classify_message("error:14090086:SSL routines:ssl3_get_server_certificate:certificate verify failed"), ConnectionProblem::Tls);
+    assert_eq!(classify_message("Connection refused (os error 111)"), ConnectionProblem::Connect);
+    assert_eq!(classify_message("something else"), ConnectionProblem::Other);
+}
+
+#[test]
+fn test_classify_errors(){
+    let refused = io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+    assert_eq!(ConnectionProblem::of(&ApiError::IO(refused)), ConnectionProblem::Connect);
+    let unauthorized = ApiError::NonSuccess { status: StatusCode::Unauthorized, message: ApiErrorMessage::Raw(String::new()), request: None };
+    assert_eq!(ConnectionProblem::of(&unauthorized), ConnectionProblem::Unauthorized);
+    let forbidden = ApiError::NonSuccess { status: StatusCode::Forbidden, message: ApiErrorMessage::Raw(String::new()), request: None };
+    assert_eq!(ConnectionProblem::of(&forbidden), ConnectionProblem::Forbidden);
+    let html = ApiError::NotCodeDx { request: "POST /api/projects/query".to_string() };
+    assert_eq!(ConnectionProblem::of(&html), ConnectionProblem::NotCodeDx);
+    let server_error = ApiError::NonSuccess { status: StatusCode::InternalServerError, message: ApiErrorMessage::Raw(String::new()), request: None };
+    assert_eq!(ConnectionProblem::of(&server_error).exit_code(), 1);
+}
+
+#[test]
+fn test_exit_codes_are_distinct(){
+    let problems = [
+        ConnectionProblem::Dns, ConnectionProblem::Connect, ConnectionProblem::Tls, ConnectionProblem::Unauthorized,
+        ConnectionProblem::Forbidden, ConnectionProblem::NotCodeDx, ConnectionProblem::Other,
+    ];
+    let mut codes: Vec<i32> = problems.iter().map(|p| p.exit_code()).collect();
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), problems.len());
+}
+
+#[test]
+fn test_looks_like_html(){
+    assert!(looks_like_html(b"\r\n<!DOCTYPE html><html>"));
+    assert!(looks_like_html(b"<HTML><body>Login</body>"));
+    assert!(!looks_like_html(b"[{\"id\": 1}]"));
+    assert!(!looks_like_html(b""));
+}
@@ -15,10 +15,33 @@
  */
 
 use clap::{ArgMatches, App, Arg, SubCommand};
+use codedx_client::check::ConnectionProblem;
 use client::*;
+use codedx_client::config::{ClientAuth, Verbosity};
+use codedx_client::gate::SeverityGate;
+use codedx_client::glob::{self, MatchedFile};
+use codedx_client::hierarchy;
+use codedx_client::interrupt::{self, Interruptible};
+use codedx_client::keyring::{KeyringEntry, KeyringError};
+use codedx_client::output::*;
+use codedx_client::polling::{self, BoundedPoll, CommandProgress, ExponentialBackoff, Observed, PollInterval, PollOutput, ShowProgress};
+use codedx_client::prep::{self, PrepReport};
+use codedx_client::retry;
+use codedx_client::standards::{self, Standard};
+use codedx_client::summary;
+use codedx_client::targets::{self, AnalysisTarget};
+use codedx_client::template::{NameTemplate, NameValues};
+use codedx_client::trend;
+use codedx_client::upload::{self, format_size, ProgressPrinter, UploadFile, UploadInput, UploadProgress};
+use codedx_client::zip::{self, TempZip, ZipOptions};
 use serde_json;
-use std::collections::HashMap;
-use std::path::Path;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::Duration;
 
 /// A vector containing all of the runnable commands in this module.
@@ -27,6 +50,29 @@ pub fn all<'a>() -> Vec<Box<Command<'a>>> {
         Box::new(ExitCommand),
         Box::new(AnalyzeCommand),
         Box::new(ProjectsCommand),
+        Box::new(CreateProjectCommand),
+        Box::new(UpdateProjectCommand),
+        Box::new(ProjectMetadataCommand),
+        Box::new(SetMetadataCommand),
+        Box::new(DeleteProjectCommand),
+        Box::new(ListAnalysesCommand),
+        Box::new(ListBranchesCommand),
+        Box::new(FindingsCommand),
+        Box::new(FindingCountsCommand),
+        Box::new(FindingCommand),
+        Box::new(TriageCommand),
+        Box::new(ListStatusesCommand),
+        Box::new(SummaryCommand),
+        Box::new(StandardsCommand),
+        Box::new(TrendCommand),
+        Box::new(ReportCommand),
+        Box::new(ServerInfoCommand),
+        Box::new(CheckCommand),
+        Box::new(LoginCommand),
+        Box::new(LogoutCommand),
+        Box::new(WaitCommand),
+        Box::new(JobStatusCommand),
+        Box::new(CancelCommand),
     ]
 }
 
@@ -106,6 +152,32 @@ impl <'a, T, A> Command<'a> for T where T: CommandInner<'a, Args = A> {
     }
 }
 
+/// Parse the value of an optional argument, e.g. a numeric id.
+///
+/// Returns `Ok(None)` if the argument wasn't given, and the `error` message if it couldn't be parsed.
+fn parse_optional<'a, T: FromStr>(matches: &'a ArgMatches, name: &str, error: &'a str) -> Result<Option<T>, &'a str> {
+    match matches.value_of(name) {
+        Some(raw) => raw.parse().map(Some).map_err(|_| error),
+        None => Ok(None),
+    }
+}
+
+/// Find the project a `--project` argument refers to, by id or by (case-insensitive) exact name,
+/// reporting the error if there's no such project.
+fn resolve_project(client: &ApiClient, project: &str) -> Result<ApiProject, Exit> {
+    client.resolve_project(project).map_err(|e| {
+        client.report_error("finding the project", &e);
+        Exit(1)
+    })
+}
+
+/// Split a `--meta` value like `Team=Payments` into its field and value (which may have more `=`s in it).
+fn parse_meta(raw: &str) -> Option<(&str, &str)> {
+    let equals = raw.find('=')?;
+    let field = raw[..equals].trim();
+    if field.is_empty() { None } else { Some((field, &raw[equals + 1..])) }
+}
+
 // -------------------------------------------------------------------------------------------------
 // ABOVE THIS POINT: command traits and supporting structs
 // -
@@ -147,25 +219,460 @@ impl <'a> CommandInner<'a> for ExitCommand {
 // COMMAND: analyze
 // -------------------------------------------------------------------------------------------------
 pub struct AnalyzeCommand;
-pub struct AnalyzeCommandArgs<'a> {
-    project_id: u32,
-    files: Vec<&'a Path>,
-    name: Option<&'a str>
+pub enum AnalyzeCommandArgs<'a> {
+    /// Analyze some files in one project.
+    Single {
+        /// The project's id or name.
+        project: &'a str,
+        /// Paths, or patterns for them (see `codedx_client::glob`).
+        files: Vec<&'a str>,
+        /// Names to upload some of the files as, instead of their own.
+        upload_names: HashMap<&'a str, &'a str>,
+        allow_empty_glob: bool,
+        /// The name to upload what's read from STDIN as, if anything is.
+        stdin_name: Option<&'a str>,
+        /// Directories to zip up and upload, and the names to upload them as.
+        zip_dirs: Vec<(PathBuf, String)>,
+        zip_options: ZipOptions,
+        /// Refuse to upload files that add up to more than this many bytes.
+        max_upload_size: Option<u64>,
+        /// How many times to upload everything again if the transfer fails.
+        upload_retries: u32,
+        /// The analysis's name, with placeholders to fill in (see `codedx_client::template`).
+        name: Option<NameTemplate>,
+        show_trend: bool,
+        verify_only: bool,
+        /// Upload the files to an analysis prep, and only analyze them if the server accepts them all.
+        use_prep: bool,
+        /// Tools to keep from analyzing the files in the prep.
+        disabled_tools: Vec<&'a str>,
+        strict_inputs: bool,
+        branch: Option<BranchSpec>,
+        gate: Option<SeverityGate>,
+        show_progress: bool,
+        /// Cancel the analysis's job if Ctrl-C is pressed while waiting for it, without asking first.
+        cancel_on_interrupt: bool,
+        poll: BoundedPoll<Option<PollInterval>>,
+        poll_output: PollOutput,
+    },
+    /// Analyze several targets (see `codedx_client::targets`).
+    Multi {
+        targets: Vec<AnalysisTarget>,
+        targets_file: Option<&'a Path>,
+        /// Projects to analyze the same `files` in, besides the targets: given by id or name, and found by a filter.
+        projects: Vec<&'a str>,
+        project_filter: Option<ProjectFilter>,
+        files: Vec<&'a str>,
+        allow_empty_glob: bool,
+        /// The `--fail-on` gate for the targets that don't have their own.
+        gate: Option<SeverityGate>,
+        concurrency: usize,
+        format: &'a str,
+        poll: BoundedPoll<Option<PollInterval>>,
+    },
 }
+/// The default for `analyze --max-upload-size`.
+const DEFAULT_MAX_UPLOAD_SIZE: &str = "5G";
+
 impl <'a> AnalyzeCommand {
     // ANALYZE - helper for argument extraction
     fn inner_parse(&self, analyze_args: &'a ArgMatches) -> Result<AnalyzeCommandArgs<'a>, &'a str> {
-        let project_id: u32 = analyze_args.value_of("project-id")
-            .ok_or("project id missing")?
-            .parse().map_err(|_| "project-id should be a number")?;
-        // get the list of files
-        let files = analyze_args.values_of("file")
-            .ok_or("must specify at least one file to analyze")?
-            .map(|file| Path::new(file))
-            .collect();
+        if ["target", "targets-file", "projects", "project-filter", "project-metadata", "project-meta"].iter().any(|&arg| analyze_args.is_present(arg)) {
+            return self.inner_parse_multi(analyze_args);
+        }
+        let project = analyze_args.value_of("project-id").ok_or("project id missing")?;
+        // names to upload some of the files as, instead of their own
+        let mut upload_names = HashMap::new();
+        for mut upload_name_values in analyze_args.values_of("upload-name") {
+            while let Some(file) = upload_name_values.next() {
+                let name = upload_name_values.next().ok_or("upload-name must be given as file name pairs")?;
+                upload_names.insert(file, name);
+            }
+        }
+        // get the list of files (or patterns, to be expanded before uploading), and whether to read one from STDIN
+        let mut files: Vec<&'a str> = analyze_args.values_of("file").map(|values| values.collect()).unwrap_or_default();
+        let stdin_name = analyze_args.value_of("stdin-file");
+        match files.iter().filter(|&&file| file == "-").count() {
+            0 => (),
+            1 if stdin_name.is_some() => files.retain(|&file| file != "-"),
+            1 => return Err("reading a file from STDIN (-) requires --stdin-file NAME, the name to upload it as"),
+            _ => return Err("STDIN (-) can only be given once"),
+        }
+        let zip_dirs: Vec<(PathBuf, String)> = analyze_args.values_of("zip-dir")
+            .map(|values| values.map(zip::parse_dir_spec).collect())
+            .unwrap_or_default();
+        let zip_options = ZipOptions {
+            excludes: analyze_args.values_of("zip-exclude").map(|values| values.map(String::from).collect()).unwrap_or_default(),
+            use_gitignore: analyze_args.is_present("zip-use-gitignore"),
+            follow_symlinks: analyze_args.is_present("zip-follow-symlinks"),
+        };
+        if files.is_empty() && stdin_name.is_none() && zip_dirs.is_empty() {
+            return Err("must specify at least one file to analyze");
+        }
+        let max_upload_size = if analyze_args.is_present("ignore-size-limit") {
+            None
+        } else {
+            let raw = analyze_args.value_of("max-upload-size").unwrap_or(DEFAULT_MAX_UPLOAD_SIZE);
+            Some(upload::parse_size(raw).ok_or("max-upload-size should be a size like 5G or 500M")?)
+        };
+        let upload_retries: u32 = parse_optional(analyze_args, "upload-retries", "upload-retries should be a number")?.unwrap_or(0);
+        if upload_retries > 0 && stdin_name.is_some() {
+            return Err("upload-retries can't be used with STDIN, which can only be read once");
+        }
+        if upload_names.keys().any(|file| !files.contains(file)) {
+            return Err("upload-name must refer to one of the files being analyzed");
+        }
+        let allow_empty_glob = analyze_args.is_present("allow-empty-glob");
         // optional name for the analysis
-        let name = analyze_args.value_of("name");
-        Ok(AnalyzeCommandArgs { project_id, files, name })
+        let name = match analyze_args.value_of("name") {
+            // the `validator` has already reported what's wrong with a name that doesn't parse
+            Some(raw) => Some(raw.parse::<NameTemplate>().map_err(|_| "name isn't a valid template")?),
+            None => None,
+        };
+        let show_trend = analyze_args.is_present("trend");
+        let verify_only = analyze_args.is_present("verify-only");
+        let strict_inputs = analyze_args.is_present("strict-inputs");
+        let use_prep = analyze_args.is_present("use-prep");
+        let disabled_tools: Vec<&'a str> = analyze_args.values_of("disable-tool").map(|values| values.collect()).unwrap_or_default();
+        if !disabled_tools.is_empty() && !(use_prep || verify_only || strict_inputs) {
+            return Err("disable-tool needs --use-prep (or --verify-only, or --strict-inputs)");
+        }
+        let branch = match (analyze_args.value_of("branch"), analyze_args.value_of("new-branch"), analyze_args.value_of("from")) {
+            (Some(name), None, None) => Some(BranchSpec::Existing(name.to_string())),
+            (None, Some(name), Some(parent)) => Some(BranchSpec::New { name: name.to_string(), parent: parent.to_string() }),
+            (None, None, None) => None,
+            (None, Some(_), None) => return Err("new-branch needs --from, the branch to base it on"),
+            _ => return Err("give either --branch, or --new-branch with --from"),
+        };
+        let gate = parse_gate(analyze_args)?;
+        let show_progress = !analyze_args.is_present("no-progress");
+        let cancel_on_interrupt = analyze_args.is_present("cancel-on-interrupt");
+        let poll = parse_poll(analyze_args)?;
+        let poll_output = parse_optional(analyze_args, "poll-output", "poll-output should be human or jsonl")?.unwrap_or_default();
+        Ok(AnalyzeCommandArgs::Single { project, files, upload_names, allow_empty_glob, stdin_name, zip_dirs, zip_options, max_upload_size, upload_retries, name, show_trend, verify_only, use_prep, disabled_tools, strict_inputs, branch, gate, show_progress, cancel_on_interrupt, poll, poll_output })
+    }
+
+    // ANALYZE - argument extraction for --target and --targets-file
+    fn inner_parse_multi(&self, analyze_args: &'a ArgMatches) -> Result<AnalyzeCommandArgs<'a>, &'a str> {
+        let mut targets = Vec::new();
+        for raw in analyze_args.values_of("target").into_iter().flat_map(|values| values) {
+            targets.push(AnalysisTarget::parse(raw).map_err(|_| "each target should look like project=NAME,file=PATH[,file=PATH...]")?);
+        }
+        let targets_file = analyze_args.value_of("targets-file").map(|file| Path::new(file));
+        let mut projects = Vec::new();
+        for raw in analyze_args.values_of("projects").into_iter().flat_map(|values| values) {
+            if !projects.contains(&raw) {
+                projects.push(raw);
+            }
+        }
+        let mut project_filter = ProjectFilter::new();
+        for mut metadata_values in analyze_args.values_of("project-metadata") {
+            while let Some(k) = metadata_values.next() {
+                let v = metadata_values.next().ok_or("project-metadata must be given as key value pairs")?;
+                project_filter = project_filter.metadata(k, v);
+            }
+        }
+        for raw in analyze_args.values_of("project-meta").into_iter().flat_map(|values| values) {
+            let (field, pattern) = parse_meta(raw).ok_or("project-meta must be given as FIELD=VALUE")?;
+            project_filter = project_filter.metadata(field, MetadataMatch::from_pattern(pattern));
+        }
+        if let Some(name) = analyze_args.value_of("project-filter") {
+            project_filter = project_filter.name(name);
+        }
+        let project_filter = if project_filter.is_empty() { None } else { Some(project_filter) };
+        // with --project-id given as a flag, the first positional argument is a file, not the project
+        let files: Vec<&'a str> = analyze_args.value_of("project-id").into_iter()
+            .chain(analyze_args.values_of("file").into_iter().flat_map(|values| values))
+            .collect();
+        let by_project = !projects.is_empty() || project_filter.is_some();
+        if by_project && files.is_empty() {
+            return Err("must specify at least one file to analyze in each project");
+        }
+        let allow_empty_glob = analyze_args.is_present("allow-empty-glob");
+        let concurrency: usize = parse_optional(analyze_args, "concurrency", "concurrency should be a number")?.unwrap_or(2);
+        if concurrency == 0 {
+            return Err("concurrency should be at least 1");
+        }
+        let gate = parse_gate(analyze_args)?;
+        let format = analyze_args.value_of("output").unwrap_or("table");
+        let poll = parse_poll(analyze_args)?;
+        Ok(AnalyzeCommandArgs::Multi { targets, targets_file, projects, project_filter, files, allow_empty_glob, gate, concurrency, format, poll })
+    }
+
+    // ANALYZE - print what the server thinks of the files uploaded to an analysis prep
+    //
+    // With `full_report`, every input is described on STDOUT; otherwise only the warnings and errors are shown, as warnings.
+    // If the inputs don't pass (see `PrepReport::passes`), the prep is deleted and this returns an `Exit`.
+    fn verify_inputs(&self, client: &ApiClient, report: ApiResult<PrepReport>, strict: bool, disabled_tools: &[&str], full_report: bool) -> Result<PrepReport, Exit> {
+        let mut report = match report {
+            Ok(report) => report,
+            Err(e) => {
+                client.report_error("verifying inputs", &e);
+                return Err(Exit(1));
+            },
+        };
+        if !disabled_tools.is_empty() {
+            if let Err(e) = prep::disable_tools(client, &mut report, disabled_tools) {
+                client.report_error("disabling tools", &e);
+                discard_prep(client, &report.prep_id);
+                return Err(Exit(1));
+            }
+        }
+        if full_report {
+            print!("{}", report.render());
+        } else {
+            for input in report.inputs.iter() {
+                for warning in input.warnings() {
+                    client.warn(&format!("{}: {}", input.file, warning));
+                }
+                for error in input.errors() {
+                    client.warn(&format!("{}: error: {}", input.file, error));
+                }
+            }
+            for error in report.prep_errors.iter() {
+                client.warn(&format!("error: {}", error));
+            }
+        }
+        if report.passes(strict) {
+            Ok(report)
+        } else {
+            discard_prep(client, &report.prep_id);
+            if report.error_count() > 0 {
+                client.report_failure("invalid_inputs", "the server rejected some of the inputs");
+            } else {
+                client.report_failure("invalid_inputs", "the server had warnings about some of the inputs, and --strict-inputs was given");
+            }
+            Err(Exit(1))
+        }
+    }
+
+    // ANALYZE - zip up each --zip-dir, into temp files that are deleted when they're dropped (whether or not the upload works)
+    fn zip_dirs(&self, client: &ApiClient, dirs: &[(PathBuf, String)], options: &ZipOptions) -> Result<Vec<(TempZip, String)>, Exit> {
+        let mut zips = Vec::new();
+        for &(ref dir, ref name) in dirs {
+            client.progress(&format!("Zipping {} as {}", dir.display(), name));
+            match TempZip::create(dir, options) {
+                Ok(zip) => zips.push((zip, name.clone())),
+                Err(e) => {
+                    client.report_other_error("io", &format!("zipping {}", dir.display()), &e);
+                    return Err(Exit(1));
+                },
+            }
+        }
+        Ok(zips)
+    }
+
+    // ANALYZE - expand any patterns among the files, and list what's going to be uploaded
+    fn expand_files<'f>(&self, client: &ApiClient, files: &[&'f str], allow_empty: bool, zips: &[(TempZip, String)], stdin_name: Option<&str>) -> Result<Vec<MatchedFile<'f>>, Exit> {
+        let matched = match glob::expand_inputs(files, allow_empty) {
+            Ok(matched) => matched,
+            Err(e) => {
+                client.report_failure("failed", &e);
+                return Err(Exit(1));
+            },
+        };
+        if matched.is_empty() && zips.is_empty() && stdin_name.is_none() {
+            client.report_failure("failed", "no files to analyze");
+            return Err(Exit(1));
+        }
+        client.progress("Files to upload:");
+        for file in &matched {
+            let size = match fs::metadata(&file.path) {
+                Ok(metadata) => format_size(metadata.len()),
+                Err(_) => "not found".to_string(),
+            };
+            client.progress(&format!("  {} ({})", file.path.display(), size));
+        }
+        for &(ref zip, ref name) in zips {
+            client.progress(&format!("  {} ({} files, {})", name, zip.summary.files, format_size(zip.summary.bytes)));
+        }
+        if let Some(name) = stdin_name {
+            client.progress(&format!("  STDIN, as {}", name));
+        }
+        Ok(matched)
+    }
+
+    // ANALYZE - check every file before uploading any, and report all of their problems at once
+    fn check_files(&self, client: &ApiClient, files: &[UploadFile], max_upload_size: Option<u64>) -> Result<(), Exit> {
+        let paths: Vec<&Path> = files.iter().map(|file| file.path).collect();
+        let total = match upload::check_files(&paths) {
+            Ok(total) => total,
+            Err(problems) => {
+                let mut message = format!("{} of the files can't be uploaded:", problems.len());
+                for problem in problems {
+                    message.push_str(&format!("\n  {} {}", problem.path.display(), problem.problem));
+                }
+                client.report_failure("invalid_inputs", &message);
+                return Err(Exit(1));
+            },
+        };
+        client.progress(&format!("Total upload size: {}", format_size(total)));
+        match max_upload_size {
+            Some(max) if total > max => {
+                client.report_failure("invalid_inputs", &format!("the files add up to {}, which is more than the {} allowed by --max-upload-size; use --ignore-size-limit to upload them anyway", format_size(total), format_size(max)));
+                Err(Exit(1))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // ANALYZE - compare a completed analysis's finding counts against the --fail-on thresholds
+    fn check_gate(&self, client: &ApiClient, project_id: u32, analysis_id: u32, gate: &SeverityGate) -> CommandResult {
+        match gate.check(client, project_id, analysis_id) {
+            Err(e) => {
+                client.report_error("counting the findings for --fail-on", &e);
+                Err(Exit(1))
+            },
+            Ok(report) => match report.exit_code() {
+                0 => {
+                    client.progress("The findings are within the --fail-on thresholds");
+                    Ok(())
+                },
+                code => {
+                    let exceeded = report.render_exceeded();
+                    client.report_failure("gate_failed", &format!("analysis {} has more findings than --fail-on allows:\n{}", analysis_id, exceeded.trim_end_matches('\n')));
+                    client.log().event("gate-failed", json!({
+                        "analysisId": analysis_id,
+                        "exceeded": report.exceeded().iter().map(|row| json!({ "severity": row.severity, "count": row.count, "max": row.max })).collect::<Vec<_>>(),
+                    }));
+                    Err(Exit(code))
+                },
+            },
+        }
+    }
+
+    // ANALYZE - one target per project given with --project-id or found with --project-filter, all with the same files
+    fn project_targets(&self, client: &ApiClient, given: &[&str], filter: Option<&ProjectFilter>, files: &[&str], allow_empty_glob: bool) -> Result<Vec<AnalysisTarget>, Exit> {
+        if given.is_empty() && filter.is_none() {
+            return Ok(Vec::new());
+        }
+        // each target resolves its own project, by id or name
+        let mut projects: Vec<String> = given.iter().map(|project| project.to_string()).collect();
+        if let Some(filter) = filter {
+            let found = match client.query_projects(filter) {
+                Ok(found) => found,
+                Err(e) => {
+                    client.report_error("finding the projects to analyze", &e);
+                    return Err(Exit(1));
+                },
+            };
+            if found.is_empty() {
+                client.report_failure("failed", "no projects match the filter");
+                return Err(Exit(1));
+            }
+            for project in found {
+                client.progress(&format!("Found project {} ({})", project.id, project.name));
+                let id = project.id.to_string();
+                if !projects.contains(&id) {
+                    projects.push(id);
+                }
+            }
+        }
+        let files: Vec<PathBuf> = match glob::expand_inputs(files, allow_empty_glob) {
+            Ok(matched) => matched.into_iter().map(|m| m.path).collect(),
+            Err(e) => {
+                client.report_failure("failed", &e);
+                return Err(Exit(1));
+            },
+        };
+        if files.is_empty() {
+            client.report_failure("failed", "there are no files to analyze");
+            return Err(Exit(1));
+        }
+        Ok(projects.into_iter().map(|project| AnalysisTarget { project, files: files.clone(), fail_on: None }).collect())
+    }
+
+    // ANALYZE - on the first Ctrl-C while waiting: offer to cancel the job (or just do it, with --cancel-on-interrupt)
+    fn on_interrupt(&self, client: &ApiClient, analysis_id: u32, job_id: Option<String>, cancel_on_interrupt: bool) -> CommandResult {
+        client.report_failure("interrupted", &format!("interrupted while waiting for analysis {}", analysis_id));
+        match job_id {
+            None => eprintln!("# Analysis {} has no job to cancel, so it will carry on", analysis_id),
+            Some(ref job_id) if cancel_on_interrupt || confirm(&format!("Cancel job {} on the server? [y/N] ", job_id)) => {
+                match client.cancel_job(job_id) {
+                    Ok(()) => client.progress(&format!("Cancelled job {}", job_id)),
+                    Err(e) => client.warn(&format!("couldn't cancel job {}: {}", job_id, e)),
+                }
+            },
+            Some(ref job_id) => eprintln!("# Leaving job {} running; cancel it later with: cancel --job {}", job_id, job_id),
+        }
+        Err(Exit(interrupt::EXIT_INTERRUPTED))
+    }
+
+    // ANALYZE - execution, for several targets
+    fn run_multi(&self, client: &ApiClient, mut targets: Vec<AnalysisTarget>, targets_file: Option<&Path>, gate: Option<&SeverityGate>, concurrency: usize, format: &str, poll: BoundedPoll<PollInterval>) -> CommandResult {
+        if let Some(path) = targets_file {
+            let mut contents = String::new();
+            let read = File::open(path).and_then(|mut file| io::Read::read_to_string(&mut file, &mut contents));
+            if let Err(e) = read {
+                client.report_other_error("io", &format!("reading {}", path.display()), &e);
+                return Err(Exit(1));
+            }
+            match targets::parse_targets_file(&contents) {
+                Ok(more) => targets.extend(more),
+                Err(e) => {
+                    client.report_other_error("failed", &format!("in {}", path.display()), &e);
+                    return Err(Exit(1));
+                },
+            }
+        }
+        if targets.is_empty() {
+            client.report_failure("failed", "no targets to analyze");
+            return Err(Exit(1));
+        }
+
+        let results = targets::analyze_targets(client, &targets, gate, concurrency, Observed::new(poll, ShowProgress(client)));
+        client.progress("Polling done");
+        match format {
+            "json" => println!("{}", serde_json::to_string(&TargetsOutput::from(&results[..])).unwrap()),
+            _ => print!("{}", targets::render_table(&results)),
+        }
+        if let Some(summary) = targets::failure_summary(&results) {
+            client.report_failure("failed", &summary);
+        }
+        match targets::exit_code(&results) {
+            0 => Ok(()),
+            code => Err(Exit(code)),
+        }
+    }
+}
+/// The `--fail-on` and `--fail-on-new-only` options.
+fn parse_gate<'a>(analyze_args: &'a ArgMatches) -> Result<Option<SeverityGate>, &'a str> {
+    match analyze_args.value_of("fail-on") {
+        Some(raw) => {
+            let mut gate: SeverityGate = raw.parse().map_err(|_| "fail-on should be a list of severity thresholds, e.g. critical:0,high:5")?;
+            gate.new_only = analyze_args.is_present("fail-on-new-only");
+            Ok(Some(gate))
+        },
+        None => Ok(None),
+    }
+}
+/// The `--poll`, `--max-polls` and `--timeout` options; without `--poll`, the interval is left to `ApiClient::poll_interval`.
+fn parse_poll<'a>(analyze_args: &'a ArgMatches) -> Result<BoundedPoll<Option<PollInterval>>, &'a str> {
+    let interval = parse_optional(analyze_args, "poll", "poll should be an interval like 2s, or a backoff like backoff:5s..2m")?;
+    let mut poll = BoundedPoll::new(interval);
+    if let Some(max) = parse_optional(analyze_args, "max-polls", "max-polls should be a number")? {
+        poll = poll.max_iterations(max);
+    }
+    if let Some(raw) = analyze_args.value_of("timeout") {
+        poll = poll.timeout(polling::parse_duration(raw).ok_or("timeout should be a duration like 30m")?);
+    }
+    Ok(poll)
+}
+/// Ask a yes/no question on the terminal; anything but a "y" (or no terminal to ask on) is a no.
+fn confirm(question: &str) -> bool {
+    if !upload::stdin_is_terminal() {
+        return false;
+    }
+    eprint!("{}", question);
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(_) => answer.trim().to_lowercase().starts_with('y'),
+        Err(_) => false,
+    }
+}
+fn discard_prep(client: &ApiClient, prep_id: &str) {
+    if let Err(e) = client.delete_analysis_prep(prep_id) {
+        client.warn(&format!("couldn't delete analysis prep {}: {}", prep_id, e));
     }
 }
 impl <'a> CommandInner<'a> for AnalyzeCommand {
@@ -177,24 +684,258 @@ impl <'a> CommandInner<'a> for AnalyzeCommand {
             .about("Analyze some files")
             .arg(Arg::with_name("project-id")
                 .index(1)
-                .value_name("ID")
-                .required(true)
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .required_unless_one(&["target", "targets-file", "projects", "project-filter", "project-metadata", "project-meta"])
                 .takes_value(true)
             )
             .arg(Arg::with_name("name")
                 .short("n")
                 .long("name")
                 .value_name("NAME")
+                .help("A name for the analysis, which may use the placeholders {date}, {time}, {project}, {project-id}, {analysis-id} and {env:VAR}")
                 .takes_value(true)
                 .required(false)
+                // clap's error shows the template's own message, e.g. which placeholder is unknown
+                .validator(|raw| raw.parse::<NameTemplate>().map(|_| ()))
+            )
+            .arg(Arg::with_name("upload-name")
+                .long("upload-name")
+                .number_of_values(2)
+                .value_names(&["FILE", "NAME"])
+                .help("Send FILE to Code Dx as NAME instead of its own file name")
+                .multiple(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("trend")
+                .long("trend")
+                .takes_value(false)
+                .help("After the analysis completes, print how its severity totals changed since the previous analysis")
+            )
+            .arg(Arg::with_name("verify-only")
+                .long("verify-only")
+                .takes_value(false)
+                .help("Upload the files to an analysis prep and report what Code Dx makes of them, without running an analysis")
+                .conflicts_with_all(&["name", "trend"])
+            )
+            .arg(Arg::with_name("use-prep")
+                .long("use-prep")
+                .takes_value(false)
+                .help("Upload the files to an analysis prep first, print what Code Dx makes of each one, and only analyze them if it has no errors about any")
+            )
+            .arg(Arg::with_name("disable-tool")
+                .long("disable-tool")
+                .value_name("TOOL")
+                .help("With --use-prep, --verify-only or --strict-inputs, keep TOOL (matched ignoring case) from analyzing any of the files; may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+            )
+            .arg(Arg::with_name("strict-inputs")
+                .long("strict-inputs")
+                .takes_value(false)
+                .help("Check the files with an analysis prep first, and do not analyze them if Code Dx has any warnings about them")
+            )
+            .arg(Arg::with_name("branch")
+                .long("branch")
+                .value_name("BRANCH")
+                .help("Analyze the files on this branch of the project (by name or id), instead of its default branch")
+                .takes_value(true)
+                .conflicts_with_all(&["new-branch", "verify-only", "use-prep", "strict-inputs"])
+            )
+            .arg(Arg::with_name("new-branch")
+                .long("new-branch")
+                .value_name("NAME")
+                .help("Create a branch with this name for the analysis (requires --from)")
+                .takes_value(true)
+                .requires("from")
+                .conflicts_with_all(&["verify-only", "use-prep", "strict-inputs"])
+            )
+            .arg(Arg::with_name("from")
+                .long("from")
+                .value_name("PARENT")
+                .help("With --new-branch, the existing branch (by name or id) to base the new one on")
+                .takes_value(true)
+                .requires("new-branch")
+            )
+            .arg(Arg::with_name("fail-on")
+                .long("fail-on")
+                .value_name("THRESHOLDS")
+                .help("After the analysis completes, exit with code 4 if it has more findings of a severity than allowed, e.g. critical:0,high:5 (with several targets, for each one without thresholds of its own)")
+                .takes_value(true)
+                .conflicts_with("verify-only")
+            )
+            .arg(Arg::with_name("fail-on-new-only")
+                .long("fail-on-new-only")
+                .takes_value(false)
+                .requires("fail-on")
+                .help("With --fail-on, only count findings whose status is still new")
+            )
+            .arg(Arg::with_name("stdin-file")
+                .long("stdin-file")
+                .value_name("NAME")
+                .help("Also upload what's piped into STDIN, as a file named NAME (a - among the files is allowed, and means the same)")
+                .takes_value(true)
+                .conflicts_with_all(&["verify-only", "use-prep", "strict-inputs"])
+            )
+            .arg(Arg::with_name("zip-dir")
+                .long("zip-dir")
+                .value_name("DIR[:NAME.zip]")
+                .help("Zip up DIR and upload the zip too, as NAME.zip (or named after DIR); may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+            )
+            .arg(Arg::with_name("zip-exclude")
+                .long("zip-exclude")
+                .value_name("PATTERN")
+                .help("Leave files matching PATTERN (e.g. 'node_modules/**' or '*.log') out of the --zip-dir zips; may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("zip-dir")
+            )
+            .arg(Arg::with_name("zip-use-gitignore")
+                .long("zip-use-gitignore")
+                .takes_value(false)
+                .requires("zip-dir")
+                .help("Leave files ignored by the directories' .gitignore files out of the --zip-dir zips")
+            )
+            .arg(Arg::with_name("zip-follow-symlinks")
+                .long("zip-follow-symlinks")
+                .takes_value(false)
+                .requires("zip-dir")
+                .help("Put what symbolic links point to into the --zip-dir zips, instead of skipping the links")
+            )
+            .arg(Arg::with_name("max-upload-size")
+                .long("max-upload-size")
+                .value_name("SIZE")
+                .help("Refuse to upload files that add up to more than SIZE, e.g. 500M (the default is 5G)")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("ignore-size-limit")
+                .long("ignore-size-limit")
+                .takes_value(false)
+                .conflicts_with("max-upload-size")
+                .help("Upload the files no matter how big they are")
+            )
+            .arg(Arg::with_name("upload-retries")
+                .long("upload-retries")
+                .value_name("N")
+                .help("If the upload fails in transfer (e.g. the connection is reset, or a gateway answers 502 or 504), upload everything again, up to N times (the default is 0), unless a new analysis showed up in the project anyway")
+                .takes_value(true)
+                .conflicts_with_all(&["verify-only", "use-prep", "strict-inputs"])
+            )
+            .arg(Arg::with_name("allow-empty-glob")
+                .long("allow-empty-glob")
+                .takes_value(false)
+                .help("Don't fail when a pattern among the files (e.g. target/*.jar) matches nothing")
+            )
+            .arg(Arg::with_name("no-progress")
+                .long("no-progress")
+                .takes_value(false)
+                .help("Don't show how much of the files has been uploaded")
+            )
+            .arg(Arg::with_name("cancel-on-interrupt")
+                .long("cancel-on-interrupt")
+                .takes_value(false)
+                .help("If Ctrl-C is pressed while waiting for the analysis, cancel its job without asking (e.g. in CI)")
+            )
+            .arg(Arg::with_name("poll")
+                .long("poll")
+                .value_name("INTERVAL")
+                .help("How long to wait between job status checks: a fixed interval like 2s (the default), or a growing one like backoff:5s..2m")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("DURATION")
+                .help("Stop waiting for the analysis after this long (e.g. 30m), and exit with code 3")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("max-polls")
+                .long("max-polls")
+                .value_name("N")
+                .help("Stop waiting for the analysis after checking its status N times, and exit with code 3")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("poll-output")
+                .long("poll-output")
+                .value_name("FORMAT")
+                .possible_values(&["human", "jsonl"])
+                .help("How to show the analysis's progress: messages on STDERR (human, the default), or also one JSON object per status check on STDOUT (jsonl), which is then all that STDOUT gets")
+                .takes_value(true)
+                .conflicts_with_all(&["verify-only", "use-prep", "strict-inputs", "target", "targets-file", "projects", "project-filter", "project-metadata", "project-meta"])
             )
             .arg(Arg::with_name("file")
                 .value_name("FILE(S)")
                 .takes_value(true)
                 .multiple(true)
-                .required(true)
+                .required_unless_one(&["target", "targets-file", "stdin-file", "zip-dir", "projects", "project-filter", "project-metadata", "project-meta"])
                 .index(2)
             )
+            .arg(Arg::with_name("target")
+                .long("target")
+                .value_name("TARGET")
+                .help("Analyze another project, given as project=NAME,file=PATH[,file=PATH...]; may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["project-id", "file", "name", "upload-name", "trend", "verify-only", "use-prep", "disable-tool", "strict-inputs", "branch", "new-branch", "stdin-file", "zip-dir"])
+            )
+            .arg(Arg::with_name("targets-file")
+                .long("targets-file")
+                .value_name("FILE")
+                .help("Read targets from FILE, a YAML list of entries with a project, files, and optionally fail-on thresholds of their own")
+                .takes_value(true)
+                .conflicts_with_all(&["project-id", "file", "name", "upload-name", "trend", "verify-only", "use-prep", "disable-tool", "strict-inputs", "branch", "new-branch", "stdin-file", "zip-dir"])
+            )
+            .arg(Arg::with_name("projects")
+                .long("project-id")
+                .value_name("PROJECT")
+                .help("Analyze the same files in this project (by id or exact name) too (the files then all come after the options); may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["target", "targets-file", "name", "upload-name", "trend", "verify-only", "use-prep", "disable-tool", "strict-inputs", "branch", "new-branch", "stdin-file", "zip-dir"])
+            )
+            .arg(Arg::with_name("project-filter")
+                .long("project-filter")
+                .value_name("NAME")
+                .help("Analyze the same files in every project whose name matches NAME (ignoring case)")
+                .takes_value(true)
+                .conflicts_with_all(&["target", "targets-file", "name", "upload-name", "trend", "verify-only", "use-prep", "disable-tool", "strict-inputs", "branch", "new-branch", "stdin-file", "zip-dir"])
+            )
+            .arg(Arg::with_name("project-metadata")
+                .long("project-metadata")
+                .number_of_values(2)
+                .value_names(&["FIELD", "VALUE"])
+                .help("Analyze the same files in every project with this metadata value; may be repeated, and combined with --project-filter")
+                .multiple(true)
+                .conflicts_with_all(&["target", "targets-file", "name", "upload-name", "trend", "verify-only", "use-prep", "disable-tool", "strict-inputs", "branch", "new-branch", "stdin-file", "zip-dir"])
+            )
+            .arg(Arg::with_name("project-meta")
+                .long("project-meta")
+                .value_name("FIELD=VALUE")
+                .help("Like --project-metadata, in one argument; a VALUE ending in * matches values starting with the rest")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["target", "targets-file", "name", "upload-name", "trend", "verify-only", "use-prep", "disable-tool", "strict-inputs", "branch", "new-branch", "stdin-file", "zip-dir"])
+            )
+            .arg(Arg::with_name("concurrency")
+                .long("concurrency")
+                .value_name("N")
+                .help("With several projects, how many uploads to run at once (default 2)")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json"])
+                .help("With several projects, the format of the combined results")
+                .takes_value(true)
+            )
     }
 
     // ANALYZE - argument extraction
@@ -210,44 +951,216 @@ impl <'a> CommandInner<'a> for AnalyzeCommand {
 
     // ANALYZE - execution
     fn run(&self, client: &ApiClient, args: AnalyzeCommandArgs<'a>) -> CommandResult {
-        let AnalyzeCommandArgs { project_id, files, name } = args;
-
-        // no matter what, start the analysis
-        let mut analysis_response: ApiResult<ApiAnalysisJobResponse> = client
-            .start_analysis(project_id, files)
-            .map(|resp| {
-                println!("# Started analysis {} with job id {}", resp.analysis_id, resp.job_id);
-                resp
+        let (project, files, upload_names, allow_empty_glob, stdin_name, zip_dirs, zip_options, max_upload_size, upload_retries, name, show_trend, verify_only, use_prep, disabled_tools, strict_inputs, branch, gate, show_progress, cancel_on_interrupt, poll, poll_output) = match args {
+            AnalyzeCommandArgs::Single { project, files, upload_names, allow_empty_glob, stdin_name, zip_dirs, zip_options, max_upload_size, upload_retries, name, show_trend, verify_only, use_prep, disabled_tools, strict_inputs, branch, gate, show_progress, cancel_on_interrupt, poll, poll_output } => {
+                (project, files, upload_names, allow_empty_glob, stdin_name, zip_dirs, zip_options, max_upload_size, upload_retries, name, show_trend, verify_only, use_prep, disabled_tools, strict_inputs, branch, gate, show_progress, cancel_on_interrupt, poll.with_default(client.poll_interval()), poll_output)
+            },
+            AnalyzeCommandArgs::Multi { targets, targets_file, projects, project_filter, files, allow_empty_glob, gate, concurrency, format, poll } => {
+                let mut targets = targets;
+                targets.extend(self.project_targets(client, &projects, project_filter.as_ref(), &files, allow_empty_glob)?);
+                return self.run_multi(client, targets, targets_file, gate.as_ref(), concurrency, format, poll.with_default(client.poll_interval()));
+            },
+        };
+
+        let project = resolve_project(client, project)?;
+        let project_id = project.id;
+        client.progress(&format!("Analyzing project {} ({})", project.name, project_id));
+
+        // fill in the name once before uploading anything, so that e.g. a missing environment variable fails early
+        let project_name = match name {
+            Some(ref template) if template.needs_project_name() => Some(project.name),
+            _ => None,
+        };
+        let mut name_values = NameValues::new(project_name.as_ref().map(|n| n.as_str()), project_id);
+        if let Some(ref template) = name {
+            if let Err(e) = template.render(&name_values) {
+                client.report_other_error("failed", "in the analysis name", &e);
+                return Err(Exit(1));
+            }
+        }
+
+        let zips = self.zip_dirs(client, &zip_dirs, &zip_options)?;
+        let matched = self.expand_files(client, &files, allow_empty_glob, &zips, stdin_name)?;
+        // only a file given as itself can have an upload name, not one matched by a pattern
+        let mut files: Vec<UploadFile> = matched.iter()
+            .map(|file| UploadFile {
+                path: &file.path,
+                name: if file.is_literal() { upload_names.get(file.source).cloned() } else { None },
+            })
+            .collect();
+        files.extend(zips.iter().map(|&(ref zip, ref name)| UploadFile { path: &zip.path, name: Some(name.as_str()) }));
+        self.check_files(client, &files, max_upload_size)?;
+
+        let identifying = Observed::new(poll.clone(), ShowProgress(client));
+        if verify_only {
+            let report = self.verify_inputs(client, prep::prepare_inputs(client, project_id, files, identifying), strict_inputs, &disabled_tools, true)?;
+            discard_prep(client, &report.prep_id);
+            return Ok(());
+        }
+
+        // check the inputs with a prep first if asked to, and otherwise too when a prep can take them,
+        // so that the server's warnings about them show up before the analysis runs
+        let prep = if use_prep || strict_inputs {
+            Some(client.create_analysis_prep(project_id))
+        } else if branch.is_none() && stdin_name.is_none() && upload_retries == 0 {
+            match client.create_analysis_prep(project_id) {
+                Err(ref e) if e.is_not_found() => {
+                    client.debug(Verbosity::Debug, "the server has no analysis preps, so the inputs aren't checked before uploading them");
+                    None
+                },
+                created => Some(created),
+            }
+        } else {
+            None
+        };
+
+        // no matter what, start the analysis (from the prep, if there is one)
+        let started = if let Some(created) = prep {
+            let report = created.and_then(|prep| prep::upload_inputs(client, prep.prep_id, files, identifying));
+            let report = self.verify_inputs(client, report, strict_inputs, &disabled_tools, use_prep || strict_inputs)?;
+            client.run_prep_analysis(&report.prep_id).map(AnalysisStart::Started)
+        } else {
+            // no printer with --no-progress (or --quiet)
+            let mut printer = if show_progress && !client.is_quiet() { Some(ProgressPrinter::new()) } else { None };
+            let progress = move |progress: &UploadProgress| {
+                if let Some(ref mut printer) = printer {
+                    printer.report(progress);
+                }
+            };
+            if upload_retries > 0 {
+                client.start_analysis_with_retry(project_id, branch.as_ref(), files, upload_retries, progress)
+            } else {
+                client.recover_upload(project_id, || {
+                    let mut inputs: Vec<UploadInput> = files.into_iter().map(UploadInput::from).collect();
+                    if let Some(name) = stdin_name {
+                        inputs.push(UploadInput::Stream { name: name.to_string(), content: Box::new(io::stdin()) });
+                    }
+                    client.start_analysis_with_progress(project_id, branch.as_ref(), inputs, progress)
+                })
+            }
+        };
+        let mut analysis_response: ApiResult<AnalysisStart> = started
+            .map(|start| {
+                match start {
+                    AnalysisStart::Started(ref resp) => client.progress(&format!("Started analysis {} with job id {}", resp.analysis_id, resp.job_id)),
+                    AnalysisStart::Recovered(ref analysis) => client.progress(&format!("The upload reported an error, but analysis {} started anyway", analysis.id)),
+                }
+                start
             });
 
         // if a name was specified, tell the server to set the name
-        if let Some(name) = name {
-            analysis_response = analysis_response.and_then(|analysis_job_response| {
-                let analysis_id = analysis_job_response.analysis_id;
+        if let Some(template) = name {
+            analysis_response = analysis_response.and_then(|analysis_start| {
+                let analysis_id = analysis_start.analysis_id();
+                name_values.analysis_id = analysis_id;
+                let name = match template.render(&name_values) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        client.warn(&format!("not naming analysis {}: {}", analysis_id, e));
+                        return Ok(analysis_start);
+                    },
+                };
 
-                client.set_analysis_name(project_id, analysis_id, name)
+                // the server may not have committed the new analysis's record yet, so give it a moment
+                let backoff = BoundedPoll::new(ExponentialBackoff::new(Duration::from_millis(500), Duration::from_secs(5)))
+                    .timeout(Duration::from_secs(30));
+                let waiting = |_: usize, e: &ApiError| client.progress(&format!("Analysis {} can't be named yet ({}); trying again", analysis_id, e));
+                retry::retry_while(|| client.set_analysis_name(project_id, analysis_id, &name), retry::is_not_available_yet, Observed::new(backoff, waiting))
                     .map(|_| {
-                        println!("# Set analysis {}'s name to \"{}\"", analysis_id, name);
-                        analysis_job_response
+                        client.progress(&format!("Set analysis {}'s name to \"{}\"", analysis_id, name));
+                        analysis_start
                     })
             });
         }
 
+        // while waiting, the first Ctrl-C stops the poll, so that the job can be cancelled before exiting
+        let job_id = match analysis_response {
+            Ok(AnalysisStart::Started(ref response)) => Some(response.job_id.clone()),
+            _ => None,
+        };
+        let interrupt_handler = interrupt::install_handler();
+        let progress = CommandProgress::new(client, poll_output, job_id.as_ref().map(|id| id.as_str()));
+        let poll = Observed::new(Interruptible::new(poll, &interrupt::INTERRUPTED), progress.clone());
         let analysis_result_status = analysis_response
-            .and_then(|analysis_job_response| {
-                let job_id = analysis_job_response.job_id;
-                client.poll_job_completion(&job_id, Duration::from_secs(2))
+            .and_then(|analysis_start| {
+                // the final status, and the reason for it if the server gave one
+                let analysis_id = analysis_start.analysis_id();
+                match analysis_start {
+                    AnalysisStart::Started(response) => client.poll_analysis_job(project_id, analysis_id, &response.job_id, poll)
+                        .map(|job| {
+                            let percent = job.progress.as_ref().and_then(|p| p.percent);
+                            (analysis_id, job.status, job.reason, percent)
+                        }),
+                    AnalysisStart::Recovered(_) => client.poll_analysis_completion(project_id, analysis_id, poll)
+                        .map(|status| (analysis_id, status, None, None)),
+                }
             });
+        // the poll is over, so Ctrl-C goes back to what it did before (e.g. exiting while the cancel prompt waits)
+        drop(interrupt_handler);
 
+        // with --poll-output jsonl, the last JSON line has the final status (or the error), and nothing else goes to STDOUT
+        match analysis_result_status {
+            Ok((_, status, _, percent)) => progress.finish(status, percent),
+            Err(ref e) => progress.fail(e),
+        }
+        let analysis_result_status = analysis_result_status.map(|(analysis_id, status, reason, _)| (analysis_id, status, reason));
+        let print_status = |status: JobStatus| if poll_output == PollOutput::Human { println!("{:?}", status) };
         match analysis_result_status {
             Err(e) => {
-                eprintln!("Error during analysis: {:?}", e);
+                client.report_error("during analysis", &e);
                 Err(Exit(1))
             },
-            Ok(status) => {
-                println!("# Polling done");
-                println!("{:?}", status);
-                Ok(())
+            Ok((analysis_id, status, _)) if !status.is_ready() && interrupt::interrupted() => {
+                self.on_interrupt(client, analysis_id, job_id, cancel_on_interrupt)
+            },
+            Ok((_, status, _)) if !status.is_ready() => {
+                client.progress("Polling done");
+                print_status(status);
+                client.report_failure("timed_out", "gave up waiting for the analysis to finish");
+                Err(Exit(polling::EXIT_TIMED_OUT))
+            },
+            Ok((analysis_id, JobStatus::Failed, reason)) => {
+                client.progress("Polling done");
+                print_status(JobStatus::Failed);
+                match reason {
+                    Some(reason) => client.report_failure("job_failed", &format!("the analysis failed: {}", reason)),
+                    None => client.report_failure("job_failed", "the analysis failed (the server didn't say why)"),
+                }
+                // so that CI logs show what the server knows about the failure, without anyone opening the UI
+                match client.get_analysis(project_id, analysis_id) {
+                    Ok(analysis) => eprintln!("# Analysis {}: {}", analysis_id, serde_json::to_string(&AnalysisRecordOutput::from(&analysis)).unwrap()),
+                    Err(e) => client.warn(&format!("couldn't load the details of analysis {}: {}", analysis_id, e)),
+                }
+                Err(Exit(1))
+            },
+            Ok((analysis_id, JobStatus::Cancelled, _)) => {
+                client.progress("Polling done");
+                print_status(JobStatus::Cancelled);
+                client.report_failure("cancelled", &format!("analysis {} was cancelled", analysis_id));
+                Err(Exit(1))
+            },
+            Ok((analysis_id, status, _)) => {
+                client.progress("Polling done");
+                print_status(status);
+                if show_trend && status.is_success() {
+                    let line = match trend::gather_trend(client, project_id, 2) {
+                        Ok(ref t) if t.points.len() > 1 => Some(format!("# Since the previous analysis: {}", t.delta_line())),
+                        Ok(ref t) => Some(format!("# No previous analysis to compare to: {}", t.delta_line())),
+                        Err(e) => {
+                            client.warn(&format!("couldn't load the severity trend: {}", e));
+                            None
+                        },
+                    };
+                    match line {
+                        Some(ref line) if poll_output == PollOutput::Human => println!("{}", line),
+                        Some(ref line) => eprintln!("{}", line),
+                        None => (),
+                    }
+                }
+                match gate {
+                    Some(ref gate) if status.is_success() => self.check_gate(client, project_id, analysis_id, gate),
+                    _ => Ok(()),
+                }
             },
         }
     }
@@ -259,26 +1172,43 @@ impl <'a> CommandInner<'a> for AnalyzeCommand {
 // -------------------------------------------------------------------------------------------------
 pub struct ProjectsCommand;
 pub struct ProjectsCommandArgs<'a> {
-    filter: Option<ApiProjectFilter<'a>>
+    filter: Option<ProjectFilter>,
+    offset: Option<u32>,
+    limit: Option<u32>,
+    branches: bool,
+    tree: bool,
+    /// Only list projects under this one, given by id or name (`--parent`).
+    parent: Option<&'a str>,
+    /// Whether `parent` means all the projects under it, rather than just its children.
+    recursive: bool,
 }
 impl <'a> ProjectsCommand {
     fn inner_parse(&self, project_args: &'a ArgMatches) -> Result<ProjectsCommandArgs<'a>, &'a str> {
-        let mut metadatas = HashMap::new();
+        let mut filter = ProjectFilter::new();
         for mut metadata_values in project_args.values_of("metadata") {
             while let Some(k) = metadata_values.next() {
                 let v = metadata_values.next().ok_or("metadata must be given as key value pairs")?;
-                metadatas.insert(k, v);
+                filter = filter.metadata(k, v);
             }
         }
-        let name = project_args.value_of("name");
-        if metadatas.is_empty() && name.is_none() {
-            Ok(ProjectsCommandArgs { filter: None })
-        } else {
-            let metadatas_opt = if metadatas.is_empty() { None } else { Some(metadatas) };
-            Ok(ProjectsCommandArgs {
-                filter: Some(ApiProjectFilter { name, metadata: metadatas_opt })
-            })
+        for raw in project_args.values_of("meta").into_iter().flat_map(|values| values) {
+            let (field, pattern) = parse_meta(raw).ok_or("meta must be given as FIELD=VALUE")?;
+            filter = filter.metadata(field, MetadataMatch::from_pattern(pattern));
+        }
+        if let Some(name) = project_args.value_of("name") {
+            filter = filter.name(name);
         }
+        let offset = parse_optional(project_args, "offset", "offset should be a number")?;
+        let limit = parse_optional(project_args, "limit", "limit should be a positive number")?;
+        if limit == Some(0) {
+            return Err("limit should be a positive number");
+        }
+        let branches = project_args.is_present("branches");
+        let filter = if filter.is_empty() { None } else { Some(filter) };
+        let tree = project_args.is_present("tree");
+        let parent = project_args.value_of("parent");
+        let recursive = project_args.is_present("recursive");
+        Ok(ProjectsCommandArgs { filter, offset, limit, branches, tree, parent, recursive })
     }
 }
 impl <'a> CommandInner<'a> for ProjectsCommand {
@@ -300,10 +1230,57 @@ impl <'a> CommandInner<'a> for ProjectsCommand {
                 .long("metadata")
                 .number_of_values(2)
                 .value_names(&["FIELD", "VALUE"])
-                .help("Provide criteria by project metadata")
+                .help("Provide criteria by project metadata; giving the same field again matches either value")
+                .multiple(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("meta")
+                .long("meta")
+                .value_name("FIELD=VALUE")
+                .help("Like --metadata, in one argument; a VALUE ending in * matches values starting with the rest")
+                .takes_value(true)
                 .multiple(true)
+                .number_of_values(1)
+                .required(false)
+            )
+            .arg(Arg::with_name("offset")
+                .long("offset")
+                .value_name("N")
+                .help("Skip the first N matching projects")
+                .takes_value(true)
                 .required(false)
             )
+            .arg(Arg::with_name("limit")
+                .long("limit")
+                .value_name("N")
+                .help("List at most N projects")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("branches")
+                .long("branches")
+                .takes_value(false)
+                .help("List each project's branches too, as \"project / branch\" rows")
+            )
+            .arg(Arg::with_name("tree")
+                .long("tree")
+                .takes_value(false)
+                .conflicts_with_all(&["branches", "offset", "limit"])
+                .help("Print the projects as an indented tree, each under its parent")
+            )
+            .arg(Arg::with_name("parent")
+                .long("parent")
+                .value_name("ID_OR_NAME")
+                .help("Only list the projects directly under this one (or all the projects under it, with --recursive)")
+                .takes_value(true)
+                .conflicts_with("branches")
+            )
+            .arg(Arg::with_name("recursive")
+                .long("recursive")
+                .takes_value(false)
+                .requires("parent")
+                .help("With --parent, list the projects under its children too, and so on")
+            )
     }
 
     fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
@@ -315,23 +1292,1905 @@ impl <'a> CommandInner<'a> for ProjectsCommand {
     }
 
     fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
-        let ProjectsCommandArgs { filter } = args;
+        let ProjectsCommandArgs { filter, offset, limit, branches, tree, parent, recursive } = args;
+        match client.output_format() {
+            Some(OutputFormat::Json) | Some(OutputFormat::Csv) if tree => {
+                // the tree is only ever text, which a script asking for json or csv couldn't read
+                client.report_failure("failed", "--tree can't be used with --format json or --format csv");
+                return Err(Exit(1));
+            },
+            _ => (),
+        }
+        if branches {
+            return self.run_with_branches(client, filter.as_ref(), offset, limit);
+        }
 
-        let plist = match filter {
-            Some(ref filter) => client.query_projects(filter),
-            None => client.get_projects(),
+        let plist = match (parent, limit) {
+            (Some(parent), _) => {
+                let projects = self.under_parent(client, filter.as_ref(), parent, recursive, tree)?;
+                Ok(projects.into_iter()
+                    .skip(offset.unwrap_or(0) as usize)
+                    .take(limit.map_or(usize::max_value(), |limit| limit as usize))
+                    .collect())
+            },
+            // one page is enough; servers that ignore paging send everything (from the start), so page it here
+            (None, Some(limit)) => client.query_projects_page(filter.as_ref(), offset.unwrap_or(0), limit)
+                .map(|projects| {
+                    let skip = if projects.len() > limit as usize { offset.unwrap_or(0) as usize } else { 0 };
+                    projects.into_iter().skip(skip).take(limit as usize).collect()
+                }),
+            (None, None) => client.query_projects_paged(filter.as_ref(), PROJECT_PAGE_SIZE)
+                .map(|projects| projects.into_iter().skip(offset.unwrap_or(0) as usize).collect()),
         };
         match plist {
             Err(e) => {
-                eprintln!("Error loading projects: {:?}", e);
+                client.report_error("loading projects", &e);
                 Err(Exit(1))
             },
             Ok(projects) => {
-                for project in projects {
-                    println!("{}", serde_json::to_string(&project).unwrap());
+                if tree {
+                    print!("{}", hierarchy::render_tree(&hierarchy::build_tree(&projects)));
+                    return Ok(());
+                }
+                match client.output_format() {
+                    Some(format) => print!("{}", projects.render(format)),
+                    // without --format, one JSON document per line, as always
+                    None => for project in projects {
+                        println!("{}", serde_json::to_string(&ProjectOutput::from(&project)).unwrap());
+                    },
                 }
                 Ok(())
             }
         }
     }
 }
+
+
+impl ProjectsCommand {
+    /// The projects under `parent` (matching `filter`, if there is one), with the parent itself first if `include_parent`.
+    fn under_parent(&self, client: &ApiClient, filter: Option<&ProjectFilter>, parent: &str, recursive: bool, include_parent: bool) -> Result<Vec<ApiProject>, Exit> {
+        let parent_id = client.resolve_project(parent).map_err(|e| {
+            client.report_error("finding the parent project", &e);
+            Exit(1)
+        })?.id;
+        let all = client.get_projects().map_err(|e| {
+            client.report_error("loading projects", &e);
+            Exit(1)
+        })?;
+        let mut under: HashSet<u32> = hierarchy::descendants(&all, parent_id, recursive).iter().map(|p| p.id).collect();
+        if let Some(filter) = filter {
+            let matching: HashSet<u32> = client.query_projects(filter).map_err(|e| {
+                client.report_error("loading projects", &e);
+                Exit(1)
+            })?.iter().map(|p| p.id).collect();
+            under = under.intersection(&matching).cloned().collect();
+        }
+        let mut projects: Vec<ApiProject> = Vec::new();
+        for mut project in all {
+            if project.id == parent_id && include_parent {
+                // it's the root of the tree, whatever it's under
+                project.parent_id = None;
+                projects.insert(0, project);
+            } else if under.contains(&project.id) {
+                projects.push(project);
+            }
+        }
+        Ok(projects)
+    }
+
+    fn run_with_branches(&self, client: &ApiClient, filter: Option<&ProjectFilter>, offset: Option<u32>, limit: Option<u32>) -> CommandResult {
+        match client.query_projects_with_branches(filter, PROJECT_PAGE_SIZE) {
+            Err(e) => {
+                client.report_error("loading projects", &e);
+                Err(Exit(1))
+            },
+            Ok(projects) => {
+                let projects = projects.into_iter()
+                    .skip(offset.unwrap_or(0) as usize)
+                    .take(limit.map_or(usize::max_value(), |limit| limit as usize));
+                for project in projects {
+                    if project.branches.is_empty() {
+                        // servers without branching leave them out
+                        println!("{}", project.name);
+                    }
+                    for branch in project.branches.iter() {
+                        println!("{} / {}", project.name, branch.name);
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: create-project
+// -------------------------------------------------------------------------------------------------
+pub struct CreateProjectCommand;
+pub struct CreateProjectCommandArgs<'a> {
+    name: &'a str,
+    parent_id: Option<u32>,
+}
+impl <'a> CommandInner<'a> for CreateProjectCommand {
+    type Args = CreateProjectCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("create-project")
+            .about("Create a project, and print its id")
+            .arg(Arg::with_name("name")
+                .index(1)
+                .value_name("NAME")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("parent")
+                .long("parent")
+                .value_name("ID")
+                .help("Create the project as a child of the project with this id")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("create-project").map(|create_args| {
+            let name = create_args.value_of("name").ok_or("project name missing")?;
+            let parent_id = match create_args.value_of("parent") {
+                Some(raw) => Some(raw.parse().map_err(|_| "parent should be a number")?),
+                None => None,
+            };
+            Ok(CreateProjectCommandArgs { name, parent_id })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let CreateProjectCommandArgs { name, parent_id } = args;
+        match client.create_project(name, parent_id) {
+            Err(e) => {
+                client.report_error(&format!("creating project {}", name), &e);
+                Err(Exit(1))
+            },
+            Ok(project) => {
+                println!("{}", project.id);
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: update-project
+// -------------------------------------------------------------------------------------------------
+pub struct UpdateProjectCommand;
+pub struct UpdateProjectCommandArgs<'a> {
+    project: &'a str,
+    name: Option<&'a str>,
+    parent_id: Option<Option<u32>>,
+}
+impl <'a> CommandInner<'a> for UpdateProjectCommand {
+    type Args = UpdateProjectCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("update-project")
+            .about("Rename a project, or move it under a different parent")
+            .arg(Arg::with_name("id")
+                .long("id")
+                .visible_alias("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("name")
+                .long("name")
+                .value_name("NAME")
+                .help("The project's new name")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("parent")
+                .long("parent")
+                .value_name("ID")
+                .help("Move the project under the project with this id")
+                .takes_value(true)
+                .conflicts_with("no-parent")
+            )
+            .arg(Arg::with_name("no-parent")
+                .long("no-parent")
+                .help("Move the project to the top level")
+                .takes_value(false)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("update-project").map(|update_args| {
+            let project = update_args.value_of("id").ok_or("project id missing")?;
+            let name = update_args.value_of("name");
+            let parent_id = match update_args.value_of("parent") {
+                Some(raw) => Some(Some(raw.parse().map_err(|_| "parent should be a number")?)),
+                None if update_args.is_present("no-parent") => Some(None),
+                None => None,
+            };
+            if name.is_none() && parent_id.is_none() {
+                return Err("nothing to update; give --name, --parent, or --no-parent");
+            }
+            Ok(UpdateProjectCommandArgs { project, name, parent_id })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let UpdateProjectCommandArgs { project, name, parent_id } = args;
+        let project_id = resolve_project(client, project)?.id;
+        match client.update_project(project_id, name, parent_id) {
+            Err(e) => {
+                client.report_error(&format!("updating project {}", project_id), &e);
+                Err(Exit(1))
+            },
+            Ok(()) => {
+                client.progress(&format!("Updated project {}", project_id));
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: project-metadata
+// -------------------------------------------------------------------------------------------------
+pub struct ProjectMetadataCommand;
+pub struct ProjectMetadataCommandArgs<'a> {
+    project: &'a str,
+    format: &'a str,
+}
+impl <'a> CommandInner<'a> for ProjectMetadataCommand {
+    type Args = ProjectMetadataCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("project-metadata")
+            .about("Show the values of a project's metadata fields")
+            .arg(Arg::with_name("id")
+                .long("id")
+                .visible_alias("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json"])
+                .default_value("table")
+                .help("Format of the metadata")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("project-metadata").map(|metadata_args| {
+            let project = metadata_args.value_of("id").ok_or("project id missing")?;
+            let format = metadata_args.value_of("output").unwrap_or("table");
+            Ok(ProjectMetadataCommandArgs { project, format })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let ProjectMetadataCommandArgs { project, format } = args;
+        let project_id = resolve_project(client, project)?.id;
+        match client.get_project_metadata(project_id) {
+            Err(e) => {
+                client.report_error(&format!("getting metadata for project {}", project_id), &e);
+                Err(Exit(1))
+            },
+            Ok(fields) => {
+                match format {
+                    "json" => println!("{}", serde_json::to_string(&ProjectMetadataOutput::new(project_id, &fields)).unwrap()),
+                    _ => {
+                        let width = fields.iter().map(|f| f.name.chars().count()).max().unwrap_or(0);
+                        for field in fields {
+                            println!("{:width$}  {}", field.name, field.value.as_ref().map(|v| v.as_str()).unwrap_or("(not set)"), width = width);
+                        }
+                    },
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: set-metadata
+// -------------------------------------------------------------------------------------------------
+pub struct SetMetadataCommand;
+pub struct SetMetadataCommandArgs<'a> {
+    project: &'a str,
+    values: HashMap<&'a str, &'a str>,
+}
+impl <'a> CommandInner<'a> for SetMetadataCommand {
+    type Args = SetMetadataCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("set-metadata")
+            .about("Set the values of some of a project's metadata fields")
+            .arg(Arg::with_name("id")
+                .long("id")
+                .visible_alias("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("set")
+                .long("set")
+                .value_name("FIELD=VALUE")
+                .help("A field to set, and its new value; repeat it to set more than one field")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("set-metadata").map(|set_args| {
+            let project = set_args.value_of("id").ok_or("project id missing")?;
+            let mut values = HashMap::new();
+            for assignment in set_args.values_of("set").into_iter().flat_map(|v| v) {
+                let mut parts = assignment.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(field), Some(value)) if !field.is_empty() => { values.insert(field, value); },
+                    _ => return Err("--set should be given as FIELD=VALUE"),
+                }
+            }
+            Ok(SetMetadataCommandArgs { project, values })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let SetMetadataCommandArgs { project, values } = args;
+        let project_id = resolve_project(client, project)?.id;
+        match client.set_project_metadata(project_id, &values) {
+            Err(e) => {
+                client.report_error(&format!("setting metadata for project {}", project_id), &e);
+                Err(Exit(1))
+            },
+            Ok(()) => {
+                client.progress(&format!("Set {} metadata field(s) for project {}", values.len(), project_id));
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: delete-project
+// -------------------------------------------------------------------------------------------------
+pub struct DeleteProjectCommand;
+pub struct DeleteProjectCommandArgs<'a> {
+    project: &'a str,
+    confirmed: bool,
+}
+impl <'a> CommandInner<'a> for DeleteProjectCommand {
+    type Args = DeleteProjectCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("delete-project")
+            .about("Delete a project, along with all of its analyses and findings")
+            .arg(Arg::with_name("project-id")
+                .index(1)
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("yes")
+                .long("yes")
+                .takes_value(false)
+                .help("Confirm that the project should really be deleted; nothing is deleted without it")
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("delete-project").map(|delete_args| {
+            let project = delete_args.value_of("project-id").ok_or("project id missing")?;
+            let confirmed = delete_args.is_present("yes");
+            Ok(DeleteProjectCommandArgs { project, confirmed })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let DeleteProjectCommandArgs { project, confirmed } = args;
+        let project_id = resolve_project(client, project)?.id;
+        if !confirmed {
+            client.report_failure("not_confirmed", &format!("not deleting project {}: deleting a project can't be undone, so add --yes to confirm it", project_id));
+            return Err(Exit(1));
+        }
+
+        match client.delete_project(project_id, Observed::new(Duration::from_secs(2), ShowProgress(client))) {
+            Err(e) => {
+                client.report_error(&format!("deleting project {}", project_id), &e);
+                Err(Exit(1))
+            },
+            Ok(()) => {
+                client.progress(&format!("Deleted project {}", project_id));
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: list-analyses
+// -------------------------------------------------------------------------------------------------
+pub struct ListAnalysesCommand;
+pub struct ListAnalysesCommandArgs<'a> {
+    project: &'a str,
+    limit: Option<usize>,
+    format: Option<OutputFormat>,
+}
+impl <'a> CommandInner<'a> for ListAnalysesCommand {
+    type Args = ListAnalysesCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("list-analyses")
+            .about("List a project's analyses, newest first")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("limit")
+                .long("limit")
+                .value_name("N")
+                .help("Only list the N most recent analyses")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json", "csv"])
+                .help("Format of the list (default: the --format given before the command, or table)")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("list-analyses").map(|list_args| {
+            let project = list_args.value_of("project").ok_or("project id missing")?;
+            let limit = parse_optional(list_args, "limit", "limit should be a number")?;
+            let format = parse_optional(list_args, "format", "unknown output format")?;
+            Ok(ListAnalysesCommandArgs { project, limit, format })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let ListAnalysesCommandArgs { project, limit, format } = args;
+        let project_id = resolve_project(client, project)?.id;
+        match client.get_analyses(project_id) {
+            Err(e) => {
+                client.report_error(&format!("loading analyses for project {}", project_id), &e);
+                Err(Exit(1))
+            },
+            Ok(mut analyses) => {
+                // analysis ids increase over time, and unlike the timestamps, every server version reports them
+                analyses.sort_by(|a, b| b.id.cmp(&a.id));
+                if let Some(limit) = limit {
+                    analyses.truncate(limit);
+                }
+                print!("{}", analyses.render(format.or(client.output_format()).unwrap_or(OutputFormat::Table)));
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: list-branches
+// -------------------------------------------------------------------------------------------------
+pub struct ListBranchesCommand;
+pub struct ListBranchesCommandArgs<'a> {
+    project: &'a str,
+    format: Option<OutputFormat>,
+}
+impl <'a> CommandInner<'a> for ListBranchesCommand {
+    type Args = ListBranchesCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("list-branches")
+            .about("List a project's branches")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json", "csv"])
+                .help("Format of the list (default: the global --format, or table)")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("list-branches").map(|branches_args| {
+            let project = branches_args.value_of("project").ok_or("project id missing")?;
+            let format = parse_optional(branches_args, "output", "unknown output format")?;
+            Ok(ListBranchesCommandArgs { project, format })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let ListBranchesCommandArgs { project, format } = args;
+        let project_id = resolve_project(client, project)?.id;
+        match client.get_branches(project_id) {
+            Err(e) => {
+                client.report_error(&format!("loading the branches of project {}", project_id), &e);
+                Err(Exit(1))
+            },
+            Ok(branches) => {
+                print!("{}", branches.render(format.or(client.output_format()).unwrap_or(OutputFormat::Table)));
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: findings
+// -------------------------------------------------------------------------------------------------
+pub struct FindingsCommand;
+pub struct FindingsCommandArgs<'a> {
+    project: &'a str,
+    filter: FindingFilter,
+    format: &'a str,
+}
+impl <'a> FindingsCommand {
+    fn inner_parse(&self, findings_args: &'a ArgMatches) -> Result<FindingsCommandArgs<'a>, &'a str> {
+        let project = findings_args.value_of("project").ok_or("project id missing")?;
+        let severity = match comma_list(findings_args, "severity") {
+            Some(names) => Some(names.iter()
+                .map(|name| summary::SEVERITIES.iter().find(|s| s.eq_ignore_ascii_case(name)).map(|s| s.to_string()))
+                .collect::<Option<Vec<String>>>()
+                .ok_or("severity should be a list of severities, e.g. critical,high")?),
+            None => None,
+        };
+        let cwe = match comma_list(findings_args, "cwe") {
+            Some(ids) => Some(ids.iter()
+                .map(|id| id.trim_start_matches("CWE-").trim_start_matches("cwe-").parse().ok())
+                .collect::<Option<Vec<u32>>>()
+                .ok_or("cwe should be a list of CWE ids, e.g. 89,79")?),
+            None => None,
+        };
+        let filter = FindingFilter {
+            severity,
+            status: comma_list(findings_args, "status").map(|list| list.into_iter().map(String::from).collect()),
+            detection_method: comma_list(findings_args, "detection-method").map(|list| list.into_iter().map(String::from).collect()),
+            cwe,
+        };
+        let format = findings_args.value_of("output").unwrap_or("table");
+        Ok(FindingsCommandArgs { project, filter, format })
+    }
+}
+impl <'a> CommandInner<'a> for FindingsCommand {
+    type Args = FindingsCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("findings")
+            .about("List a project's findings, optionally narrowed down by severity, status, detection method, or CWE")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("severity")
+                .long("severity")
+                .value_name("SEVERITIES")
+                .help("Only list findings with one of these (comma-separated) severities, e.g. critical,high")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("status")
+                .long("status")
+                .value_name("STATUSES")
+                .help("Only list findings with one of these (comma-separated) statuses, e.g. new,escalated")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("detection-method")
+                .long("detection-method")
+                .value_name("METHODS")
+                .help("Only list findings found by one of these (comma-separated) detection methods, e.g. Static")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("cwe")
+                .long("cwe")
+                .value_name("CWES")
+                .help("Only list findings with one of these (comma-separated) CWE ids, e.g. 89,79")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json"])
+                .default_value("table")
+                .help("Format of the list")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        if let Some(findings_args) = matches.subcommand_matches("findings") {
+            Some(self.inner_parse(findings_args))
+        } else {
+            None
+        }
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let FindingsCommandArgs { project, mut filter, format } = args;
+        let project_id = resolve_project(client, project)?.id;
+        if let Some(given) = filter.status.take() {
+            // the server wants status ids, but people know statuses by their names
+            match status_ids(client, project_id, &given) {
+                Err(e) => {
+                    client.report_error(&format!("checking the statuses for project {}", project_id), &e);
+                    return Err(Exit(1));
+                },
+                Ok(ids) => filter.status = Some(ids),
+            }
+        }
+        let query = FindingQuery { filter: Some(filter.to_value()), ..FindingQuery::default() };
+        match client.query_all_findings(project_id, &query, summary::FINDINGS_PAGE_SIZE) {
+            Err(e) => {
+                client.report_error(&format!("loading findings for project {}", project_id), &e);
+                Err(Exit(1))
+            },
+            Ok(findings) => {
+                match format {
+                    "json" => println!("{}", serde_json::to_string(&FindingsOutput::new(project_id, &findings, &client.get_config().base_url)).unwrap()),
+                    _ => print!("{}", render_findings_table(&findings)),
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// The ids of the statuses that each of `given` means (by id or by name), checked against the project's statuses.
+fn status_ids(client: &ApiClient, project_id: u32, given: &[String]) -> ApiResult<Vec<String>> {
+    let statuses = client.get_finding_statuses(project_id)?;
+    given.iter().map(|name| FindingStatus::resolve(&statuses, name).map(|status| status.id.clone())).collect()
+}
+
+/// One line per finding: its id, severity, status, CWE, location, and title.
+fn render_findings_table(findings: &[ApiFinding]) -> String {
+    let rows = findings.iter().map(|f| vec![
+        f.id.to_string(),
+        f.severity.clone().unwrap_or_default(),
+        f.status.clone().unwrap_or_default(),
+        f.cwe.as_ref().map(|cwe| format!("CWE-{}", cwe.id)).unwrap_or_default(),
+        summary::location_text(f).unwrap_or_default(),
+        f.title().to_string(),
+    ]).collect();
+    render_columns(&["ID", "SEVERITY", "STATUS", "CWE", "LOCATION", "TITLE"], rows)
+}
+
+/// The comma-separated values of an optional argument, e.g. `--severity critical,high`.
+fn comma_list<'a>(matches: &'a ArgMatches, name: &str) -> Option<Vec<&'a str>> {
+    matches.value_of(name).map(|raw| raw.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()).collect())
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: finding-counts
+// -------------------------------------------------------------------------------------------------
+pub struct FindingCountsCommand;
+pub struct FindingCountsCommandArgs<'a> {
+    project: &'a str,
+    group_by: CountGroup,
+    format: &'a str,
+}
+impl <'a> CommandInner<'a> for FindingCountsCommand {
+    type Args = FindingCountsCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("finding-counts")
+            .about("Count a project's findings, grouped by severity, status, CWE, or rule")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("by")
+                .long("by")
+                .value_name("GROUP")
+                .possible_values(&["severity", "status", "cwe", "rule"])
+                .default_value("severity")
+                .help("What to group the findings by")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json"])
+                .default_value("table")
+                .help("Format of the counts")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("finding-counts").map(|counts_args| {
+            let project = counts_args.value_of("project").ok_or("project id missing")?;
+            let group_by = match counts_args.value_of("by") {
+                Some("status") => CountGroup::Status,
+                Some("cwe") => CountGroup::Cwe,
+                Some("rule") => CountGroup::Rule,
+                _ => CountGroup::Severity,
+            };
+            let format = counts_args.value_of("output").unwrap_or("table");
+            Ok(FindingCountsCommandArgs { project, group_by, format })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let FindingCountsCommandArgs { project, group_by, format } = args;
+        let project_id = resolve_project(client, project)?.id;
+        match client.get_finding_counts(project_id, group_by, None) {
+            Err(e) => {
+                client.report_error(&format!("counting findings for project {}", project_id), &e);
+                Err(Exit(1))
+            },
+            Ok(counts) => {
+                match format {
+                    "json" => println!("{}", serde_json::to_string(&FindingCountsOutput::new(project_id, group_by, &counts)).unwrap()),
+                    _ if counts.is_empty() => println!("# No findings"),
+                    _ => {
+                        let rows = counts.iter().map(|c| vec![c.name.clone(), c.count.to_string()]).collect();
+                        print!("{}", render_columns(&["GROUP", "COUNT"], rows));
+                    },
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: finding
+// -------------------------------------------------------------------------------------------------
+pub struct FindingCommand;
+pub struct FindingCommandArgs<'a> {
+    project: &'a str,
+    finding_id: u64,
+    expand: Vec<FindingExpand>,
+}
+impl <'a> CommandInner<'a> for FindingCommand {
+    type Args = FindingCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("finding")
+            .about("Print everything the server has to say about one finding, as JSON")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("id")
+                .long("id")
+                .value_name("FINDING ID")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("expand")
+                .long("expand")
+                .value_name("DETAILS")
+                .help("Extra (comma-separated) details to include: results, descriptions, and/or triage-time")
+                .takes_value(true)
+                .required(false)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("finding").map(|finding_args| {
+            let project = finding_args.value_of("project").ok_or("project id missing")?;
+            let finding_id: u64 = finding_args.value_of("id")
+                .ok_or("finding id missing")?
+                .parse().map_err(|_| "id should be a number")?;
+            let expand = comma_list(finding_args, "expand").unwrap_or_default().into_iter()
+                .map(FindingExpand::from_key)
+                .collect::<Option<Vec<_>>>()
+                .ok_or("expand should be a list of results, descriptions, and/or triage-time")?;
+            Ok(FindingCommandArgs { project, finding_id, expand })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let FindingCommandArgs { project, finding_id, expand } = args;
+        let project_id = resolve_project(client, project)?.id;
+        match client.get_finding(project_id, finding_id, &expand) {
+            Err(e) => {
+                client.report_error(&format!("loading finding {}", finding_id), &e);
+                Err(Exit(1))
+            },
+            Ok(finding) => {
+                println!("{}", serde_json::to_string_pretty(&finding).unwrap());
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: triage
+// -------------------------------------------------------------------------------------------------
+pub struct TriageCommand;
+pub struct TriageCommandArgs<'a> {
+    project: &'a str,
+    filter: serde_json::Value,
+    status: &'a str,
+    comment: Option<&'a str>,
+}
+impl <'a> CommandInner<'a> for TriageCommand {
+    type Args = TriageCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("triage")
+            .about("Change the status of every finding in a project that matches a filter")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("filter-path")
+                .long("filter-path")
+                .value_name("GLOB")
+                .help("Only change the findings in files matching this pattern, e.g. 'vendor/**' (can be given more than once)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+            )
+            .arg(Arg::with_name("filter")
+                .long("filter")
+                .value_name("JSON")
+                .help("Only change the findings matching this filter (see the Code Dx API guide for its format)")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("set-status")
+                .long("set-status")
+                .value_name("STATUS")
+                .help("The status to give the findings, by id or name, e.g. false-positive")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("comment")
+                .long("comment")
+                .value_name("TEXT")
+                .help("A comment to add to each changed finding, saying why")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("triage").map(|triage_args| {
+            let project = triage_args.value_of("project").ok_or("project id missing")?;
+            let mut filter = match triage_args.value_of("filter") {
+                Some(raw) => match serde_json::from_str(raw) {
+                    Ok(filter @ serde_json::Value::Object(_)) => filter,
+                    _ => return Err("filter should be a JSON object"),
+                },
+                None => json!({}),
+            };
+            if let Some(paths) = triage_args.values_of("filter-path") {
+                filter["path"] = json!(paths.collect::<Vec<_>>());
+            }
+            // an empty filter would change every finding in the project, which is never what a typo meant
+            if filter.as_object().map_or(true, |criteria| criteria.is_empty()) {
+                return Err("which findings? (give --filter-path and/or --filter)");
+            }
+            let status = triage_args.value_of("set-status").ok_or("status missing")?;
+            let comment = triage_args.value_of("comment");
+            Ok(TriageCommandArgs { project, filter, status, comment })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let TriageCommandArgs { project, filter, status, comment } = args;
+        let project_id = resolve_project(client, project)?.id;
+        let job = match client.bulk_update_finding_status(project_id, filter, status, comment) {
+            Err(e) => {
+                client.report_error(&format!("updating the findings of project {}", project_id), &e);
+                return Err(Exit(1));
+            },
+            Ok(job) => job,
+        };
+        client.progress(&format!("Updating findings with job id {}", job.job_id));
+        match client.poll_job_completion(&job.job_id, Observed::new(Duration::from_secs(2), ShowProgress(client))) {
+            Err(e) => {
+                client.report_error(&format!("waiting for job {}", job.job_id), &e);
+                Err(Exit(1))
+            },
+            Ok(status) if !status.is_success() => {
+                client.report_failure("job_failed", &format!("updating the findings failed: job {} ended as {:?}", job.job_id, status));
+                Err(Exit(1))
+            },
+            Ok(_) => {
+                let mut result = Vec::new();
+                match client.download_job_result(&job.job_id, &mut result).ok().and_then(|_| updated_count(&result)) {
+                    Some(count) => println!("Updated {} findings", count),
+                    None => println!("Updated the findings"),
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// The number of findings a bulk status update says it changed, if its job result says.
+fn updated_count(job_result: &[u8]) -> Option<u64> {
+    let result: serde_json::Value = serde_json::from_slice(job_result).ok()?;
+    ["updated", "updatedCount", "count"].iter().filter_map(|key| result.get(key)).filter_map(|count| count.as_u64()).next()
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: list-statuses
+// -------------------------------------------------------------------------------------------------
+pub struct ListStatusesCommand;
+pub struct ListStatusesCommandArgs<'a> {
+    project: &'a str,
+    format: &'a str,
+}
+impl <'a> CommandInner<'a> for ListStatusesCommand {
+    type Args = ListStatusesCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("list-statuses")
+            .about("List the statuses a project's findings can have, including custom ones")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json"])
+                .default_value("table")
+                .help("Format of the list")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("list-statuses").map(|statuses_args| {
+            let project = statuses_args.value_of("project").ok_or("project id missing")?;
+            let format = statuses_args.value_of("output").unwrap_or("table");
+            Ok(ListStatusesCommandArgs { project, format })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let ListStatusesCommandArgs { project, format } = args;
+        let project_id = resolve_project(client, project)?.id;
+        match client.get_finding_statuses(project_id) {
+            Err(e) => {
+                client.report_error(&format!("loading the statuses for project {}", project_id), &e);
+                Err(Exit(1))
+            },
+            Ok(statuses) => {
+                match format {
+                    "json" => println!("{}", serde_json::to_string(&StatusesOutput::new(project_id, &statuses)).unwrap()),
+                    _ => {
+                        let rows = statuses.iter().map(|s| vec![
+                            s.id.clone(),
+                            s.name.clone(),
+                            if s.custom { "custom" } else { "built-in" }.to_string(),
+                        ]).collect();
+                        print!("{}", render_columns(&["ID", "NAME", "KIND"], rows));
+                    },
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: summary
+// -------------------------------------------------------------------------------------------------
+pub struct SummaryCommand;
+pub struct SummaryCommandArgs<'a> {
+    project: &'a str,
+    analysis_id: Option<u32>,
+    compare_to: Option<u32>,
+    top: u32,
+    output_file: Option<&'a Path>,
+    format: &'a str,
+    max_length: usize,
+}
+impl <'a> SummaryCommand {
+    fn inner_parse(&self, summary_args: &'a ArgMatches) -> Result<SummaryCommandArgs<'a>, &'a str> {
+        let project = summary_args.value_of("project").ok_or("project id missing")?;
+        let analysis_id = parse_optional(summary_args, "analysis", "analysis should be a number")?;
+        let compare_to = parse_optional(summary_args, "compare", "compare should be an analysis number")?;
+        let top: u32 = parse_optional(summary_args, "top", "top should be a number")?.unwrap_or(10);
+        let output_file = summary_args.value_of("output-file").map(|file| Path::new(file));
+        let format = summary_args.value_of("output").unwrap_or("html");
+        let max_length: usize = parse_optional(summary_args, "max-length", "max-length should be a number")?
+            .unwrap_or(summary::DEFAULT_MARKDOWN_LIMIT);
+        Ok(SummaryCommandArgs { project, analysis_id, compare_to, top, output_file, format, max_length })
+    }
+}
+impl <'a> CommandInner<'a> for SummaryCommand {
+    type Args = SummaryCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("summary")
+            .about("Generate a one-page HTML, Markdown, or JSON summary of a project's findings")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("analysis")
+                .long("analysis")
+                .value_name("ANALYSIS ID")
+                .help("Summarize a specific analysis instead of the latest one")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("compare")
+                .long("compare")
+                .value_name("ANALYSIS ID")
+                .help("Include new/resolved findings relative to an earlier analysis")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("top")
+                .long("top")
+                .value_name("N")
+                .help("How many of the most severe findings to list (default 10)")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("output-file")
+                .short("o")
+                .long("output-file")
+                .value_name("FILE")
+                .help("Where to write the summary (defaults to STDOUT)")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["html", "markdown", "json"])
+                .default_value("html")
+                .help("Format of the summary")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("max-length")
+                .long("max-length")
+                .value_name("BYTES")
+                .help("Truncate the list of findings to keep Markdown output under this size (default 65536)")
+                .takes_value(true)
+                .required(false)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        if let Some(summary_args) = matches.subcommand_matches("summary") {
+            Some(self.inner_parse(summary_args))
+        } else {
+            None
+        }
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let SummaryCommandArgs { project, analysis_id, compare_to, top, output_file, format, max_length } = args;
+        let project_id = resolve_project(client, project)?.id;
+
+        let written = summary::gather_summary(client, project_id, analysis_id, compare_to, top)
+            .and_then(|gathered| {
+                let rendered = match format {
+                    "markdown" => summary::render_markdown(&gathered, max_length),
+                    "json" => serde_json::to_string_pretty(&SummaryOutput::from(&gathered)).unwrap() + "\n",
+                    _ => summary::render_html(&gathered),
+                };
+                let write_result = match output_file {
+                    Some(path) => File::create(path).and_then(|mut file| file.write_all(rendered.as_bytes())),
+                    None => io::stdout().write_all(rendered.as_bytes()),
+                };
+                write_result.map_err(ApiError::from)
+            });
+
+        match written {
+            Err(e) => {
+                client.report_error("generating summary", &e);
+                Err(Exit(1))
+            },
+            Ok(()) => {
+                if let Some(path) = output_file {
+                    client.progress(&format!("Wrote summary to {}", path.display()));
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: standards
+// -------------------------------------------------------------------------------------------------
+pub struct StandardsCommand;
+pub struct StandardsCommandArgs<'a> {
+    project: &'a str,
+    standard: Standard,
+    severities: Option<Vec<&'static str>>,
+    format: &'a str,
+}
+impl <'a> StandardsCommand {
+    fn inner_parse(&self, standards_args: &'a ArgMatches) -> Result<StandardsCommandArgs<'a>, &'a str> {
+        let project = standards_args.value_of("project").ok_or("project id missing")?;
+        let standard = parse_optional(standards_args, "standard", "standard should be owasp2021 or cwe-top25")?
+            .unwrap_or(Standard::Owasp2021);
+        let severities = match standards_args.value_of("min-severity") {
+            Some(floor) => Some(standards::severities_at_least(floor).ok_or("min-severity should be a severity, e.g. high")?),
+            None => None,
+        };
+        let format = standards_args.value_of("output").unwrap_or("table");
+        Ok(StandardsCommandArgs { project, standard, severities, format })
+    }
+}
+impl <'a> CommandInner<'a> for StandardsCommand {
+    type Args = StandardsCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("standards")
+            .about("Count a project's open findings in each category of a security standard")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("standard")
+                .long("standard")
+                .value_name("STANDARD")
+                .help("Which standard to use: owasp2021 (default) or cwe-top25")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("min-severity")
+                .long("min-severity")
+                .value_name("SEVERITY")
+                .help("Only count findings of this severity or worse")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json", "csv"])
+                .default_value("table")
+                .help("Format of the counts")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        if let Some(standards_args) = matches.subcommand_matches("standards") {
+            Some(self.inner_parse(standards_args))
+        } else {
+            None
+        }
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let StandardsCommandArgs { project, standard, severities, format } = args;
+        let project_id = resolve_project(client, project)?.id;
+        let severities = severities.as_ref().map(|list| list.as_slice());
+
+        match standards::gather_coverage(client, project_id, standard, severities) {
+            Err(e) => {
+                client.report_error("counting findings by standard", &e);
+                Err(Exit(1))
+            },
+            Ok(coverage) => {
+                match format {
+                    "json" => println!("{}", serde_json::to_string(&StandardsOutput::new(project_id, severities, &coverage)).unwrap()),
+                    "csv" => print!("{}", coverage.render_csv()),
+                    _ => {
+                        println!("# {}", standard.title());
+                        print!("{}", coverage.render_table());
+                    },
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: trend
+// -------------------------------------------------------------------------------------------------
+pub struct TrendCommand;
+pub struct TrendCommandArgs<'a> {
+    project: &'a str,
+    window: usize,
+}
+impl <'a> CommandInner<'a> for TrendCommand {
+    type Args = TrendCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("trend")
+            .about("Show how a project's severity totals changed over its recent analyses")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .value_name("PROJECT")
+                .help("The project's id or (exact) name")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("window")
+                .long("window")
+                .value_name("N")
+                .help("How many of the most recent completed analyses to include (default 5)")
+                .takes_value(true)
+                .required(false)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("trend").map(|trend_args| {
+            let project = trend_args.value_of("project").ok_or("project id missing")?;
+            let window: usize = parse_optional(trend_args, "window", "window should be a number")?.unwrap_or(5);
+            if window == 0 {
+                Err("window should be at least 1")
+            } else {
+                Ok(TrendCommandArgs { project, window })
+            }
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let TrendCommandArgs { project, window } = args;
+        let project_id = resolve_project(client, project)?.id;
+
+        match trend::gather_trend(client, project_id, window) {
+            Err(e) => {
+                client.report_error("loading severity trend", &e);
+                Err(Exit(1))
+            },
+            Ok(t) => {
+                for analysis in t.skipped.iter() {
+                    let state = analysis.state.as_ref().map(|s| s.as_str()).unwrap_or("unfinished");
+                    println!("# Skipped analysis {} ({})", analysis.id, state);
+                }
+                match t.points.len() {
+                    0 => println!("# No completed analyses"),
+                    1 => {
+                        println!("# Only one completed analysis; nothing to compare it to");
+                        print!("{}", t.render_table());
+                    },
+                    _ => {
+                        print!("{}", t.render_table());
+                        println!("# Since the previous analysis: {}", t.delta_line());
+                    },
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: report
+// -------------------------------------------------------------------------------------------------
+pub struct ReportCommand;
+pub struct ReportCommandArgs<'a> {
+    kind: ReportKind,
+    project: &'a str,
+    options: ReportOptions,
+    output: ReportOutput<'a>,
+}
+/// Where the `report` command saves the report.
+enum ReportOutput<'a> {
+    File(&'a Path),
+    /// A directory, to save the report in under the file name the server suggests.
+    Dir(&'a Path),
+}
+impl <'a> ReportCommand {
+    fn inner_parse(&self, report_args: &'a ArgMatches) -> Result<ReportCommandArgs<'a>, &'a str> {
+        let (kind, kind_args) = match report_args.subcommand() {
+            ("pdf", Some(pdf_args)) => (ReportKind::Pdf, pdf_args),
+            ("csv", Some(csv_args)) => (ReportKind::Csv, csv_args),
+            ("xml", Some(xml_args)) => (ReportKind::Xml, xml_args),
+            _ => return Err("which kind of report? (pdf, csv, or xml)"),
+        };
+        let project = kind_args.value_of("project").ok_or("project id missing")?;
+        let output = match (kind_args.value_of("output-file"), kind_args.value_of("output-dir")) {
+            (Some(file), _) => ReportOutput::File(Path::new(file)),
+            (None, Some(dir)) if Path::new(dir).is_dir() => ReportOutput::Dir(Path::new(dir)),
+            (None, Some(_)) => return Err("output-dir should be an existing directory"),
+            (None, None) => return Err("output file missing"),
+        };
+        let filter = match kind_args.value_of("filter") {
+            Some(raw) => Some(serde_json::from_str(raw).map_err(|_| "filter should be a JSON object")?),
+            None => None,
+        };
+        let summary_mode = match kind_args.value_of("summary-mode") {
+            Some("detailed") => PdfSummaryMode::Detailed,
+            _ => PdfSummaryMode::Simple,
+        };
+        let columns: Vec<String> = kind_args.value_of("columns")
+            .map(|raw| raw.split(',').map(|column| column.trim()).filter(|column| !column.is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+        if kind == ReportKind::Csv && columns.is_empty() {
+            return Err("columns should be a comma-separated list, e.g. severity,status,cwe,location");
+        }
+        let options = ReportOptions {
+            filter,
+            summary_mode,
+            include_standards: kind_args.is_present("include-standards"),
+            include_source: kind_args.is_present("include-source"),
+            columns,
+        };
+        Ok(ReportCommandArgs { kind, project, options, output })
+    }
+}
+impl <'a> CommandInner<'a> for ReportCommand {
+    type Args = ReportCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("report")
+            .about("Generate a report of a project's findings, and save it to a file")
+            .subcommand(report_subcommand("pdf", "A PDF report, like the ones from the Code Dx UI")
+                .arg(Arg::with_name("summary-mode")
+                    .long("summary-mode")
+                    .value_name("MODE")
+                    .possible_values(&["simple", "detailed"])
+                    .default_value("simple")
+                    .help("How much detail the summary section goes into")
+                    .takes_value(true)
+                )
+                .arg(Arg::with_name("include-standards")
+                    .long("include-standards")
+                    .help("Include the sections on security standards")
+                    .takes_value(false)
+                )
+                .arg(Arg::with_name("include-source")
+                    .long("include-source")
+                    .help("Include the source code around each finding")
+                    .takes_value(false)
+                )
+            )
+            .subcommand(report_subcommand("csv", "A CSV export of the findings, with the columns you pick")
+                .arg(Arg::with_name("columns")
+                    .long("columns")
+                    .value_name("COLUMNS")
+                    .help("Comma-separated columns to export, e.g. severity,status,cwe,location")
+                    .takes_value(true)
+                    .required(true)
+                )
+            )
+            .subcommand(report_subcommand("xml", "An XML report, for importing into other tools")
+                .arg(Arg::with_name("include-standards")
+                    .long("include-standards")
+                    .help("Include each finding's security standards")
+                    .takes_value(false)
+                )
+                .arg(Arg::with_name("include-source")
+                    .long("include-source")
+                    .help("Include the source code around each finding")
+                    .takes_value(false)
+                )
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        if let Some(report_args) = matches.subcommand_matches("report") {
+            Some(self.inner_parse(report_args))
+        } else {
+            None
+        }
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let ReportCommandArgs { kind, project, options, output } = args;
+        let project_id = resolve_project(client, project)?.id;
+        let poll = Observed::new(Duration::from_secs(2), ShowProgress(client));
+        let result = match output {
+            ReportOutput::File(path) => client.run_report(project_id, kind, options, path, poll),
+            ReportOutput::Dir(dir) => client.run_report_to_dir(project_id, kind, options, dir, poll),
+        };
+        match result {
+            Err(e) => {
+                client.report_error(&format!("generating the {} report", kind.name()), &e);
+                Err(Exit(1))
+            },
+            Ok(path) => {
+                client.progress(&format!("Wrote the {} report to {}", kind.name(), path.display()));
+                Ok(())
+            },
+        }
+    }
+}
+
+/// A `report` subcommand, with the arguments every kind of report takes.
+fn report_subcommand(name: &'static str, about: &'static str) -> App<'static, 'static> {
+    SubCommand::with_name(name)
+        .about(about)
+        .arg(Arg::with_name("project")
+            .long("project")
+            .value_name("PROJECT")
+            .help("The project's id or (exact) name")
+            .takes_value(true)
+            .required(true)
+        )
+        .arg(Arg::with_name("output-file")
+            .short("o")
+            .long("output-file")
+            .value_name("FILE")
+            .help("Where to save the report")
+            .takes_value(true)
+            .required_unless("output-dir")
+        )
+        .arg(Arg::with_name("output-dir")
+            .long("output-dir")
+            .value_name("DIR")
+            .help("Save the report in DIR, under the file name the server suggests")
+            .takes_value(true)
+            .conflicts_with("output-file")
+        )
+        .arg(Arg::with_name("filter")
+            .long("filter")
+            .value_name("JSON")
+            .help("Only report on the findings matching this filter (see the Code Dx API guide for its format)")
+            .takes_value(true)
+            .required(false)
+        )
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: server-info
+// -------------------------------------------------------------------------------------------------
+pub struct ServerInfoCommand;
+impl <'a> CommandInner<'a> for ServerInfoCommand {
+    type Args = ();
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("server-info")
+            .about("Print the Code Dx server's version")
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("server-info").map(|_| Ok(()))
+    }
+
+    fn run(&self, client: &ApiClient, _args: Self::Args) -> CommandResult {
+        match client.get_system_info() {
+            Err(e) => {
+                client.report_error("getting the server's version", &e);
+                Err(Exit(1))
+            },
+            Ok(info) => {
+                println!("version: {}", if info.version.is_empty() { "(unknown)" } else { info.version.as_str() });
+                if let Some(date) = info.date {
+                    println!("date: {}", date);
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: check
+// -------------------------------------------------------------------------------------------------
+pub struct CheckCommand;
+impl <'a> CommandInner<'a> for CheckCommand {
+    type Args = ();
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("check")
+            .about("Check that the server can be reached, and accepts the credentials")
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("check").map(|_| Ok(()))
+    }
+
+    fn run(&self, client: &ApiClient, _args: Self::Args) -> CommandResult {
+        match client.check_connection() {
+            Err(e) => {
+                let problem = ConnectionProblem::of(&e);
+                println!("FAILED: {}", problem.diagnosis());
+                println!("Proxy: {}", client.proxy_decision());
+                client.report_error("checking the connection", &e);
+                Err(Exit(problem.exit_code()))
+            },
+            Ok(report) => {
+                let version = report.server_version.map(|v| format!("Code Dx {}, ", v)).unwrap_or_default();
+                println!("OK: {} ({}{}ms)", report.base_url, version, report.elapsed.as_secs() * 1000 + u64::from(report.elapsed.subsec_nanos() / 1_000_000));
+                println!("Proxy: {}", client.proxy_decision());
+                Ok(())
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: login
+// -------------------------------------------------------------------------------------------------
+pub struct LoginCommand;
+impl <'a> CommandInner<'a> for LoginCommand {
+    type Args = ();
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("login")
+            .about("Check a username and password with the server, and store the password in the OS keyring for later commands")
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("login").map(|_| Ok(()))
+    }
+
+    fn run(&self, client: &ApiClient, _args: Self::Args) -> CommandResult {
+        let config = client.get_config();
+        let (username, password) = match config.auth_info {
+            ClientAuth::Basic { ref username, ref password } => (username, password),
+            ClientAuth::ApiKey(_) => {
+                client.report_failure("invalid_inputs", "login stores a password; an API key can be given with CODEDX_API_KEY instead");
+                return Err(Exit(1));
+            },
+        };
+        if let Err(e) = client.check_connection() {
+            let problem = ConnectionProblem::of(&e);
+            client.report_error("checking the credentials", &e);
+            return Err(Exit(problem.exit_code()));
+        }
+        let entry = KeyringEntry::new(&config.base_url, username);
+        match entry.set_password(password) {
+            Ok(()) => {
+                client.progress(&format!("Stored the password for {} at {} in the OS keyring", entry.username, entry.server));
+                Ok(())
+            },
+            Err(e) => {
+                client.report_other_error("keyring", "storing the password", &format!("{}; set CODEDX_PASSWORD or use --password-stdin instead", e));
+                Err(Exit(1))
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: logout
+// -------------------------------------------------------------------------------------------------
+pub struct LogoutCommand;
+impl <'a> CommandInner<'a> for LogoutCommand {
+    type Args = ();
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("logout")
+            .about("Remove the password that login stored in the OS keyring")
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("logout").map(|_| Ok(()))
+    }
+
+    fn run(&self, client: &ApiClient, _args: Self::Args) -> CommandResult {
+        let config = client.get_config();
+        let username = match config.auth_info {
+            ClientAuth::Basic { ref username, .. } => username,
+            ClientAuth::ApiKey(_) => {
+                client.report_failure("invalid_inputs", "logout needs the username whose password was stored");
+                return Err(Exit(1));
+            },
+        };
+        let entry = KeyringEntry::new(&config.base_url, username);
+        match entry.delete_password() {
+            Ok(()) => {
+                client.progress(&format!("Removed the password for {} at {} from the OS keyring", entry.username, entry.server));
+                Ok(())
+            },
+            Err(KeyringError::NotFound) => {
+                client.progress(&format!("There's no password for {} at {} in the OS keyring", entry.username, entry.server));
+                Ok(())
+            },
+            Err(e) => {
+                client.report_other_error("keyring", "removing the password", &e);
+                Err(Exit(1))
+            },
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: wait
+// -------------------------------------------------------------------------------------------------
+pub struct WaitCommand;
+pub struct WaitCommandArgs<'a> {
+    job_ids: Vec<&'a str>,
+    poll: BoundedPoll<Option<PollInterval>>,
+}
+impl <'a> CommandInner<'a> for WaitCommand {
+    type Args = WaitCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("wait")
+            .about("Wait for some jobs (e.g. analyses started elsewhere) to finish")
+            .arg(Arg::with_name("job")
+                .long("job")
+                .value_name("ID")
+                .help("A job to wait for; may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+            )
+            .arg(Arg::with_name("poll")
+                .long("poll")
+                .value_name("INTERVAL")
+                .help("How long to wait between rounds of status checks: a fixed interval like 2s (the default), or a growing one like backoff:5s..2m")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("DURATION")
+                .help("Stop waiting after this long (e.g. 30m), and exit with code 3")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("max-polls")
+                .long("max-polls")
+                .value_name("N")
+                .help("Stop waiting after checking the jobs N times, and exit with code 3")
+                .takes_value(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("wait").map(|wait_args| {
+            let mut job_ids: Vec<&'a str> = Vec::new();
+            for job_id in wait_args.values_of("job").into_iter().flat_map(|values| values) {
+                if !job_ids.contains(&job_id) {
+                    job_ids.push(job_id);
+                }
+            }
+            let poll = parse_poll(wait_args)?;
+            Ok(WaitCommandArgs { job_ids, poll })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let WaitCommandArgs { job_ids, poll } = args;
+        let (finished, failed, gave_up) = match client.poll_jobs_completion(&job_ids, Observed::new(poll.with_default(client.poll_interval()), ShowProgress(client))) {
+            Ok(finished) => (finished, HashMap::new(), HashMap::new()),
+            Err(ApiError::JobsUnfinished { finished, failed, gave_up }) => (finished, failed, gave_up),
+            Err(e) => {
+                client.report_error("waiting for the jobs", &e);
+                return Err(Exit(1));
+            },
+        };
+        let width = cmp::max(job_ids.iter().map(|id| id.len()).max().unwrap_or(0), "Job".len());
+        println!("{:<width$}  {}", "Job", "Outcome", width = width);
+        for job_id in job_ids.iter() {
+            let outcome = match (finished.get(*job_id), failed.get(*job_id), gave_up.get(*job_id)) {
+                (Some(status), _, _) | (_, _, Some(status)) => format!("{:?}", status).to_lowercase(),
+                (_, Some(&ApiError::JobNotFound(_)), _) => "error: not found (it may have expired)".to_string(),
+                (_, Some(e), _) => format!("error: {}", e),
+                (None, None, None) => "unknown".to_string(),
+            };
+            println!("{:<width$}  {}", job_id, outcome, width = width);
+        }
+        let unsuccessful = finished.values().filter(|status| !status.is_success()).count() + failed.len();
+        let total = job_ids.len();
+        if unsuccessful + gave_up.len() == 0 {
+            Ok(())
+        } else if unsuccessful == 0 {
+            client.report_failure("timed_out", &format!("gave up waiting for {} of {} jobs", gave_up.len(), total));
+            Err(Exit(polling::EXIT_TIMED_OUT))
+        } else {
+            client.report_failure("job_failed", &format!("{} of {} jobs didn't complete", unsuccessful + gave_up.len(), total));
+            Err(Exit(1))
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: job-status
+// -------------------------------------------------------------------------------------------------
+pub struct JobStatusCommand;
+pub struct JobStatusCommandArgs<'a> {
+    job_id: &'a str,
+    wait: bool,
+    format: Option<OutputFormat>,
+    poll: BoundedPoll<Option<PollInterval>>,
+    poll_output: PollOutput,
+}
+impl <'a> CommandInner<'a> for JobStatusCommand {
+    type Args = JobStatusCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("job-status")
+            .about("Show the status of a job, e.g. an analysis started by an earlier pipeline stage")
+            .arg(Arg::with_name("job")
+                .long("job")
+                .value_name("ID")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("wait")
+                .long("wait")
+                .help("Wait for the job to finish, and show its final status")
+                .takes_value(false)
+            )
+            .arg(Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json"])
+                .help("Format of the status (default: the global --format, or table)")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("poll")
+                .long("poll")
+                .value_name("INTERVAL")
+                .help("With --wait, how long to wait between status checks: a fixed interval like 2s (the default), or a growing one like backoff:5s..2m")
+                .takes_value(true)
+                .requires("wait")
+            )
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("DURATION")
+                .help("With --wait, stop waiting after this long (e.g. 30m), and exit with code 3")
+                .takes_value(true)
+                .requires("wait")
+            )
+            .arg(Arg::with_name("max-polls")
+                .long("max-polls")
+                .value_name("N")
+                .help("With --wait, stop waiting after checking the job N times, and exit with code 3")
+                .takes_value(true)
+                .requires("wait")
+            )
+            .arg(Arg::with_name("poll-output")
+                .long("poll-output")
+                .value_name("FORMAT")
+                .possible_values(&["human", "jsonl"])
+                .help("With --wait, how to show the job's progress: messages on STDERR (human, the default), or one JSON object per status check on STDOUT (jsonl), the last of which replaces the usual output")
+                .takes_value(true)
+                .requires("wait")
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("job-status").map(|status_args| {
+            let job_id = status_args.value_of("job").ok_or("job id missing")?;
+            let wait = status_args.is_present("wait");
+            let format = parse_optional(status_args, "output", "unknown output format")?;
+            let poll = parse_poll(status_args)?;
+            let poll_output = parse_optional(status_args, "poll-output", "poll-output should be human or jsonl")?.unwrap_or_default();
+            Ok(JobStatusCommandArgs { job_id, wait, format, poll, poll_output })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let JobStatusCommandArgs { job_id, wait, format, poll, poll_output } = args;
+        let progress = CommandProgress::new(client, poll_output, Some(job_id));
+        let result = if wait {
+            client.poll_job_completion_detailed(job_id, Observed::new(poll.with_default(client.poll_interval()), progress.clone()))
+        } else {
+            client.get_job_status_detailed(job_id)
+        };
+        let job = match result {
+            Ok(job) => job,
+            Err(e) => {
+                progress.fail(&e);
+                // a JobNotFound says plainly that the job's record has probably expired
+                client.report_error(&format!("checking the status of job {}", job_id), &e);
+                return Err(Exit(1));
+            },
+        };
+        match (poll_output, format.or(client.output_format()).unwrap_or(OutputFormat::Table)) {
+            (PollOutput::JsonLines, _) => progress.finish(job.status, job.progress.as_ref().and_then(|p| p.percent)),
+            (_, OutputFormat::Json) => println!("{}", serde_json::to_string(&JobStatusOutput::from(&job)).unwrap()),
+            _ => println!("{}", job.describe()),
+        }
+        // the exit code tells scripts how the job ended without parsing anything
+        if wait && !job.status.is_ready() {
+            client.report_failure("timed_out", &format!("gave up waiting for job {}", job_id));
+        }
+        match polling::job_exit_code(job.status, wait) {
+            0 => Ok(()),
+            code => Err(Exit(code)),
+        }
+    }
+}
+
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: cancel
+// -------------------------------------------------------------------------------------------------
+pub struct CancelCommand;
+pub struct CancelCommandArgs<'a> {
+    job_ids: Vec<&'a str>,
+}
+impl <'a> CommandInner<'a> for CancelCommand {
+    type Args = CancelCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("cancel")
+            .about("Cancel some jobs, e.g. analyses that are no longer needed")
+            .arg(Arg::with_name("job")
+                .long("job")
+                .value_name("ID")
+                .help("A job to cancel; may be repeated. A job that already finished is left as it is")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("cancel").map(|cancel_args| {
+            let job_ids = cancel_args.values_of("job").map(|values| values.collect()).unwrap_or_default();
+            Ok(CancelCommandArgs { job_ids })
+        })
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let mut failed = false;
+        for job_id in args.job_ids {
+            match client.cancel_job(job_id) {
+                Ok(()) => client.progress(&format!("Cancelled job {}", job_id)),
+                Err(e) => {
+                    client.report_error(&format!("cancelling job {}", job_id), &e);
+                    failed = true;
+                },
+            }
+        }
+        if failed { Err(Exit(1)) } else { Ok(()) }
+    }
+}
@@ -0,0 +1,201 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Failing a build when an analysis has too many findings of some severity (`analyze --fail-on`).
+//!
+//! A gate is a list of thresholds like `critical:0,high:5`: the most findings of each severity
+//! that an analysis may have before the gate fails. Only the findings present in the analysis
+//! are counted, and optionally only the ones whose status is still `new`.
+
+use client::*;
+use serde_json;
+use std::fmt::Write;
+use std::str::FromStr;
+use summary::{self, SEVERITIES};
+
+/// Exit code for the CLI when an analysis completed, but had more findings than a `--fail-on` threshold allows,
+/// as opposed to `1` for a failed analysis (or request) and `polling::EXIT_TIMED_OUT` for giving up on one.
+pub const EXIT_GATE_FAILED: i32 = 4;
+
+/// The most findings of one severity that a gate allows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Threshold {
+    /// The severity, as named in `summary::SEVERITIES`.
+    pub severity: &'static str,
+    pub max: u64,
+}
+
+/// A set of thresholds, at most one per severity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeverityGate {
+    pub thresholds: Vec<Threshold>,
+    /// Only count findings whose status is `new`, so that findings someone has already triaged don't fail the build.
+    pub new_only: bool,
+}
+
+impl FromStr for SeverityGate {
+    type Err = String;
+
+    /// Parse comma-separated `severity:max` pairs, e.g. `critical:0,high:5` (severities are case-insensitive).
+    fn from_str(s: &str) -> Result<SeverityGate, String> {
+        let mut thresholds: Vec<Threshold> = Vec::new();
+        for part in s.split(',').map(|part| part.trim()).filter(|part| !part.is_empty()) {
+            let mut halves = part.splitn(2, ':');
+            let name = halves.next().unwrap_or("").trim();
+            let max = halves.next().ok_or_else(|| format!("\"{}\" should look like SEVERITY:MAX, e.g. high:5", part))?;
+            let severity = SEVERITIES.iter().cloned().find(|s| s.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("\"{}\" isn't a severity (the severities are {})", name, SEVERITIES.join(", ")))?;
+            let max = max.trim().parse().map_err(|_| format!("the most {} findings allowed should be a number, not \"{}\"", severity, max.trim()))?;
+            if thresholds.iter().any(|t| t.severity == severity) {
+                return Err(format!("{} is given more than once", severity));
+            }
+            thresholds.push(Threshold { severity, max });
+        }
+        if thresholds.is_empty() {
+            return Err("no thresholds given".to_string());
+        }
+        Ok(SeverityGate { thresholds, new_only: false })
+    }
+}
+
+impl SeverityGate {
+    /// The filter for the findings the gate counts: the ones in `analysis_id`, of the severities with a threshold.
+    pub fn filter(&self, analysis_id: u32) -> serde_json::Value {
+        let mut filter = analysis_filter(analysis_id);
+        filter["severity"] = json!(self.thresholds.iter().map(|t| t.severity).collect::<Vec<_>>());
+        if self.new_only {
+            filter["status"] = json!(["new"]);
+        }
+        filter
+    }
+
+    /// Compare severity counts (as from `ApiClient::get_finding_counts`) against the thresholds.
+    pub fn evaluate(&self, counts: &[GroupedCount]) -> GateReport {
+        GateReport {
+            rows: self.thresholds.iter().map(|t| GateRow {
+                severity: t.severity,
+                count: summary::count_for(counts, t.severity),
+                max: t.max,
+            }).collect(),
+        }
+    }
+
+    /// Count the findings in a (completed) analysis, and compare them against the thresholds.
+    pub fn check(&self, client: &ApiClient, project_id: u32, analysis_id: u32) -> ApiResult<GateReport> {
+        let counts = client.get_finding_counts(project_id, CountGroup::Severity, Some(self.filter(analysis_id)))?;
+        Ok(self.evaluate(&counts))
+    }
+}
+
+/// How one severity's count compared to its threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GateRow {
+    pub severity: &'static str,
+    pub count: u64,
+    pub max: u64,
+}
+
+impl GateRow {
+    pub fn exceeded(&self) -> bool {
+        self.count > self.max
+    }
+}
+
+/// The outcome of `SeverityGate::check`, with one row per threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GateReport {
+    pub rows: Vec<GateRow>,
+}
+
+impl GateReport {
+    /// The thresholds that were exceeded.
+    pub fn exceeded(&self) -> Vec<&GateRow> {
+        self.rows.iter().filter(|row| row.exceeded()).collect()
+    }
+
+    /// `0` if every count is within its threshold, otherwise `EXIT_GATE_FAILED`.
+    pub fn exit_code(&self) -> i32 {
+        if self.exceeded().is_empty() { 0 } else { EXIT_GATE_FAILED }
+    }
+
+    /// A table of the thresholds that were exceeded: each severity, its count, and the most allowed.
+    pub fn render_exceeded(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "{:<12} {:>6}  {}", "Severity", "Count", "Allowed").unwrap();
+        for row in self.exceeded() {
+            writeln!(out, "{:<12} {:>6}  {}", row.severity, row.count, row.max).unwrap();
+        }
+        out
+    }
+}
+
+#[test]
+fn test_parse_gate(){
+    let gate: SeverityGate = "critical:0, High:5".parse().unwrap();
+    assert_eq!(gate.thresholds, vec![
+        Threshold { severity: "Critical", max: 0 },
+        Threshold { severity: "High", max: 5 },
+    ]);
+    assert!(!gate.new_only);
+
+    assert!("".parse::<SeverityGate>().unwrap_err().contains("no thresholds"));
+    assert!("critical".parse::<SeverityGate>().unwrap_err().contains("SEVERITY:MAX"));
+    assert!("severe:1".parse::<SeverityGate>().unwrap_err().contains("isn't a severity"));
+    assert!("high:lots".parse::<SeverityGate>().unwrap_err().contains("should be a number"));
+    assert!("high:1,HIGH:2".parse::<SeverityGate>().unwrap_err().contains("more than once"));
+}
+
+#[test]
+fn test_gate_filter(){
+    let mut gate: SeverityGate = "critical:0,high:5".parse().unwrap();
+    assert_eq!(gate.filter(12), json!({ "analysis": 12, "severity": ["Critical", "High"] }));
+    gate.new_only = true;
+    assert_eq!(gate.filter(12), json!({ "analysis": 12, "severity": ["Critical", "High"], "status": ["new"] }));
+}
+
+#[test]
+fn test_gate_exit_code(){
+    let gate: SeverityGate = "critical:0,high:5".parse().unwrap();
+    let counts = |critical, high| vec![
+        GroupedCount { name: "Critical".to_string(), count: critical },
+        GroupedCount { name: "High".to_string(), count: high },
+    ];
+    assert_eq!(gate.evaluate(&counts(0, 5)).exit_code(), 0);
+    assert_eq!(gate.evaluate(&[]).exit_code(), 0);
+
+    let report = gate.evaluate(&counts(2, 5));
+    assert_eq!(report.exit_code(), EXIT_GATE_FAILED);
+    assert_eq!(report.exceeded(), vec![&GateRow { severity: "Critical", count: 2, max: 0 }]);
+    assert_eq!(report.render_exceeded(), "Severity      Count  Allowed\nCritical          2  0\n");
+}
+
+#[test]
+fn test_gate_check(){
+    use test_server::*;
+    let server = TestServer::start(|req| match req.path.as_str() {
+        "/codedx/api/projects/5/findings/grouped-counts" => TestResponse::json(200, json!([
+            { "name": "High", "count": 6 },
+        ])),
+        _ => TestResponse::status(404),
+    });
+    let gate: SeverityGate = "critical:0,high:5".parse().unwrap();
+    let report = gate.check(&server.client(), 5, 12).unwrap();
+    assert_eq!(report.exceeded(), vec![&GateRow { severity: "High", count: 6, max: 5 }]);
+    assert_eq!(server.requests()[0].json(), json!({
+        "filter": { "analysis": 12, "severity": ["Critical", "High"] },
+        "countBy": "severity",
+    }));
+}
@@ -0,0 +1,363 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Expanding glob patterns like `target/*.jar` in the files given to `analyze`.
+//!
+//! Patterns are expanded here instead of by a shell, so that they work the same way on Windows.
+//! Within one path component, `*` matches any run of characters, `?` matches any one character,
+//! and `[abc]`, `[a-z]` or `[!abc]` match one character from (or not from) a set. A component
+//! that's just `**` matches any number of directories. As in a shell, `*` and `?` don't match
+//! the `.` at the start of a hidden file's name.
+//!
+//! A path that exists is never treated as a pattern, even if it has `*`, `?` or `[` in it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A file found by `expand_inputs`, and the argument (a path or a pattern) that found it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchedFile<'s> {
+    pub path: PathBuf,
+    pub source: &'s str,
+}
+
+impl <'s> MatchedFile<'s> {
+    /// Whether the file was given as itself, rather than matched by a pattern.
+    pub fn is_literal(&self) -> bool {
+        self.path == Path::new(self.source)
+    }
+}
+
+/// Whether `s` has any of the characters that make it a pattern.
+pub fn is_pattern(s: &str) -> bool {
+    s.contains(|c: char| c == '*' || c == '?' || c == '[')
+}
+
+/// Turn a list of paths and patterns into a list of files.
+///
+/// Paths are passed through untouched (whether or not they exist; uploading a file that doesn't
+/// will fail with a clearer error than this could give). Each pattern is replaced by the files it
+/// matches, in order by name, and a file matched more than once is only listed the first time.
+///
+/// Fails if a pattern matches no files, unless `allow_empty` is set.
+pub fn expand_inputs<'s>(args: &[&'s str], allow_empty: bool) -> Result<Vec<MatchedFile<'s>>, String> {
+    let mut files: Vec<MatchedFile<'s>> = Vec::new();
+    let mut seen: Vec<PathBuf> = Vec::new();
+    for &arg in args {
+        let paths = if Path::new(arg).exists() || !is_pattern(arg) {
+            vec![PathBuf::from(arg)]
+        } else {
+            let matched = expand(arg).map_err(|e| format!("couldn't expand \"{}\": {}", arg, e))?;
+            if matched.is_empty() && !allow_empty {
+                return Err(format!("no files match \"{}\"", arg));
+            }
+            matched
+        };
+        for path in paths {
+            let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            if !seen.contains(&key) {
+                seen.push(key);
+                files.push(MatchedFile { path, source: arg });
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// The files matching a pattern, in order by path.
+pub fn expand(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    // start from the directories before the first component with a pattern in it,
+    // which keeps a root, a drive letter or a `..` just as they were given
+    let components: Vec<&str> = pattern.split(|c: char| c == '/' || c == '\\').collect();
+    let first_pattern = components.iter().position(|c| is_pattern(c)).unwrap_or(components.len());
+    let base = match components[..first_pattern].join("/") {
+        ref base if base.is_empty() && first_pattern > 0 => PathBuf::from("/"),
+        base => PathBuf::from(base),
+    };
+    let mut candidates = vec![base];
+    for &component in components[first_pattern..].iter().filter(|c| !c.is_empty()) {
+        let mut next = Vec::new();
+        for dir in candidates {
+            if component == "**" {
+                next.push(dir.clone());
+                descendant_dirs(&dir, &mut next)?;
+            } else if is_pattern(component) {
+                for name in dir_entries(&dir)? {
+                    if matches_component(component, &name) {
+                        next.push(dir.join(name));
+                    }
+                }
+            } else {
+                next.push(dir.join(component));
+            }
+        }
+        candidates = next;
+    }
+    let mut files: Vec<PathBuf> = candidates.into_iter().filter(|path| path.is_file()).collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// The names in a directory (`""` meaning the current one); a directory that doesn't exist has none.
+fn dir_entries(dir: &Path) -> io::Result<Vec<String>> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        names.push(entry?.file_name().to_string_lossy().into_owned());
+    }
+    Ok(names)
+}
+
+/// Every directory below `dir` (except hidden ones), for `**`.
+fn descendant_dirs(dir: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    for name in dir_entries(dir)? {
+        let path = dir.join(&name);
+        if !name.starts_with('.') && path.is_dir() {
+            out.push(path.clone());
+            descendant_dirs(&path, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether a relative path (with `/` separators) matches a whole pattern, e.g. `node_modules/**`
+/// matches `node_modules` and everything in it.
+pub fn matches_path(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    matches_components(&pattern, &path)
+}
+
+fn matches_components(pattern: &[&str], path: &[&str]) -> bool {
+    wildcard_match(pattern, path, |&component| component == "**", |component, name| matches_component(component, name))
+}
+
+/// Whether a file name matches one component of a pattern.
+pub fn matches_component(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    if name.first() == Some(&'.') && pattern.first() != Some(&'.') {
+        return false;
+    }
+    let tokens = tokenize(&pattern);
+    wildcard_match(&tokens, &name, |token| *token == Token::Star, |token, &c| token.matches(c))
+}
+
+/// Whether `items` match `pattern`, where each part of the pattern that `is_star` matches any run of items,
+/// and every other part matches one item if `matches` says so.
+///
+/// On a mismatch, only the last star seen takes one more item and the rest of the pattern is tried again:
+/// an earlier star taking more can't succeed where the last one failed, so this takes at most about
+/// `pattern.len() * items.len()` steps, instead of trying every way of splitting `items` between the stars.
+fn wildcard_match<P, I, S, M>(pattern: &[P], items: &[I], is_star: S, matches: M) -> bool
+    where S: Fn(&P) -> bool, M: Fn(&P, &I) -> bool
+{
+    let (mut p, mut i) = (0, 0);
+    // just after the last star: where the rest of the pattern starts, and where in `items` it was last tried from
+    let mut backtrack: Option<(usize, usize)> = None;
+    while p < pattern.len() || i < items.len() {
+        if p < pattern.len() {
+            if is_star(&pattern[p]) {
+                p += 1;
+                backtrack = Some((p, i));
+                continue;
+            }
+            if i < items.len() && matches(&pattern[p], &items[i]) {
+                p += 1;
+                i += 1;
+                continue;
+            }
+        }
+        match backtrack {
+            Some((after_star, from)) if from < items.len() => {
+                p = after_star;
+                i = from + 1;
+                backtrack = Some((after_star, i));
+            },
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// One part of a path component's pattern.
+#[derive(PartialEq)]
+enum Token {
+    Star,
+    Any,
+    Set(CharSet),
+    Char(char),
+}
+
+impl Token {
+    /// Whether the token matches one character (never true for `Star`, which matches runs of them).
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            Token::Star => false,
+            Token::Any => true,
+            Token::Set(ref set) => set.contains(c),
+            Token::Char(expected) => c == expected,
+        }
+    }
+}
+
+fn tokenize(pattern: &[char]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = pattern;
+    while let Some(&c) = rest.first() {
+        rest = match c {
+            '*' => {
+                tokens.push(Token::Star);
+                &rest[1..]
+            },
+            '?' => {
+                tokens.push(Token::Any);
+                &rest[1..]
+            },
+            '[' => match parse_set(&rest[1..]) {
+                Some((set, after)) => {
+                    tokens.push(Token::Set(set));
+                    after
+                },
+                // a `[` that isn't closed is just a `[`
+                None => {
+                    tokens.push(Token::Char('['));
+                    &rest[1..]
+                },
+            },
+            c => {
+                tokens.push(Token::Char(c));
+                &rest[1..]
+            },
+        };
+    }
+    tokens
+}
+
+/// The characters in a `[...]` set.
+#[derive(PartialEq)]
+struct CharSet {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl CharSet {
+    fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(low, high)| low <= c && c <= high) != self.negated
+    }
+}
+
+/// Parse a set from just after its `[`, returning it and the rest of the pattern after its `]`.
+fn parse_set(pattern: &[char]) -> Option<(CharSet, &[char])> {
+    let negated = pattern.first() == Some(&'!');
+    let mut i = if negated { 1 } else { 0 };
+    let mut ranges = Vec::new();
+    // a `]` right at the start is part of the set, not its end
+    let start = i;
+    while i < pattern.len() && (pattern[i] != ']' || i == start) {
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            ranges.push((pattern[i], pattern[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((pattern[i], pattern[i]));
+            i += 1;
+        }
+    }
+    if i < pattern.len() {
+        Some((CharSet { negated, ranges }, &pattern[i + 1..]))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_matches_component(){
+    assert!(matches_component("*.jar", "app.jar"));
+    assert!(!matches_component("*.jar", ".jar"));
+    assert!(!matches_component("*.jar", "app.jar.bak"));
+    assert!(matches_component("scan-?.xml", "scan-1.xml"));
+    assert!(!matches_component("scan-?.xml", "scan-10.xml"));
+    assert!(matches_component("report[0-9].xml", "report7.xml"));
+    assert!(!matches_component("report[!0-9].xml", "report7.xml"));
+    assert!(matches_component("[]x].txt", "].txt"));
+    assert!(matches_component("a[b", "a[b"));
+    assert!(matches_component(".*", ".hidden"));
+    assert!(matches_component("*a*b", "xaxxb"));
+    assert!(!matches_component("*a*b", "xbxxa"));
+    assert!(matches_component("**", "anything"));
+}
+
+#[test]
+fn test_many_stars_match_quickly(){
+    // each of these would take exponential time if every split between the stars were tried
+    let name: String = ::std::iter::repeat('a').take(200).collect();
+    let stars: String = ::std::iter::repeat("*a").take(30).collect();
+    assert!(!matches_component(&format!("{}*b", stars), &name));
+    assert!(matches_component(&format!("{}*", stars), &name));
+    let path: String = ::std::iter::repeat("a").take(200).collect::<Vec<_>>().join("/");
+    let dirs: String = ::std::iter::repeat("**/a/").take(30).collect();
+    assert!(!matches_path(&format!("{}**/b", dirs), &path));
+    assert!(matches_path(&format!("{}**", dirs), &path));
+}
+
+#[test]
+fn test_matches_path(){
+    assert!(matches_path("node_modules/**", "node_modules"));
+    assert!(matches_path("node_modules/**", "node_modules/left-pad/index.js"));
+    assert!(!matches_path("node_modules/**", "src/node_modules"));
+    assert!(matches_path("**/*.log", "build/logs/out.log"));
+    assert!(matches_path("**/*.log", "out.log"));
+    assert!(!matches_path("src/*.js", "src/lib/a.js"));
+}
+
+#[test]
+fn test_expand_inputs(){
+    let dir = ::test_dir::TestDir::new("glob");
+    for file in &["target/b.jar", "target/a.jar", "target/readme.txt", "target/deps/c.jar", "odd[name]/scan.xml"] {
+        dir.file(file, b"x");
+    }
+    let root = dir.path().to_string_lossy().into_owned();
+    let jars = format!("{}/target/*.jar", root);
+    let all_jars = format!("{}/target/**/*.jar", root);
+    let nothing = format!("{}/target/*.war", root);
+    let literal = format!("{}/odd[name]/scan.xml", root);
+
+    let expanded = expand_inputs(&[&jars, &all_jars, &literal], false);
+    let empty = expand_inputs(&[&nothing], false);
+    let allowed = expand_inputs(&[&nothing], true);
+
+    let names: Vec<String> = expanded.unwrap().iter()
+        .map(|m| m.path.strip_prefix(&dir).unwrap().to_string_lossy().replace('\\', "/"))
+        .collect();
+    assert_eq!(names, vec!["target/a.jar", "target/b.jar", "target/deps/c.jar", "odd[name]/scan.xml"]);
+    assert_eq!(empty.unwrap_err(), format!("no files match \"{}\"", nothing));
+    assert!(allowed.unwrap().is_empty());
+}
+
+#[test]
+fn test_literal_paths_pass_through(){
+    let files = expand_inputs(&["does/not/exist.zip", "also-missing.xml", "does/not/exist.zip"], false).unwrap();
+    assert_eq!(files, vec![
+        MatchedFile { path: PathBuf::from("does/not/exist.zip"), source: "does/not/exist.zip" },
+        MatchedFile { path: PathBuf::from("also-missing.xml"), source: "also-missing.xml" },
+    ]);
+    assert!(files[0].is_literal());
+}
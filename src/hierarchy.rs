@@ -0,0 +1,177 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The project hierarchy (e.g. org → team → app), built from each project's `parent_id`.
+//!
+//! This works on whatever list of projects it's given, which may not include every project's parent
+//! (e.g. the result of a filtered query). A project whose parent isn't in the list is shown at the root,
+//! marked as an orphan, rather than left out. The server shouldn't allow cycles, but if the list has one
+//! anyway, the cycle is broken at its lowest project id, which is put at the root and marked as such.
+
+use client::ApiProject;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// A project and the projects under it.
+#[derive(Debug)]
+pub struct ProjectNode<'a> {
+    pub project: &'a ApiProject,
+    /// Whether the project is at the root even though it has a parent, because its parent isn't
+    /// in the list (or is part of a cycle).
+    pub orphan: bool,
+    /// Whether the project is at the root to break a cycle: its parent is somewhere under it.
+    pub breaks_cycle: bool,
+    pub children: Vec<ProjectNode<'a>>,
+}
+
+/// Arrange `projects` as a forest. Projects keep the order they're given in, among their siblings.
+pub fn build_tree(projects: &[ApiProject]) -> Vec<ProjectNode> {
+    let ids: HashSet<u32> = projects.iter().map(|p| p.id).collect();
+    let mut placed = HashSet::new();
+    let mut roots = Vec::new();
+    for project in projects {
+        match project.parent_id {
+            Some(parent_id) if ids.contains(&parent_id) => (),
+            _ => {
+                let orphan = project.parent_id.is_some();
+                roots.push(build_node(projects, project, orphan, &mut placed));
+            },
+        }
+    }
+    // whatever isn't under a root is in a cycle
+    loop {
+        let next = projects.iter().filter(|p| !placed.contains(&p.id)).min_by_key(|p| p.id);
+        match next {
+            Some(project) => {
+                let mut node = build_node(projects, project, true, &mut placed);
+                node.breaks_cycle = true;
+                roots.push(node);
+            },
+            None => return roots,
+        }
+    }
+}
+
+fn build_node<'a>(projects: &'a [ApiProject], project: &'a ApiProject, orphan: bool, placed: &mut HashSet<u32>) -> ProjectNode<'a> {
+    placed.insert(project.id);
+    let mut children = Vec::new();
+    for child in projects.iter().filter(|p| p.parent_id == Some(project.id)) {
+        if !placed.contains(&child.id) {
+            children.push(build_node(projects, child, false, placed));
+        }
+    }
+    ProjectNode { project, orphan, breaks_cycle: false, children }
+}
+
+/// The projects under `parent_id`: just its children, or (with `recursive`) their children too, and so on.
+/// Each project appears once, even if the list has a cycle.
+pub fn descendants(projects: &[ApiProject], parent_id: u32, recursive: bool) -> Vec<&ApiProject> {
+    let mut found: Vec<&ApiProject> = Vec::new();
+    let mut seen = HashSet::new();
+    seen.insert(parent_id);
+    let mut next = 0;
+    let mut parents = vec![parent_id];
+    while next < parents.len() {
+        let parent = parents[next];
+        next += 1;
+        for child in projects.iter().filter(|p| p.parent_id == Some(parent)) {
+            if seen.insert(child.id) {
+                found.push(child);
+                if recursive {
+                    parents.push(child.id);
+                }
+            }
+        }
+    }
+    found
+}
+
+/// The forest as indented text, one project per line, e.g. `  payments-api (12)`.
+pub fn render_tree(nodes: &[ProjectNode]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        render_node(&mut out, node, 0);
+    }
+    out
+}
+
+fn render_node(out: &mut String, node: &ProjectNode, depth: usize) {
+    let _ = write!(out, "{}{} ({})", "  ".repeat(depth), node.project.name, node.project.id);
+    if let Some(parent_id) = node.project.parent_id {
+        if node.breaks_cycle {
+            let _ = write!(out, " [cycle: parent {} is under this project]", parent_id);
+        } else if node.orphan {
+            let _ = write!(out, " [orphan: parent {} isn't shown above]", parent_id);
+        }
+    }
+    out.push('\n');
+    for child in node.children.iter() {
+        render_node(out, child, depth + 1);
+    }
+}
+
+#[cfg(test)]
+fn project(id: u32, name: &str, parent_id: Option<u32>) -> ApiProject {
+    ApiProject { id, name: name.to_string(), parent_id }
+}
+
+#[test]
+fn test_build_tree(){
+    let projects = vec![
+        project(1, "acme", None),
+        project(2, "payments", Some(1)),
+        project(3, "payments-api", Some(2)),
+        project(4, "billing", Some(1)),
+        project(5, "lost", Some(99)),
+    ];
+    let tree = build_tree(&projects);
+    assert_eq!(render_tree(&tree), "acme (1)\n  payments (2)\n    payments-api (3)\n  billing (4)\nlost (5) [orphan: parent 99 isn't shown above]\n");
+    assert_eq!(tree.len(), 2);
+    assert!(!tree[0].orphan);
+    assert!(tree[1].orphan);
+}
+
+#[test]
+fn test_build_tree_with_cycle(){
+    let projects = vec![
+        project(7, "b", Some(6)),
+        project(6, "a", Some(7)),
+        project(8, "c", Some(7)),
+        project(1, "root", None),
+    ];
+    let tree = build_tree(&projects);
+    assert_eq!(render_tree(&tree), "root (1)\na (6) [cycle: parent 7 is under this project]\n  b (7)\n    c (8)\n");
+    assert!(tree[1].orphan && tree[1].breaks_cycle);
+    assert!(!tree[0].breaks_cycle);
+}
+
+#[test]
+fn test_descendants(){
+    let projects = vec![
+        project(1, "acme", None),
+        project(2, "payments", Some(1)),
+        project(3, "payments-api", Some(2)),
+        project(4, "billing", Some(1)),
+        // a cycle, which shouldn't happen, but mustn't loop forever
+        project(5, "x", Some(6)),
+        project(6, "y", Some(5)),
+    ];
+    let ids = |found: Vec<&ApiProject>| found.iter().map(|p| p.id).collect::<Vec<_>>();
+    assert_eq!(ids(descendants(&projects, 1, false)), vec![2, 4]);
+    assert_eq!(ids(descendants(&projects, 1, true)), vec![2, 4, 3]);
+    assert_eq!(ids(descendants(&projects, 3, true)), Vec::<u32>::new());
+    assert_eq!(ids(descendants(&projects, 5, true)), vec![6]);
+}
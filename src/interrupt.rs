@@ -0,0 +1,184 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Noticing Ctrl-C while waiting for a job, so that the job can be cancelled on the server first.
+//!
+//! `install_handler` replaces the default "exit right away" reaction to the first Ctrl-C with
+//! setting a flag; a poll wrapped in `Interruptible` stops soon after the flag is set, and the caller
+//! decides what to do about the job. A second Ctrl-C exits as usual, and so does any Ctrl-C once the
+//! `HandlerGuard` that `install_handler` returns is dropped (when the poll is over), since that puts
+//! back whatever handler was there before.
+//!
+//! Only unix signals are handled; elsewhere `install_handler` does nothing, and Ctrl-C still just exits.
+
+use client::PollingStrategy;
+use std::cmp;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exit code for the CLI being interrupted with Ctrl-C, as a shell would report it (128 + SIGINT).
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Set by the handler when Ctrl-C is pressed.
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// How often an `Interruptible` poll checks the flag while it waits.
+const CHECK_INTERVAL_MS: u64 = 100;
+
+/// Whether Ctrl-C has been pressed since `install_handler` was called.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+
+    const SIGINT: c_int = 2;
+    const SIG_DFL: usize = 0;
+    const SIG_ERR: usize = !0;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    extern "C" fn on_interrupt(_signum: c_int) {
+        super::INTERRUPTED.store(true, Ordering::SeqCst);
+        // only the first Ctrl-C is caught; the next one exits
+        unsafe { signal(SIGINT, SIG_DFL); }
+    }
+
+    pub fn handler() -> usize {
+        on_interrupt as extern "C" fn(c_int) as usize
+    }
+
+    /// Set `handler` as the one for SIGINT, returning the previous one (if it could be set).
+    pub fn set(handler: usize) -> Option<usize> {
+        match unsafe { signal(SIGINT, handler) } {
+            SIG_ERR => None,
+            previous => Some(previous),
+        }
+    }
+}
+
+/// Puts back the Ctrl-C handler that `install_handler` replaced, when it's dropped.
+#[must_use]
+pub struct HandlerGuard {
+    previous: Option<usize>,
+}
+
+impl Drop for HandlerGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Some(previous) = self.previous.take() {
+                unix::set(previous);
+            }
+        }
+    }
+}
+
+/// Catch the next Ctrl-C (until the guard is dropped), instead of exiting right away.
+///
+/// This also clears the flag from any earlier Ctrl-C, e.g. from a previous command in the REPL.
+#[cfg(unix)]
+pub fn install_handler() -> HandlerGuard {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    HandlerGuard { previous: unix::set(unix::handler()) }
+}
+
+/// Catch the next Ctrl-C, instead of exiting right away (which only works on unix; see the module docs).
+#[cfg(not(unix))]
+pub fn install_handler() -> HandlerGuard {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    HandlerGuard { previous: None }
+}
+
+/// Decorates a polling strategy so that the poll stops (as if the strategy gave up) shortly after `flag` is set.
+///
+/// The inner strategy's waits are slept here, a little at a time, checking the flag in between;
+/// so the poll itself is only told to wait for whatever is left over.
+pub struct Interruptible<'f, P> {
+    inner: P,
+    flag: &'f AtomicBool,
+}
+
+impl <'f, P> Interruptible<'f, P> {
+    /// Wrap `inner`, stopping when `flag` (usually `INTERRUPTED`) is set.
+    pub fn new(inner: P, flag: &'f AtomicBool) -> Interruptible<'f, P> {
+        Interruptible { inner, flag }
+    }
+}
+
+impl <'f, T, P: PollingStrategy<T>> PollingStrategy<T> for Interruptible<'f, P> {
+    fn next_wait(&self, iteration_number: usize, state: &T) -> Option<Duration> {
+        if self.flag.load(Ordering::SeqCst) {
+            return None;
+        }
+        let wait = self.inner.next_wait(iteration_number, state)?;
+        let start = Instant::now();
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= wait {
+                return Some(Duration::from_secs(0));
+            }
+            thread::sleep(cmp::min(wait - elapsed, Duration::from_millis(CHECK_INTERVAL_MS)));
+            if self.flag.load(Ordering::SeqCst) {
+                return None;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_interruptible_poll(){
+    let flag = AtomicBool::new(false);
+    let poll = Interruptible::new(Duration::from_millis(20), &flag);
+    assert_eq!(poll.next_wait(1, &()), Some(Duration::from_secs(0)));
+    flag.store(true, Ordering::SeqCst);
+    assert_eq!(poll.next_wait(2, &()), None);
+
+    // a long wait is cut short when the flag is set part way through
+    let flag = AtomicBool::new(false);
+    let started = Instant::now();
+    let waits = {
+        let poll = Interruptible::new(Duration::from_secs(30), &flag);
+        let flag = &flag;
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                flag.store(true, Ordering::SeqCst);
+            });
+            poll.next_wait(1, &())
+        })
+    };
+    assert_eq!(waits, None);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_handler_is_restored(){
+    let guard = install_handler();
+    assert_eq!(unix::set(unix::handler()), Some(unix::handler()));
+    drop(guard);
+    // whatever was there before is back, so setting it again hands that back
+    let current = unix::set(unix::handler()).unwrap();
+    assert_ne!(current, unix::handler());
+    unix::set(current);
+}
@@ -0,0 +1,113 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Client library for [Code Dx's REST API](https://codedx.com/Documentation/APIGuide.html).
+//!
+//! This crate backs the `codedx-client` command line program, but it can also be used as a library.
+//! Most of what you need is available from the `prelude`:
+//!
+//! ```no_run
+//! extern crate codedx_client;
+//!
+//! use codedx_client::prelude::*;
+//!
+//! fn main() {
+//!     let base_url = Url::parse("https://localhost/codedx").unwrap();
+//!     let auth = ClientAuth::ApiKey("8e218b38-fcdd-453d-8f78-185f7d1d9fa7".to_string());
+//!     let client = ApiClient::new(Box::new(ClientConfig::new(base_url, auth))).expect("couldn't set up the client");
+//!
+//!     match client.get_projects() {
+//!         Ok(projects) => {
+//!             for project in projects {
+//!                 println!("{}: {}", project.id, project.name);
+//!             }
+//!         },
+//!         Err(ApiError::NonSuccess { status, .. }) if status == StatusCode::Forbidden => {
+//!             eprintln!("Not allowed to list projects");
+//!         },
+//!         Err(e) => eprintln!("Error loading projects: {}", e),
+//!     }
+//! }
+//! ```
+//!
+//! Code that takes a `&CodeDxApi` (see the `api` module) instead of an `ApiClient` can be tested with a fake server.
+//!
+//! The client logs each request it sends (its method and url, the response's status, and how long it took) with the
+//! `log` crate, at the `debug` level; install a logger (e.g. `env_logger`) to see them.
+//!
+//! `ApiClient` is blocking: each operation (including polling a job) waits on the calling thread.
+//! For async code, the `async` cargo feature adds `AsyncApiClient` (see the `async_client` module), whose
+//! operations return futures instead, sending their requests with reqwest's async client on a tokio-core event loop.
+
+extern crate base64;
+extern crate libflate;
+#[cfg(feature = "async")] extern crate futures;
+extern crate reqwest;
+extern crate rpassword;
+extern crate serde;
+extern crate serde_yaml;
+extern crate time;
+#[cfg(feature = "async")] extern crate tokio_core;
+extern crate toml;
+extern crate url;
+
+#[macro_use] extern crate clap;
+#[macro_use] extern crate hyper;
+#[macro_use] extern crate log;
+#[macro_use] extern crate serde_json;
+#[macro_use] extern crate serde_derive;
+
+pub mod api;
+#[cfg(feature = "async")] pub mod async_client;
+pub mod check;
+pub mod client;
+pub mod config;
+pub mod gate;
+pub mod glob;
+pub mod hierarchy;
+pub mod interrupt;
+pub mod keyring;
+pub mod limit;
+pub mod output;
+pub mod polling;
+pub mod prelude;
+pub mod prep;
+pub mod profiles;
+pub mod proxy;
+pub mod redact;
+pub mod redirect;
+pub mod retry;
+pub mod runlog;
+pub mod standards;
+pub mod summary;
+pub mod targets;
+pub mod template;
+#[cfg(test)] mod test_dir;
+pub mod test_server;
+pub mod tls;
+pub mod trend;
+pub mod upload;
+pub mod zip;
+
+/// The HTTP status code type used in `ApiError::NonSuccess`.
+///
+/// Re-exported so that library users don't need a dependency on the matching version of `hyper`.
+pub use hyper::StatusCode;
+
+/// The URL type used in `ClientConfig`.
+///
+/// Re-exported so that library users don't need a dependency on the matching version of `url`.
+pub use url::Url;
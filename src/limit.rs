@@ -0,0 +1,114 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Limiting how many requests a client has in flight at once (`--max-concurrent-requests`).
+//!
+//! An `ApiClient` shared between threads (e.g. by a multi-target `analyze`) would otherwise open as many
+//! connections as there are threads. Every request it sends takes a permit from its `RequestLimit` first,
+//! and the `ApiResponse` keeps it until the response's body has been read (or the request failed), since
+//! the connection is busy until then; a request that has to wait for a permit waits in whatever order the
+//! threads happen to wake up. Waiting to retry doesn't hold a permit, so a server that's struggling doesn't
+//! make the other requests wait longer.
+
+use std::sync::{Arc, Condvar, Mutex};
+
+/// The default for `ClientConfig::max_concurrent_requests`.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 4;
+
+/// A counting semaphore for the requests in flight.
+#[derive(Debug)]
+pub struct RequestLimit {
+    max: usize,
+    state: Arc<LimitState>,
+}
+
+#[derive(Debug)]
+struct LimitState {
+    in_flight: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Permission to send one request; the permit is given back when this is dropped.
+///
+/// It doesn't borrow the `RequestLimit`, so it can travel with the response it was taken for.
+#[derive(Debug)]
+pub struct RequestPermit {
+    state: Arc<LimitState>,
+}
+
+impl RequestLimit {
+    /// A limit of `max` requests at once (at least 1).
+    pub fn new(max: usize) -> RequestLimit {
+        let state = LimitState { in_flight: Mutex::new(0), released: Condvar::new() };
+        RequestLimit { max: if max == 0 { 1 } else { max }, state: Arc::new(state) }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Wait until fewer than `max` requests are in flight, then count one more.
+    /// Also says whether it had to wait.
+    pub fn acquire(&self) -> (RequestPermit, bool) {
+        let mut in_flight = self.state.in_flight.lock().unwrap();
+        let waited = *in_flight >= self.max;
+        while *in_flight >= self.max {
+            in_flight = self.state.released.wait(in_flight).unwrap();
+        }
+        *in_flight += 1;
+        (RequestPermit { state: self.state.clone() }, waited)
+    }
+
+    /// How many requests are in flight right now.
+    pub fn in_flight(&self) -> usize {
+        *self.state.in_flight.lock().unwrap()
+    }
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        let mut in_flight = self.state.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        *in_flight -= 1;
+        self.state.released.notify_one();
+    }
+}
+
+#[test]
+fn test_request_limit(){
+    use std::cmp;
+    use std::thread;
+    use std::time::Duration;
+
+    let limit = Arc::new(RequestLimit::new(3));
+    let peak = Arc::new(Mutex::new(0));
+    let workers: Vec<_> = (0..12).map(|_| {
+        let (limit, peak) = (limit.clone(), peak.clone());
+        thread::spawn(move || {
+            let _permit = limit.acquire();
+            {
+                let mut peak = peak.lock().unwrap();
+                *peak = cmp::max(*peak, limit.in_flight());
+            }
+            thread::sleep(Duration::from_millis(5));
+        })
+    }).collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert!(*peak.lock().unwrap() <= 3);
+    assert_eq!(limit.in_flight(), 0);
+    assert_eq!(RequestLimit::new(0).max(), 1);
+}
@@ -0,0 +1,49 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use codedx_client::config::Verbosity;
+use log::{self, Log, LogLevelFilter, LogMetadata, LogRecord};
+
+/// Shows the client's log messages on STDERR, like progress messages, e.g. `# debug: GET ... -> 200 OK (41 ms)`.
+struct StderrLogger {
+    level: LogLevelFilter,
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= self.level && metadata.target().starts_with("codedx_client")
+    }
+
+    fn log(&self, record: &LogRecord) {
+        if self.enabled(record.metadata()) {
+            eprintln!("# {}: {}", record.level().to_string().to_lowercase(), record.args());
+        }
+    }
+}
+
+/// Show the `debug` messages with `-v`, and the `trace` ones too with `-vv` (or `--trace`).
+pub fn init(verbosity: Verbosity) {
+    let level = match verbosity {
+        Verbosity::Quiet | Verbosity::Normal => LogLevelFilter::Warn,
+        Verbosity::Debug => LogLevelFilter::Debug,
+        Verbosity::Trace => LogLevelFilter::Trace,
+    };
+    // this is the only place a logger is set, so it can't already have been
+    let _ = log::set_logger(|max_level| {
+        max_level.set(level);
+        Box::new(StderrLogger { level })
+    });
+}
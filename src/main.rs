@@ -14,26 +14,23 @@
  * limitations under the License.
  */
 
-extern crate reqwest;
-extern crate rpassword;
-extern crate serde;
-extern crate url;
+extern crate clap;
+extern crate codedx_client;
+extern crate log;
 
-#[macro_use] extern crate clap;
-#[macro_use] extern crate hyper;
 #[macro_use] extern crate nom;
 #[macro_use] extern crate serde_json;
-#[macro_use] extern crate serde_derive;
 
-mod client;
 mod commands;
-mod config;
+mod logger;
 mod repl;
 
 use clap::{ArgMatches, App, AppSettings};
 use std::io;
 use std::io::Write;
 
+use codedx_client::{client, config};
+use codedx_client::output::ErrorOutput;
 use config::*;
 use client::*;
 use repl::CmdArgs;
@@ -47,10 +44,22 @@ fn main(){
         base_app
     };
     let matches = app.get_matches();
+    // the config may not get far enough to know this itself
+    let error_format = if matches.value_of("error-format") == Some("json") { ErrorFormat::Json } else { ErrorFormat::Text };
 
     match ClientConfig::from_matches(&matches) {
         Ok(config) => {
-            let client = ApiClient::new(Box::new(config));
+            logger::init(config.verbosity());
+            let client = match ApiClient::new(Box::new(config)) {
+                Ok(client) => client,
+                Err(e) => {
+                    match error_format {
+                        ErrorFormat::Text => eprintln!("{}", e),
+                        ErrorFormat::Json => eprintln!("{}", serde_json::to_string(&ErrorOutput::from_api_error("setting up the client", &e)).unwrap()),
+                    }
+                    std::process::exit(1);
+                },
+            };
             if let (_, Some(_)) = matches.subcommand() {
                 run_oneoff(client, &matches);
             } else {
@@ -64,9 +73,49 @@ fn main(){
                 run_repl(client);
             }
         },
-        Err(ConfigError::MissingAuth) => eprintln!("Authorization info missing or incomplete. Either an API Key or a Username + Password must be provided"),
-        Err(ConfigError::MissingUrl) => eprintln!("Missing the Base URL"),
-        Err(ConfigError::InvalidUrl) => eprintln!("Invalid Base URL. Did you forget 'http://' or 'https://' ?"),
+        Err(e) => {
+            let message = match e {
+                ConfigError::MissingAuth => "Authorization info missing or incomplete. Either an API Key or a Username + Password must be provided".to_string(),
+                ConfigError::NoPasswordPrompt => "No password given, and STDIN isn't a terminal to ask for one on. Use --password-stdin, --password or CODEDX_PASSWORD".to_string(),
+                ConfigError::InvalidPasswordStdin(Some(e)) => format!("Couldn't read the password from STDIN: {}", e),
+                ConfigError::InvalidPasswordStdin(None) => "--password-stdin read an empty password".to_string(),
+                ConfigError::PasswordStdinConflict(other) => format!("--password-stdin can't be used with {}; give the password with CODEDX_PASSWORD instead", other),
+                ConfigError::MissingUrl => "Missing the Base URL".to_string(),
+                ConfigError::InvalidUrl => "Invalid Base URL. Did you forget 'http://' or 'https://' ?".to_string(),
+                ConfigError::InvalidProxyUrl => "Invalid proxy URL. It should look like 'http://proxy.example.com:3128'".to_string(),
+                ConfigError::InvalidProxyAuth => "Invalid --proxy-auth value. It should look like 'username:password'".to_string(),
+                ConfigError::ProxyAuthNeedsHttp => "Proxy credentials can only be sent for an http base url; for an https one, the proxy has to allow opening a tunnel without them".to_string(),
+                ConfigError::InvalidCaCert(path, e) => format!("Couldn't load CA certificates from {}: {}", path.display(), e),
+                ConfigError::NoVerifyCertUnsupported => "--no-verify-cert isn't supported yet, since this version's TLS library can't accept a certificate that isn't from a trusted CA. \
+                    Pass the server's certificate (or its CA's) to --cacert instead, which trusts it without turning off any checks".to_string(),
+                ConfigError::InvalidClientCert(path, e) => format!("Couldn't load the client certificate from {}: {}", path.display(), e),
+                ConfigError::InvalidRetries => "Invalid --retries value. It should be a whole number, like 3".to_string(),
+                ConfigError::InvalidTraceBodyLimit => "Invalid --trace-body-limit value. It should be a size like 10k or 1M".to_string(),
+                ConfigError::InvalidMaxConcurrentRequests => "Invalid --max-concurrent-requests value. It should be a whole number of at least 1".to_string(),
+                ConfigError::InvalidUserAgentComment => "Invalid --user-agent-comment. It can't have parentheses or control characters".to_string(),
+                ConfigError::InvalidHeader(reason) => format!("Invalid --header: {}", reason),
+                ConfigError::InvalidTimeout => "Invalid timeout. It should be a duration like '30s' or '5m', or 'none'".to_string(),
+                ConfigError::InvalidSessionTimeout => "Invalid session timeout. It should be a duration like '15m'".to_string(),
+                ConfigError::InvalidEnvVar(name) => format!("Invalid {} environment variable. It should be 1, true or yes, or 0, false or no", name),
+                ConfigError::MissingConfigFile(Some(path), Some(e)) => format!("Couldn't read the config file {}: {}", path.display(), e),
+                ConfigError::MissingConfigFile(..) => "Couldn't find a config file for the profile; give its location with --config".to_string(),
+                ConfigError::InvalidConfigFile(path, e) => format!("Invalid config file {}, {}", path.display(), e),
+                ConfigError::UnknownProfile { name, path, available } => {
+                    let available = if available.is_empty() { "(none)".to_string() } else { available.join(", ") };
+                    format!("There's no profile named \"{}\" in {}; the profiles are: {}", name, path.display(), available)
+                },
+            };
+            report(error_format, "invalid_config", &message);
+        },
+    }
+}
+
+/// Print a failure that happens outside of any command (which would use `ApiClient::report_failure`),
+/// in the `--error-format`'s format.
+fn report(error_format: ErrorFormat, kind: &str, message: &str) {
+    match error_format {
+        ErrorFormat::Text => eprintln!("{}", message),
+        ErrorFormat::Json => eprintln!("{}", serde_json::to_string(&ErrorOutput::new(kind, message)).unwrap()),
     }
 }
 
@@ -81,15 +130,20 @@ fn run_oneoff<'a>(client: ApiClient, arg_matches: &ArgMatches<'a>) -> ! {
         CommandRunnerResult::Done => 0,
         CommandRunnerResult::RequestedExit(code) => code,
         CommandRunnerResult::UnknownCommand => {
-            eprintln!("Unknown command.");
+            report(client.get_config().error_format, "unknown_command", "Unknown command.");
             -1
         },
         CommandRunnerResult::InvalidArguments(msg) => {
-            eprintln!("Invalid arguments for command: {}", msg);
+            report(client.get_config().error_format, "invalid_arguments", &format!("Invalid arguments for command: {}", msg));
             -2
         },
     };
 
+    client.flush_failure();
+
+    // a dry run ends at the first request that would change something, which isn't a failure (unless something else failed too)
+    let exit_code = if exit_code > 0 && client.stopped_for_dry_run() && !client.reported_failure() { 0 } else { exit_code };
+    client.log().event("exit", json!({ "code": exit_code }));
     std::process::exit(exit_code);
 }
 
@@ -146,7 +200,9 @@ fn run_repl(client: ApiClient) {
                     Ok(arg_matches) => {
                         let command_runner = CommandRunner(commands::all());
 
-                        match command_runner.maybe_run(&arg_matches, &client) {
+                        let result = command_runner.maybe_run(&arg_matches, &client);
+                        client.flush_failure();
+                        match result {
                             CommandRunnerResult::UnknownCommand => eprintln!("Unknown command; try again."),
                             CommandRunnerResult::InvalidArguments(msg) => eprintln!("Invalid arguments for command: {}\nTry again.", msg),
                             CommandRunnerResult::RequestedExit(code) => std::process::exit(code),
@@ -183,10 +239,19 @@ fn repl_app() -> App<'static, 'static> {
 struct CommandRunner<'a>(Vec<Box<commands::Command<'a>>>);
 impl <'a> CommandRunner<'a> {
     fn maybe_run<'b>(&self, arg_matches: &'a ArgMatches, client: &'b ApiClient) -> CommandRunnerResult<'a> {
+        let command_name = arg_matches.subcommand_name();
+        client.log().event("command-start", json!({ "command": command_name }));
         let raw_result = self.0.iter().filter_map(|command_box| {
             let cmd = command_box.as_ref();
             cmd.maybe_run(arg_matches, client)
         }).next();
+        let outcome = match raw_result {
+            Some(Ok(Ok(()))) => json!({ "command": command_name, "outcome": "done" }),
+            Some(Ok(Err(commands::Exit(code)))) => json!({ "command": command_name, "outcome": "exit", "code": code }),
+            Some(Err(msg)) => json!({ "command": command_name, "outcome": "invalid-arguments", "message": msg }),
+            None => json!({ "command": command_name, "outcome": "unknown-command" }),
+        };
+        client.log().event("command-end", outcome);
         raw_result.into()
     }
 }
@@ -0,0 +1,998 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Documents written by the CLI's machine-readable (JSON) output modes.
+//!
+//! These structs are deliberately separate from the API models in the `client` module,
+//! so that changes in what the Code Dx server sends don't silently change what we print.
+//!
+//! Every top-level document carries a `schemaVersion` field, and the shapes follow this policy:
+//!
+//!  - Fields may be *added* to any document at any time, without changing `SCHEMA_VERSION`.
+//!    Consumers should ignore fields they don't recognize.
+//!  - Fields are only *renamed or removed* along with an increment of `SCHEMA_VERSION`,
+//!    and only in a release that bumps the major version of this crate.
+//!
+//! Machine-readable output is the same no matter where the CLI runs:
+//!
+//!  - Timestamps are RFC 3339 in UTC (see `utc_timestamp`), never in the local time zone.
+//!  - Numbers are printed by `serde_json` or `Display`, neither of which consult the system locale.
+//!  - File paths use forward slashes (see `portable_path`); documents that include a path
+//!    also include the path exactly as the server reported it.
+//!  - Lines end with `\n`, including on Windows.
+//!
+//! Listings (projects, analyses, branches) can also be printed as a table or as CSV, chosen with
+//! the global `--format` option; see `Render`.
+//!
+//! The tests at the bottom of this module pin the field names of every document;
+//! if one of them fails, you're about to break somebody's parser.
+
+use client::*;
+use serde::ser::Serialize;
+use serde_json;
+use standards::Coverage;
+use std::str::FromStr;
+use summary::{self, Summary};
+use targets::{TargetOutcome, TargetResult};
+use time;
+use url::Url;
+
+/// Version of the JSON output schema, embedded in each document as `schemaVersion`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Convert a timestamp to RFC 3339 in UTC, e.g. `2017-10-05T12:00:00Z`.
+///
+/// Servers may report times with fractional seconds or a UTC offset; both are normalized away.
+/// Anything that isn't a recognizable timestamp is returned unchanged rather than dropped.
+pub fn utc_timestamp(raw: &str) -> String {
+    let parsed = time::strptime(raw, "%Y-%m-%dT%H:%M:%S.%f%z")
+        .or_else(|_| time::strptime(raw, "%Y-%m-%dT%H:%M:%S%z"));
+    match parsed {
+        Ok(mut tm) => {
+            // `Tm::to_timespec` treats any non-zero offset as the *local* zone, so apply it by hand
+            let offset = tm.tm_utcoff as i64;
+            tm.tm_utcoff = 0;
+            tm.tm_nsec = 0;
+            time::at_utc(tm.to_timespec() - time::Duration::seconds(offset)).rfc3339().to_string()
+        },
+        Err(_) => raw.to_string(),
+    }
+}
+
+/// Convert a file path to use forward slashes, e.g. `src\db.rs` becomes `src/db.rs`.
+pub fn portable_path(raw: &str) -> String {
+    raw.replace('\\', "/")
+}
+
+/// One project, as printed (one per line) by the `projects` command.
+#[derive(Debug, Serialize)]
+pub struct ProjectOutput {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    pub id: u32,
+    pub name: String,
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<u32>,
+}
+impl <'a> From<&'a ApiProject> for ProjectOutput {
+    fn from(project: &'a ApiProject) -> ProjectOutput {
+        ProjectOutput {
+            schema_version: SCHEMA_VERSION,
+            id: project.id,
+            name: project.name.clone(),
+            parent_id: project.parent_id,
+        }
+    }
+}
+
+/// JSON form of a `summary::Summary`.
+#[derive(Debug, Serialize)]
+pub struct SummaryOutput {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    pub project: ProjectRefOutput,
+    pub analysis: Option<AnalysisOutput>,
+    #[serde(rename = "severityCounts")]
+    pub severity_counts: Vec<SeverityCountOutput>,
+    #[serde(rename = "topFindings")]
+    pub top_findings: Vec<FindingOutput>,
+    pub comparison: Option<ComparisonOutput>,
+    pub verdict: String,
+    #[serde(rename = "generatedAt")]
+    pub generated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectRefOutput {
+    pub id: u32,
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalysisOutput {
+    pub id: u32,
+    pub name: Option<String>,
+    pub state: Option<String>,
+    #[serde(rename = "scanTime")]
+    pub scan_time: Option<String>,
+}
+impl <'a> From<&'a ApiAnalysis> for AnalysisOutput {
+    fn from(analysis: &'a ApiAnalysis) -> AnalysisOutput {
+        AnalysisOutput {
+            id: analysis.id,
+            name: analysis.name.clone(),
+            state: analysis.state.clone(),
+            scan_time: summary::analysis_time(analysis).map(utc_timestamp),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeverityCountOutput {
+    pub severity: String,
+    pub count: u64,
+    /// Change since the baseline analysis; only present when comparing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FindingOutput {
+    pub id: u64,
+    pub severity: Option<String>,
+    pub status: Option<String>,
+    pub title: String,
+    pub cwe: Option<u32>,
+    /// The finding's file path, with forward slashes.
+    pub path: Option<String>,
+    /// The finding's file path, exactly as the server reported it.
+    #[serde(rename = "originalPath")]
+    pub original_path: Option<String>,
+    pub line: Option<u32>,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComparisonOutput {
+    #[serde(rename = "baselineAnalysisId")]
+    pub baseline_analysis_id: u32,
+    #[serde(rename = "newFindings")]
+    pub new_findings: Vec<FindingOutput>,
+    #[serde(rename = "resolvedFindings")]
+    pub resolved_findings: Vec<FindingOutput>,
+}
+
+impl FindingOutput {
+    pub fn new(finding: &ApiFinding, url: String) -> FindingOutput {
+        let location = finding.location.as_ref();
+        FindingOutput {
+            id: finding.id,
+            severity: finding.severity.clone(),
+            status: finding.status.clone(),
+            title: finding.title().to_string(),
+            cwe: finding.cwe.as_ref().map(|cwe| cwe.id),
+            path: location.and_then(|loc| loc.path.as_ref()).map(|path| portable_path(path)),
+            original_path: location.and_then(|loc| loc.path.clone()),
+            line: location.and_then(|loc| loc.line_range.as_ref()).map(|lines| lines.start),
+            url,
+        }
+    }
+}
+
+impl <'a> From<&'a Summary> for SummaryOutput {
+    fn from(s: &'a Summary) -> SummaryOutput {
+        let findings = |list: &[ApiFinding]| -> Vec<FindingOutput> {
+            list.iter().map(|f| FindingOutput::new(f, s.finding_link(f))).collect()
+        };
+        SummaryOutput {
+            schema_version: SCHEMA_VERSION,
+            project: ProjectRefOutput {
+                id: s.project.id,
+                name: s.project.name.clone(),
+                url: s.project_link(),
+            },
+            analysis: s.analysis.as_ref().map(AnalysisOutput::from),
+            severity_counts: summary::SEVERITIES.iter().map(|severity| {
+                let (count, change) = s.severity_total(severity);
+                SeverityCountOutput { severity: severity.to_string(), count, change }
+            }).collect(),
+            top_findings: findings(&s.top_findings),
+            comparison: s.comparison.as_ref().map(|c| ComparisonOutput {
+                baseline_analysis_id: c.baseline.id,
+                new_findings: findings(&c.new_findings),
+                resolved_findings: findings(&c.resolved_findings),
+            }),
+            verdict: summary::verdict(s),
+            generated_at: s.generated_at.clone(),
+        }
+    }
+}
+
+/// JSON form of a `standards::Coverage`, as printed by the `standards` command.
+#[derive(Debug, Serialize)]
+pub struct StandardsOutput {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    pub standard: String,
+    #[serde(rename = "projectId")]
+    pub project_id: u32,
+    /// The severities that were counted, or `None` if every severity was.
+    pub severities: Option<Vec<String>>,
+    pub categories: Vec<CategoryCountOutput>,
+    pub unmapped: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategoryCountOutput {
+    pub id: String,
+    pub name: String,
+    pub count: u64,
+}
+
+impl StandardsOutput {
+    pub fn new(project_id: u32, severities: Option<&[&str]>, coverage: &Coverage) -> StandardsOutput {
+        StandardsOutput {
+            schema_version: SCHEMA_VERSION,
+            standard: coverage.standard.key().to_string(),
+            project_id,
+            severities: severities.map(|list| list.iter().map(|s| s.to_string()).collect()),
+            categories: coverage.categories.iter().map(|c| CategoryCountOutput {
+                id: c.category.id.to_string(),
+                name: c.category.name.to_string(),
+                count: c.count,
+            }).collect(),
+            unmapped: coverage.unmapped,
+        }
+    }
+}
+
+/// JSON form of finding counts, as printed by the `finding-counts` command.
+#[derive(Debug, Serialize)]
+pub struct FindingCountsOutput {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "projectId")]
+    pub project_id: u32,
+    /// What the findings were grouped by, e.g. `"severity"`.
+    #[serde(rename = "groupBy")]
+    pub group_by: CountGroup,
+    /// One entry per group with any findings in it; empty if there are no findings at all.
+    pub counts: Vec<CountOutput>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CountOutput {
+    pub name: String,
+    pub count: u64,
+}
+
+impl FindingCountsOutput {
+    pub fn new(project_id: u32, group_by: CountGroup, counts: &[GroupedCount]) -> FindingCountsOutput {
+        FindingCountsOutput {
+            schema_version: SCHEMA_VERSION,
+            project_id,
+            group_by,
+            counts: counts.iter().map(|c| CountOutput { name: c.name.clone(), count: c.count }).collect(),
+        }
+    }
+}
+
+/// JSON form of a list of findings, as printed by the `findings` command.
+#[derive(Debug, Serialize)]
+pub struct FindingsOutput {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "projectId")]
+    pub project_id: u32,
+    pub findings: Vec<FindingOutput>,
+}
+impl FindingsOutput {
+    /// `web_url` is the server's base url, which each finding's `url` links into.
+    pub fn new(project_id: u32, findings: &[ApiFinding], web_url: &Url) -> FindingsOutput {
+        FindingsOutput {
+            schema_version: SCHEMA_VERSION,
+            project_id,
+            findings: findings.iter().map(|f| FindingOutput::new(f, summary::web_link(web_url, &["findings", &f.id.to_string()]))).collect(),
+        }
+    }
+}
+
+/// JSON form of an analysis, as `list-analyses` prints it (in an array), with every timestamp the server reported.
+#[derive(Debug, Serialize)]
+pub struct AnalysisRecordOutput {
+    pub id: u32,
+    pub name: Option<String>,
+    /// The state as the server reported it.
+    pub state: Option<String>,
+    /// The state, interpreted the same way for every server version (see `ApiAnalysis::status`).
+    pub status: JobStatus,
+    #[serde(rename = "creationTime")]
+    pub creation_time: Option<String>,
+    #[serde(rename = "startTime")]
+    pub start_time: Option<String>,
+    #[serde(rename = "finishTime")]
+    pub finish_time: Option<String>,
+    /// Each input, with the tools that ran on it (which is how to tell which one failed).
+    pub inputs: Vec<ApiAnalysisInput>,
+}
+impl <'a> From<&'a ApiAnalysis> for AnalysisRecordOutput {
+    fn from(analysis: &'a ApiAnalysis) -> AnalysisRecordOutput {
+        AnalysisRecordOutput {
+            id: analysis.id,
+            name: analysis.name.clone(),
+            state: analysis.state.clone(),
+            status: analysis.status(),
+            creation_time: analysis.creation_time.as_ref().map(|t| utc_timestamp(t)),
+            start_time: analysis.start_time.as_ref().map(|t| utc_timestamp(t)),
+            finish_time: analysis.finish_time.as_ref().map(|t| utc_timestamp(t)),
+            inputs: analysis.inputs.clone(),
+        }
+    }
+}
+
+/// JSON form of a project's metadata, as printed by the `project-metadata` command.
+#[derive(Debug, Serialize)]
+pub struct ProjectMetadataOutput {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "projectId")]
+    pub project_id: u32,
+    /// Every field, in the order the server defines them; unset fields have a `null` value.
+    pub fields: Vec<MetadataFieldOutput>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetadataFieldOutput {
+    pub id: u32,
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl ProjectMetadataOutput {
+    pub fn new(project_id: u32, fields: &[ProjectMetadataField]) -> ProjectMetadataOutput {
+        ProjectMetadataOutput {
+            schema_version: SCHEMA_VERSION,
+            project_id,
+            fields: fields.iter().map(|f| MetadataFieldOutput {
+                id: f.id,
+                name: f.name.clone(),
+                value: f.value.clone(),
+            }).collect(),
+        }
+    }
+}
+
+/// JSON form of a project's finding statuses, as printed by the `list-statuses` command.
+#[derive(Debug, Serialize)]
+pub struct StatusesOutput {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "projectId")]
+    pub project_id: u32,
+    pub statuses: Vec<StatusOutput>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusOutput {
+    pub id: String,
+    pub name: String,
+    pub custom: bool,
+}
+
+impl StatusesOutput {
+    pub fn new(project_id: u32, statuses: &[FindingStatus]) -> StatusesOutput {
+        StatusesOutput {
+            schema_version: SCHEMA_VERSION,
+            project_id,
+            statuses: statuses.iter().map(|s| StatusOutput {
+                id: s.id.clone(),
+                name: s.name.clone(),
+                custom: s.custom,
+            }).collect(),
+        }
+    }
+}
+
+/// JSON form of a job's status, as printed by the `job-status` command.
+#[derive(Debug, Serialize)]
+pub struct JobStatusOutput<'a> {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "jobId")]
+    pub job_id: &'a str,
+    pub status: JobStatus,
+    pub progress: Option<&'a JobProgress>,
+    pub reason: Option<&'a str>,
+    #[serde(rename = "blockedBy")]
+    pub blocked_by: &'a [String],
+}
+
+impl <'a> From<&'a JobStatusResponse> for JobStatusOutput<'a> {
+    fn from(job: &'a JobStatusResponse) -> JobStatusOutput<'a> {
+        JobStatusOutput {
+            schema_version: SCHEMA_VERSION,
+            job_id: &job.job_id,
+            status: job.status,
+            progress: job.progress.as_ref(),
+            reason: job.reason.as_ref().map(|r| r.as_str()),
+            blocked_by: &job.blocked_by,
+        }
+    }
+}
+
+/// JSON form of a multi-target `analyze` run: one entry per target, in the order they were given.
+#[derive(Debug, Serialize)]
+pub struct TargetsOutput {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    pub targets: Vec<TargetOutput>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TargetOutput {
+    /// The project as it was given (a name or an id).
+    pub project: String,
+    #[serde(rename = "projectId")]
+    pub project_id: Option<u32>,
+    pub files: Vec<String>,
+    #[serde(rename = "originalFiles")]
+    pub original_files: Vec<String>,
+    #[serde(rename = "analysisId")]
+    pub analysis_id: Option<u32>,
+    /// The analysis's final job status, or `None` if the target failed before it finished.
+    pub status: Option<JobStatus>,
+    /// What went wrong, including too many findings for the target's gate (with a `completed` status).
+    pub error: Option<String>,
+}
+
+impl <'a> From<&'a [TargetResult]> for TargetsOutput {
+    fn from(results: &'a [TargetResult]) -> TargetsOutput {
+        TargetsOutput {
+            schema_version: SCHEMA_VERSION,
+            targets: results.iter().map(|r| {
+                let original_files: Vec<String> = r.target.files.iter().map(|f| f.to_string_lossy().into_owned()).collect();
+                let (status, error) = match r.outcome {
+                    TargetOutcome::Finished(status) => (Some(status), None),
+                    TargetOutcome::GateFailed(_) => (Some(JobStatus::Completed), Some(r.outcome.describe())),
+                    TargetOutcome::Error { .. } => (None, Some(r.outcome.describe())),
+                };
+                TargetOutput {
+                    project: r.target.project.clone(),
+                    project_id: r.project_id,
+                    files: original_files.iter().map(|f| portable_path(f)).collect(),
+                    original_files,
+                    analysis_id: r.analysis_id,
+                    status,
+                    error,
+                }
+            }).collect(),
+        }
+    }
+}
+
+/// A failure, as printed to STDERR with `--error-format json` (one document per failure, on one line).
+#[derive(Debug, Serialize)]
+pub struct ErrorOutput {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    /// What went wrong: one of `ApiError::kind`'s names for an error from the API (e.g. `non_success`,
+    /// `protocol` or `io`), or e.g. `failed` or `timed_out` for a command's own checks.
+    pub kind: String,
+    /// The HTTP status, for a `non_success` error.
+    pub status: Option<u16>,
+    /// For a `non_success` error, the server's message (or its response body, if it didn't send one);
+    /// otherwise, a description of the error.
+    pub message: String,
+    /// What the command was doing, e.g. `loading projects`.
+    pub operation: Option<String>,
+    /// The request that went wrong, e.g. `GET /codedx/api/jobs/123`, when it's known.
+    pub request: Option<String>,
+}
+
+impl ErrorOutput {
+    /// A failure found by a command itself, rather than an error from the API.
+    pub fn new(kind: &str, message: &str) -> ErrorOutput {
+        ErrorOutput {
+            schema_version: SCHEMA_VERSION,
+            kind: kind.to_string(),
+            status: None,
+            message: message.to_string(),
+            operation: None,
+            request: None,
+        }
+    }
+
+    pub fn from_api_error(operation: &str, error: &ApiError) -> ErrorOutput {
+        let message = match *error {
+            ApiError::NonSuccess { ref message, .. } => message.to_string(),
+            ref other => other.to_string(),
+        };
+        ErrorOutput {
+            schema_version: SCHEMA_VERSION,
+            kind: error.kind().to_string(),
+            status: error.status().map(|status| status.as_u16()),
+            message,
+            operation: Some(operation.to_string()),
+            request: error.request().map(|request| request.to_string()),
+        }
+    }
+}
+
+/// How a listing is printed, chosen with the global `--format` option.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Columns lined up under a header row.
+    Table,
+    /// One JSON array.
+    Json,
+    /// A header row, then one row per item, quoted as in RFC 4180.
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            other => Err(format!("unknown output format \"{}\"; the formats are: table, json, csv", other)),
+        }
+    }
+}
+
+/// A listing that can be printed in any `OutputFormat`.
+///
+/// Whatever the format, the result goes to STDOUT on its own; messages about how the listing was
+/// made belong on STDERR, so that e.g. `--format json` can always be piped into `jq`.
+pub trait Render {
+    /// The whole listing, ending with a newline. An empty one is `[]` as JSON, just the header row
+    /// as CSV, and `no results` as a table.
+    fn render(&self, format: OutputFormat) -> String;
+}
+
+impl Render for [ApiProject] {
+    fn render(&self, format: OutputFormat) -> String {
+        let items: Vec<ProjectOutput> = self.iter().map(ProjectOutput::from).collect();
+        render_listing(format, &items, &["ID", "NAME", "PARENT"], self.iter().map(|p| vec![
+            p.id.to_string(),
+            p.name.clone(),
+            p.parent_id.map(|id| id.to_string()).unwrap_or_default(),
+        ]).collect())
+    }
+}
+
+impl Render for [ApiAnalysis] {
+    fn render(&self, format: OutputFormat) -> String {
+        let items: Vec<AnalysisRecordOutput> = self.iter().map(AnalysisRecordOutput::from).collect();
+        render_listing(format, &items, &["ID", "STATUS", "FINISHED", "NAME"], self.iter().map(|a| vec![
+            a.id.to_string(),
+            format!("{:?}", a.status()).to_lowercase(),
+            a.finish_time.as_ref().map(|t| utc_timestamp(t)).unwrap_or_else(|| "-".to_string()),
+            a.name.clone().unwrap_or_default(),
+        ]).collect())
+    }
+}
+
+impl Render for [ApiBranch] {
+    fn render(&self, format: OutputFormat) -> String {
+        render_listing(format, self, &["ID", "NAME", "DEFAULT"], self.iter().map(|b| vec![
+            b.id.to_string(),
+            b.name.clone(),
+            if b.is_default { "yes" } else { "" }.to_string(),
+        ]).collect())
+    }
+}
+
+/// Shared by the `Render` impls: `items` is what's printed as JSON, and `rows` (one per item) are the table/CSV cells.
+fn render_listing<T: Serialize>(format: OutputFormat, items: &[T], headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    match format {
+        OutputFormat::Json => format!("{}\n", serde_json::to_string(items).unwrap()),
+        OutputFormat::Csv => render_csv(headers, rows),
+        OutputFormat::Table if rows.is_empty() => "no results\n".to_string(),
+        OutputFormat::Table => render_columns(headers, rows),
+    }
+}
+
+/// Lay out `rows` in columns under `headers`, each column as wide as its widest cell.
+pub fn render_columns(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    let header_row: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    for row in Some(header_row).into_iter().chain(rows) {
+        let line: Vec<String> = row.iter().zip(&widths).map(|(cell, &width)| format!("{:<width$}", cell, width = width)).collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// A header row and `rows` as CSV. Lines end with `\n` rather than RFC 4180's `\r\n`, like the rest of the output.
+pub fn render_csv(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut out = String::new();
+    let header_row: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    for row in Some(header_row).into_iter().chain(rows) {
+        let line: Vec<String> = row.iter().map(|cell| csv_field(cell)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Quote a CSV field if it needs it: if it has a comma, a quote or a line break, it's wrapped in
+/// quotes, and each quote in it is doubled.
+pub fn csv_field(value: &str) -> String {
+    if value.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[test]
+fn test_project_output_schema(){
+    let project = ApiProject { id: 5, name: "WebGoat".to_string(), parent_id: Some(2) };
+    let json = ::serde_json::to_value(ProjectOutput::from(&project)).unwrap();
+    assert_eq!(json, json!({
+        "schemaVersion": 1,
+        "id": 5,
+        "name": "WebGoat",
+        "parentId": 2,
+    }));
+}
+
+#[test]
+fn test_finding_output_schema(){
+    let finding: ApiFinding = ::serde_json::from_value(json!({
+        "id": 12,
+        "severity": "High",
+        "status": { "name": "New" },
+        "cwe": { "id": 89 },
+        "descriptor": { "name": "SQL Injection" },
+        "primaryLocation": { "path": "src/db.rs", "lineRange": { "start": 40, "end": 42 } },
+    })).unwrap();
+    let json = ::serde_json::to_value(FindingOutput::new(&finding, "https://localhost/codedx/findings/12".to_string())).unwrap();
+    assert_eq!(json, json!({
+        "id": 12,
+        "severity": "High",
+        "status": "New",
+        "title": "SQL Injection",
+        "cwe": 89,
+        "path": "src/db.rs",
+        "originalPath": "src/db.rs",
+        "line": 40,
+        "url": "https://localhost/codedx/findings/12",
+    }));
+}
+
+#[test]
+fn test_summary_output_schema(){
+    let baseline: ApiAnalysis = ::serde_json::from_value(json!({ "id": 6 })).unwrap();
+    let mut s = Summary {
+        project: ApiProject { id: 5, name: "WebGoat".to_string(), parent_id: None },
+        analysis: Some(::serde_json::from_value(json!({ "id": 7, "name": "nightly", "state": "complete", "finishTime": "2017-10-05T12:00:00Z" })).unwrap()),
+        severity_counts: vec![GroupedCount { name: "High".to_string(), count: 3 }],
+        top_findings: vec![],
+        comparison: Some(summary::Comparison {
+            baseline,
+            baseline_counts: vec![GroupedCount { name: "High".to_string(), count: 1 }],
+            new_findings: vec![],
+            resolved_findings: vec![],
+        }),
+        generated_at: "2017-10-06T00:00:00Z".to_string(),
+        web_url: ::url::Url::parse("https://localhost/codedx").unwrap(),
+    };
+    let json = ::serde_json::to_value(SummaryOutput::from(&s)).unwrap();
+    assert_eq!(json, json!({
+        "schemaVersion": 1,
+        "project": { "id": 5, "name": "WebGoat", "url": "https://localhost/codedx/projects/5" },
+        "analysis": { "id": 7, "name": "nightly", "state": "complete", "scanTime": "2017-10-05T12:00:00Z" },
+        "severityCounts": [
+            { "severity": "Critical", "count": 0, "change": 0 },
+            { "severity": "High", "count": 3, "change": 2 },
+            { "severity": "Medium", "count": 0, "change": 0 },
+            { "severity": "Low", "count": 0, "change": 0 },
+            { "severity": "Info", "count": 0, "change": 0 },
+            { "severity": "Unspecified", "count": 0, "change": 0 },
+        ],
+        "topFindings": [],
+        "comparison": { "baselineAnalysisId": 6, "newFindings": [], "resolvedFindings": [] },
+        "verdict": "No new findings",
+        "generatedAt": "2017-10-06T00:00:00Z",
+    }));
+
+    // without a baseline, there's no change to report
+    s.comparison = None;
+    let json = ::serde_json::to_value(SummaryOutput::from(&s)).unwrap();
+    assert_eq!(json["severityCounts"][1], json!({ "severity": "High", "count": 3 }));
+}
+
+#[test]
+fn test_standards_output_schema(){
+    let coverage = ::standards::bucket(::standards::Standard::Owasp2021, &[GroupedCount { name: "CWE-918".to_string(), count: 2 }]);
+    let json = ::serde_json::to_value(StandardsOutput::new(5, Some(&["Critical", "High"]), &coverage)).unwrap();
+    assert_eq!(json["schemaVersion"], json!(1));
+    assert_eq!(json["standard"], json!("owasp2021"));
+    assert_eq!(json["projectId"], json!(5));
+    assert_eq!(json["severities"], json!(["Critical", "High"]));
+    assert_eq!(json["categories"].as_array().unwrap().len(), 10);
+    assert_eq!(json["categories"][9], json!({ "id": "A10:2021", "name": "Server-Side Request Forgery (SSRF)", "count": 2 }));
+    assert_eq!(json["unmapped"], json!(0));
+}
+
+#[test]
+fn test_finding_counts_output_schema(){
+    let counts = vec![GroupedCount { name: "High".to_string(), count: 3 }];
+    let json = ::serde_json::to_value(FindingCountsOutput::new(5, CountGroup::Severity, &counts)).unwrap();
+    assert_eq!(json, json!({
+        "schemaVersion": 1,
+        "projectId": 5,
+        "groupBy": "severity",
+        "counts": [{ "name": "High", "count": 3 }],
+    }));
+    let empty = ::serde_json::to_value(FindingCountsOutput::new(5, CountGroup::Cwe, &[])).unwrap();
+    assert_eq!(empty["counts"], json!([]));
+}
+
+#[test]
+fn test_findings_output_schema(){
+    let findings: Vec<ApiFinding> = ::serde_json::from_value(json!([{ "id": 12, "severity": "High" }])).unwrap();
+    let json = ::serde_json::to_value(FindingsOutput::new(5, &findings, &Url::parse("https://localhost/codedx/").unwrap())).unwrap();
+    assert_eq!(json["schemaVersion"], json!(1));
+    assert_eq!(json["projectId"], json!(5));
+    assert_eq!(json["findings"][0]["id"], json!(12));
+    assert_eq!(json["findings"][0]["severity"], json!("High"));
+    assert_eq!(json["findings"][0]["url"], json!("https://localhost/codedx/findings/12"));
+}
+
+#[test]
+fn test_analyses_output_schema(){
+    let analyses: Vec<ApiAnalysis> = ::serde_json::from_value(json!([
+        { "id": 9, "name": "nightly", "state": "complete", "creationTime": "2017-10-05T11:58:00Z", "startTime": "2017-10-05T11:59:00Z", "finishTime": "2017-10-05T12:00:00.250+0000",
+          "inputs": [{ "name": "webapp.war", "tools": ["FindBugs"] }] },
+        { "id": 8 },
+    ])).unwrap();
+    let json: ::serde_json::Value = ::serde_json::from_str(&analyses.render(OutputFormat::Json)).unwrap();
+    assert_eq!(json, json!([
+        {
+            "id": 9, "name": "nightly", "state": "complete", "status": "completed",
+            "creationTime": "2017-10-05T11:58:00Z", "startTime": "2017-10-05T11:59:00Z", "finishTime": "2017-10-05T12:00:00Z",
+            "inputs": [{ "name": "webapp.war", "tools": ["FindBugs"] }],
+        },
+        {
+            "id": 8, "name": null, "state": null, "status": "unknown",
+            "creationTime": null, "startTime": null, "finishTime": null, "inputs": [],
+        },
+    ]));
+}
+
+#[test]
+fn test_project_metadata_output_schema(){
+    let fields = vec![
+        ProjectMetadataField { id: 1, name: "Team".to_string(), value: Some("Payments".to_string()) },
+        ProjectMetadataField { id: 2, name: "Criticality".to_string(), value: None },
+    ];
+    let json = ::serde_json::to_value(ProjectMetadataOutput::new(5, &fields)).unwrap();
+    assert_eq!(json, json!({
+        "schemaVersion": 1,
+        "projectId": 5,
+        "fields": [
+            { "id": 1, "name": "Team", "value": "Payments" },
+            { "id": 2, "name": "Criticality", "value": null },
+        ],
+    }));
+}
+
+#[test]
+fn test_branches_schema(){
+    let branches = vec![ApiBranch { id: 10, name: "main".to_string(), is_default: true }];
+    let json: ::serde_json::Value = ::serde_json::from_str(&branches.render(OutputFormat::Json)).unwrap();
+    assert_eq!(json, json!([{ "id": 10, "name": "main", "isDefault": true }]));
+}
+
+#[test]
+fn test_statuses_schema(){
+    let statuses = vec![
+        FindingStatus { id: "false-positive".to_string(), name: "False Positive".to_string(), custom: false },
+        FindingStatus { id: "7".to_string(), name: "Vendor Code".to_string(), custom: true },
+    ];
+    let json = ::serde_json::to_value(StatusesOutput::new(5, &statuses)).unwrap();
+    assert_eq!(json, json!({
+        "schemaVersion": 1,
+        "projectId": 5,
+        "statuses": [
+            { "id": "false-positive", "name": "False Positive", "custom": false },
+            { "id": "7", "name": "Vendor Code", "custom": true },
+        ],
+    }));
+}
+
+#[test]
+fn test_job_status_schema(){
+    let mut job = JobStatusResponse::from_status("1b9e4c2a", JobStatus::Running);
+    job.progress = Some(JobProgress { percent: Some(40.0), message: Some("Analyzing files".to_string()) });
+    let json = ::serde_json::to_value(JobStatusOutput::from(&job)).unwrap();
+    assert_eq!(json, json!({
+        "schemaVersion": 1,
+        "jobId": "1b9e4c2a",
+        "status": "running",
+        "progress": { "percent": 40.0, "message": "Analyzing files" },
+        "reason": null,
+        "blockedBy": [],
+    }));
+}
+
+#[test]
+fn test_utc_timestamp(){
+    assert_eq!(utc_timestamp("2017-10-05T12:00:00Z"), "2017-10-05T12:00:00Z");
+    assert_eq!(utc_timestamp("2017-10-05T12:00:00.123+0000"), "2017-10-05T12:00:00Z");
+    assert_eq!(utc_timestamp("2017-10-05T08:30:00-04:00"), "2017-10-05T12:30:00Z");
+    assert_eq!(utc_timestamp("2017-10-06T01:00:00+02:00"), "2017-10-05T23:00:00Z");
+    assert_eq!(utc_timestamp("last tuesday"), "last tuesday");
+}
+
+#[test]
+fn test_finding_output_windows_path(){
+    let finding: ApiFinding = ::serde_json::from_value(json!({
+        "id": 3,
+        "primaryLocation": { "path": "src\\main\\java\\Login.java" },
+    })).unwrap();
+    let json = ::serde_json::to_value(FindingOutput::new(&finding, String::new())).unwrap();
+    assert_eq!(json["path"], json!("src/main/java/Login.java"));
+    assert_eq!(json["originalPath"], json!("src\\main\\java\\Login.java"));
+}
+
+#[test]
+fn test_machine_output_ignores_locale(){
+    use std::env;
+    use std::process::Command;
+    // the checks run in a copy of this test binary that's given a German locale, rather than changing
+    // this process's environment under the tests running next to it
+    if env::var_os("CODEDX_CLIENT_LOCALE_TEST").is_none() {
+        let output = Command::new(env::current_exe().unwrap())
+            .args(&["--exact", "output::test_machine_output_ignores_locale", "--test-threads=1"])
+            .env("CODEDX_CLIENT_LOCALE_TEST", "1")
+            // which would write 1234567 as "1.234.567" and 0.5 as "0,5" if anything consulted it
+            .env("LC_ALL", "de_DE.UTF-8")
+            .env("LANG", "de_DE.UTF-8")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success() && stdout.contains("1 passed"), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+        return;
+    }
+
+    let coverage = ::standards::bucket(::standards::Standard::Owasp2021, &[GroupedCount { name: "CWE-89".to_string(), count: 1234567 }]);
+    let csv = coverage.render_csv();
+    assert!(csv.contains("A03:2021,Injection,1234567\n"));
+    assert!(!csv.contains('\r'));
+
+    let json = ::serde_json::to_string(&StandardsOutput::new(5, None, &coverage)).unwrap();
+    assert!(json.contains("\"count\":1234567"));
+    assert_eq!(::serde_json::to_string(&json!({ "ratio": 0.5 })).unwrap(), "{\"ratio\":0.5}");
+
+    assert_eq!(utc_timestamp("2017-10-05T14:00:00+02:00"), "2017-10-05T12:00:00Z");
+}
+
+#[test]
+fn test_targets_output_schema(){
+    use targets::AnalysisTarget;
+    let results = vec![
+        TargetResult {
+            target: AnalysisTarget { project: "web".to_string(), files: vec!["build\\scan.xml".into()], fail_on: None },
+            project_id: Some(3),
+            analysis_id: Some(40),
+            job_id: Some("job-40".to_string()),
+            outcome: TargetOutcome::Finished(JobStatus::Completed),
+        },
+        TargetResult {
+            target: AnalysisTarget { project: "api".to_string(), files: vec!["api.xml".into()], fail_on: None },
+            project_id: None,
+            analysis_id: None,
+            job_id: None,
+            outcome: TargetOutcome::Error { stage: "project", message: "no project named \"api\"".to_string() },
+        },
+    ];
+    let json = ::serde_json::to_value(TargetsOutput::from(&results[..])).unwrap();
+    assert_eq!(json, json!({
+        "schemaVersion": 1,
+        "targets": [
+            {
+                "project": "web",
+                "projectId": 3,
+                "files": ["build/scan.xml"],
+                "originalFiles": ["build\\scan.xml"],
+                "analysisId": 40,
+                "status": "completed",
+                "error": null,
+            },
+            {
+                "project": "api",
+                "projectId": null,
+                "files": ["api.xml"],
+                "originalFiles": ["api.xml"],
+                "analysisId": null,
+                "status": null,
+                "error": "error (project): no project named \"api\"",
+            },
+        ],
+    }));
+}
+
+#[test]
+fn test_csv_field(){
+    assert_eq!(csv_field("WebGoat"), "WebGoat");
+    assert_eq!(csv_field("Tom, Dick"), "\"Tom, Dick\"");
+    assert_eq!(csv_field("the \"new\" one"), "\"the \"\"new\"\" one\"");
+    assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    assert_eq!(csv_field(""), "");
+}
+
+#[test]
+fn test_render_projects(){
+    let projects = vec![
+        ApiProject { id: 5, name: "WebGoat".to_string(), parent_id: None },
+        ApiProject { id: 12, name: "WebGoat, Legacy".to_string(), parent_id: Some(5) },
+    ];
+    assert_eq!(projects.render(OutputFormat::Table), "ID  NAME             PARENT\n5   WebGoat\n12  WebGoat, Legacy  5\n");
+    assert_eq!(projects.render(OutputFormat::Csv), "ID,NAME,PARENT\n5,WebGoat,\n12,\"WebGoat, Legacy\",5\n");
+    let json: ::serde_json::Value = ::serde_json::from_str(&projects.render(OutputFormat::Json)).unwrap();
+    assert_eq!(json, json!([
+        { "schemaVersion": 1, "id": 5, "name": "WebGoat", "parentId": null },
+        { "schemaVersion": 1, "id": 12, "name": "WebGoat, Legacy", "parentId": 5 },
+    ]));
+}
+
+#[test]
+fn test_render_empty_listings(){
+    let projects: Vec<ApiProject> = Vec::new();
+    assert_eq!(projects.render(OutputFormat::Json), "[]\n");
+    assert_eq!(projects.render(OutputFormat::Csv), "ID,NAME,PARENT\n");
+    assert_eq!(projects.render(OutputFormat::Table), "no results\n");
+    let branches: Vec<ApiBranch> = Vec::new();
+    assert_eq!(branches.render(OutputFormat::Json), "[]\n");
+}
+
+#[test]
+fn test_parse_output_format(){
+    assert_eq!("csv".parse::<OutputFormat>(), Ok(OutputFormat::Csv));
+    assert_eq!("table".parse::<OutputFormat>(), Ok(OutputFormat::Table));
+    assert!("yaml".parse::<OutputFormat>().is_err());
+}
+
+#[test]
+fn test_error_output_schema(){
+    let error = ApiError::NonSuccess { status: ::StatusCode::NotFound, message: ApiErrorMessage::Nice("Project not found".to_string()), request: Some("GET /codedx/x/projects/5/analyses".to_string()) };
+    let json = ::serde_json::to_value(ErrorOutput::from_api_error("loading analyses for project 5", &error)).unwrap();
+    assert_eq!(json, json!({
+        "schemaVersion": 1,
+        "kind": "non_success",
+        "status": 404,
+        "message": "Project not found",
+        "operation": "loading analyses for project 5",
+        "request": "GET /codedx/x/projects/5/analyses",
+    }));
+    let failure = ::serde_json::to_value(ErrorOutput::new("timed_out", "gave up waiting for the analysis to finish")).unwrap();
+    assert_eq!(failure["kind"], json!("timed_out"));
+    assert_eq!(failure["status"], json!(null));
+}
@@ -0,0 +1,581 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! `PollingStrategy` implementations beyond the fixed interval of a plain `Duration`.
+
+use client::{ApiClient, ApiError, JobStatus, JobStatusResponse, PollingStrategy};
+use serde_json;
+use std::cmp;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Exit code for the CLI giving up on a job before it finished (see `BoundedPoll`),
+/// as opposed to `1` for a job that failed.
+pub const EXIT_TIMED_OUT: i32 = 3;
+
+/// Exit code for the CLI when a job it was asked about was cancelled (see the `job-status` command),
+/// as opposed to `1` for one that failed.
+pub const EXIT_CANCELLED: i32 = 5;
+
+/// Exit code for the `job-status` command when the job failed, as opposed to `1` for not being able to check it.
+pub const EXIT_JOB_FAILED: i32 = 6;
+
+/// Exit code for the `job-status` command (without `--wait`) when the job is still queued or running.
+pub const EXIT_JOB_PENDING: i32 = 7;
+
+/// The exit code for a job that ended up with `status`, after waiting for it (`waited`) or just looking.
+///
+/// A job that isn't done yet is `EXIT_TIMED_OUT` only when waiting gave up on it.
+pub fn job_exit_code(status: JobStatus, waited: bool) -> i32 {
+    match status {
+        JobStatus::Completed => 0,
+        JobStatus::Failed => EXIT_JOB_FAILED,
+        JobStatus::Cancelled => EXIT_CANCELLED,
+        _ if waited => EXIT_TIMED_OUT,
+        _ => EXIT_JOB_PENDING,
+    }
+}
+
+/// Polling strategy that starts with short waits, and makes each wait longer than the last.
+///
+/// The first wait is `initial`; each one after that is `multiplier` times longer, until the waits
+/// reach `max`, after which every wait is `max`. Short jobs are noticed quickly, without long jobs
+/// being checked on every few seconds for their whole run.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExponentialBackoff {
+    pub initial: Duration,
+    pub multiplier: f64,
+    pub max: Duration,
+}
+
+impl ExponentialBackoff {
+    /// A backoff from `initial` to `max` that doubles the wait each time.
+    pub fn new(initial: Duration, max: Duration) -> ExponentialBackoff {
+        ExponentialBackoff { initial, multiplier: 2.0, max }
+    }
+
+    pub fn multiplier(self, multiplier: f64) -> ExponentialBackoff {
+        ExponentialBackoff { multiplier, ..self }
+    }
+
+    /// How long to wait after the given (1-based) iteration.
+    pub fn wait_for(&self, iteration_number: usize) -> Duration {
+        let exponent = iteration_number.saturating_sub(1) as f64;
+        let secs = duration_secs(self.initial) * self.multiplier.powf(exponent);
+        // the float math saturates to infinity (or NaN, with a silly multiplier) rather than overflowing,
+        // and both of those fail this comparison, so a huge wait never makes it into a `Duration`
+        if secs < duration_secs(self.max) {
+            let whole = secs.floor();
+            Duration::new(whole as u64, ((secs - whole) * 1e9) as u32)
+        } else {
+            self.max
+        }
+    }
+}
+
+impl <T> PollingStrategy<T> for ExponentialBackoff {
+    fn next_wait(&self, iteration_number: usize, _state: &T) -> Option<Duration> {
+        Some(self.wait_for(iteration_number))
+    }
+}
+
+/// A polling strategy chosen at runtime, e.g. from a command line argument like `2s` or `backoff:5s..2m`.
+///
+/// Parses from:
+///
+///  - a duration like `2s`, for a fixed interval,
+///  - `backoff:INITIAL..MAX` for an `ExponentialBackoff` that doubles each time, or
+///  - `backoff:INITIAL..MAX*MULTIPLIER` for one with some other multiplier.
+///
+/// Durations are a number followed by `ms`, `s`, `m` or `h`; a number alone means seconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PollInterval {
+    Fixed(Duration),
+    Backoff(ExponentialBackoff),
+}
+
+impl <T> PollingStrategy<T> for PollInterval {
+    fn next_wait(&self, iteration_number: usize, state: &T) -> Option<Duration> {
+        match *self {
+            PollInterval::Fixed(ref interval) => interval.next_wait(iteration_number, state),
+            PollInterval::Backoff(ref backoff) => backoff.next_wait(iteration_number, state),
+        }
+    }
+}
+
+impl FromStr for PollInterval {
+    type Err = ();
+
+    fn from_str(raw: &str) -> Result<PollInterval, ()> {
+        let raw = raw.trim();
+        if raw.starts_with("backoff:") {
+            let spec = &raw["backoff:".len()..];
+            let (range, multiplier) = match spec.find('*') {
+                Some(index) => (&spec[..index], spec[index + 1..].trim().parse::<f64>().map_err(|_| ())?),
+                None => (spec, 2.0),
+            };
+            let index = range.find("..").ok_or(())?;
+            let initial = parse_duration(&range[..index]).ok_or(())?;
+            let max = parse_duration(&range[index + 2..]).ok_or(())?;
+            if !(multiplier >= 1.0) || initial > max {
+                return Err(());
+            }
+            Ok(PollInterval::Backoff(ExponentialBackoff { initial, multiplier, max }))
+        } else {
+            parse_duration(raw).map(PollInterval::Fixed).ok_or(())
+        }
+    }
+}
+
+/// Decorates another polling strategy, giving up after a number of iterations or an amount of time.
+///
+/// A job can sit in `Queued` indefinitely (e.g. when the server has no free analysis slots);
+/// without a bound, polling it never ends. When a bound is reached, `next_wait` returns `None`,
+/// so the poll ends with whatever status the job had last.
+///
+/// ```
+/// # use codedx_client::polling::BoundedPoll;
+/// # use std::time::Duration;
+/// let poll = BoundedPoll::new(Duration::from_secs(5))
+///     .max_iterations(100)
+///     .timeout(Duration::from_secs(30 * 60));
+/// ```
+#[derive(Debug)]
+pub struct BoundedPoll<P> {
+    inner: P,
+    max_iterations: Option<usize>,
+    timeout: Option<Duration>,
+    /// When the current poll began (at its first check), which is what `timeout` counts from.
+    started: Mutex<Option<Instant>>,
+}
+
+impl <P> BoundedPoll<P> {
+    /// Wrap `inner`, with no bounds yet.
+    pub fn new(inner: P) -> BoundedPoll<P> {
+        BoundedPoll { inner, max_iterations: None, timeout: None, started: Mutex::new(None) }
+    }
+
+    /// Give up after checking the state `max` times.
+    pub fn max_iterations(self, max: usize) -> BoundedPoll<P> {
+        BoundedPoll { max_iterations: Some(max), ..self }
+    }
+
+    /// Give up once `timeout` has passed, counting from the first check of each poll this is used for.
+    pub fn timeout(self, timeout: Duration) -> BoundedPoll<P> {
+        BoundedPoll { timeout: Some(timeout), ..self }
+    }
+}
+
+impl <P> BoundedPoll<Option<P>> {
+    /// Poll with `default` if no strategy was given (e.g. `--poll` was left out), keeping the bounds.
+    pub fn with_default(self, default: P) -> BoundedPoll<P> {
+        BoundedPoll { inner: self.inner.unwrap_or(default), max_iterations: self.max_iterations, timeout: self.timeout, started: self.started }
+    }
+}
+
+/// A clone hasn't started polling yet, so its `timeout` counts from its own first check.
+impl <P: Clone> Clone for BoundedPoll<P> {
+    fn clone(&self) -> BoundedPoll<P> {
+        BoundedPoll { inner: self.inner.clone(), max_iterations: self.max_iterations, timeout: self.timeout, started: Mutex::new(None) }
+    }
+}
+
+impl <T, P: PollingStrategy<T>> PollingStrategy<T> for BoundedPoll<P> {
+    fn next_wait(&self, iteration_number: usize, state: &T) -> Option<Duration> {
+        let started = {
+            let mut started = self.started.lock().unwrap();
+            // the first iteration is the start of a (new) poll
+            if iteration_number <= 1 || started.is_none() {
+                *started = Some(Instant::now());
+            }
+            started.unwrap()
+        };
+        if let Some(max) = self.max_iterations {
+            if iteration_number >= max {
+                return None;
+            }
+        }
+        let wait = self.inner.next_wait(iteration_number, state)?;
+        match self.timeout {
+            // don't oversleep the deadline; the check after the shortened wait is the last one
+            Some(timeout) => {
+                let elapsed = started.elapsed();
+                if elapsed >= timeout { None } else { Some(cmp::min(wait, timeout - elapsed)) }
+            },
+            None => Some(wait),
+        }
+    }
+}
+
+/// Something that wants to hear about each state a poll sees, e.g. to show progress.
+pub trait PollObserver<T> {
+    fn on_status(&self, iteration_number: usize, state: &T);
+}
+
+impl <T, F> PollObserver<T> for F
+    where F: Fn(usize, &T)
+{
+    fn on_status(&self, iteration_number: usize, state: &T) {
+        self(iteration_number, state)
+    }
+}
+
+/// A `PollObserver` that reports each iteration as a progress message of the client's (see `ApiClient::progress`),
+/// e.g. `# Polling job completion, iteration 3: status = Running`.
+#[derive(Copy, Clone)]
+pub struct ShowProgress<'a>(pub &'a ApiClient);
+
+impl <'a> PollObserver<JobStatus> for ShowProgress<'a> {
+    fn on_status(&self, iteration_number: usize, state: &JobStatus) {
+        self.0.progress(&format!("Polling job completion, iteration {}: status = {:?}", iteration_number, state));
+    }
+}
+
+/// Includes the job's progress and what it's waiting for, e.g. `status = Running, 40% (Analyzing files)`.
+impl <'a> PollObserver<JobStatusResponse> for ShowProgress<'a> {
+    fn on_status(&self, iteration_number: usize, state: &JobStatusResponse) {
+        self.0.progress(&format!("Polling job completion, iteration {}: status = {}", iteration_number, state.describe()));
+    }
+}
+
+/// Decorates a polling strategy with a `PollObserver`, which is told about every state
+/// that the poll sees before it finishes (the final state is what the poll returns).
+///
+/// ```no_run
+/// # use codedx_client::prelude::*;
+/// # use codedx_client::polling::{Observed, ShowProgress};
+/// # use std::time::Duration;
+/// # fn example(client: &ApiClient, job_id: &str) -> ApiResult<JobStatus> {
+/// client.poll_job_completion(job_id, Observed::new(Duration::from_secs(2), ShowProgress(client)))
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Observed<P, O> {
+    inner: P,
+    observer: O,
+}
+
+impl <P, O> Observed<P, O> {
+    pub fn new(inner: P, observer: O) -> Observed<P, O> {
+        Observed { inner, observer }
+    }
+}
+
+impl <T, P: PollingStrategy<T>, O: PollObserver<T>> PollingStrategy<T> for Observed<P, O> {
+    fn next_wait(&self, iteration_number: usize, state: &T) -> Option<Duration> {
+        self.observer.on_status(iteration_number, state);
+        self.inner.next_wait(iteration_number, state)
+    }
+}
+
+/// How the CLI shows a poll's progress (`--poll-output`).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PollOutput {
+    /// Progress messages on STDERR, and nothing else (the default).
+    Human,
+    /// Besides the messages, one JSON object per iteration on STDOUT (see `JsonLines`), and nothing else there.
+    JsonLines,
+}
+
+impl Default for PollOutput {
+    fn default() -> PollOutput {
+        PollOutput::Human
+    }
+}
+
+impl FromStr for PollOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PollOutput, String> {
+        match s {
+            "human" => Ok(PollOutput::Human),
+            "jsonl" => Ok(PollOutput::JsonLines),
+            other => Err(format!("unknown poll output \"{}\"; the choices are: human, jsonl", other)),
+        }
+    }
+}
+
+/// A `PollObserver` that writes one line of JSON to STDOUT for each iteration of a poll, flushed right away
+/// so that e.g. a CI UI can show it live:
+///
+/// ```text
+/// {"iteration":3,"jobId":"1b9e4c2a","status":"running","progress":42,"elapsedSeconds":95}
+/// ```
+///
+/// An observer only sees the states before the last one, so the line with the final status is written
+/// by `finish`, once the poll returns; if the poll fails instead, `fail` writes a last line with a `null`
+/// status and the `error`, so a reader never waits for a final line that isn't coming. `progress` is the job's percentage (if the server says), and
+/// `elapsedSeconds` counts from when the `JsonLines` was made. Clones write the same sequence of lines.
+#[derive(Clone, Debug)]
+pub struct JsonLines {
+    job_id: Option<String>,
+    started: Instant,
+    last_iteration: Arc<AtomicUsize>,
+}
+
+impl JsonLines {
+    /// Lines for the poll of `job_id`, or of something without a job id (e.g. an analysis found by
+    /// `ApiClient::poll_analysis_completion`), whose lines have a `null` one.
+    pub fn new(job_id: Option<&str>) -> JsonLines {
+        JsonLines { job_id: job_id.map(|id| id.to_string()), started: Instant::now(), last_iteration: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    /// Write the line with the final status, numbered after the last line `on_status` wrote.
+    pub fn finish(&self, status: JobStatus, percent: Option<f64>) {
+        let iteration = self.last_iteration.load(Ordering::SeqCst) + 1;
+        self.write(iteration, Ok(status), percent);
+    }
+
+    /// Write the last line for a poll that failed with `error`, numbered like `finish`'s.
+    pub fn fail(&self, error: &str) {
+        let iteration = self.last_iteration.load(Ordering::SeqCst) + 1;
+        self.write(iteration, Err(error), None);
+    }
+
+    /// One line (without its line break), for a status or the error that ended the poll.
+    pub fn line(&self, iteration: usize, status: Result<JobStatus, &str>, percent: Option<f64>, elapsed: Duration) -> String {
+        serde_json::to_string(&JsonLine {
+            iteration,
+            job_id: self.job_id.as_ref().map(|id| id.as_str()),
+            status: status.ok(),
+            progress: percent.map(|p| p.round() as u64),
+            elapsed_seconds: elapsed.as_secs(),
+            error: status.err(),
+        }).unwrap()
+    }
+
+    fn write(&self, iteration: usize, status: Result<JobStatus, &str>, percent: Option<f64>) {
+        self.last_iteration.store(iteration, Ordering::SeqCst);
+        let line = self.line(iteration, status, percent, self.started.elapsed());
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        // a reader that went away (e.g. a closed pipe) shouldn't stop the poll
+        let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+    }
+}
+
+// a struct rather than `json!`, to keep the fields in this order
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    iteration: usize,
+    #[serde(rename = "jobId")]
+    job_id: Option<&'a str>,
+    status: Option<JobStatus>,
+    progress: Option<u64>,
+    #[serde(rename = "elapsedSeconds")]
+    elapsed_seconds: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+impl PollObserver<JobStatus> for JsonLines {
+    fn on_status(&self, iteration_number: usize, state: &JobStatus) {
+        self.write(iteration_number, Ok(*state), None);
+    }
+}
+
+impl PollObserver<JobStatusResponse> for JsonLines {
+    fn on_status(&self, iteration_number: usize, state: &JobStatusResponse) {
+        self.write(iteration_number, Ok(state.status), state.progress.as_ref().and_then(|p| p.percent));
+    }
+}
+
+/// The `PollObserver` for a CLI command's poll: `ShowProgress`, plus `JsonLines` for `PollOutput::JsonLines`.
+#[derive(Clone)]
+pub struct CommandProgress<'a> {
+    show: ShowProgress<'a>,
+    json: Option<JsonLines>,
+}
+
+impl <'a> CommandProgress<'a> {
+    pub fn new(client: &'a ApiClient, output: PollOutput, job_id: Option<&str>) -> CommandProgress<'a> {
+        let json = match output {
+            PollOutput::Human => None,
+            PollOutput::JsonLines => Some(JsonLines::new(job_id)),
+        };
+        CommandProgress { show: ShowProgress(client), json }
+    }
+
+    /// Write the final JSON line, if there are JSON lines (see `JsonLines::finish`).
+    pub fn finish(&self, status: JobStatus, percent: Option<f64>) {
+        if let Some(ref json) = self.json {
+            json.finish(status, percent);
+        }
+    }
+
+    /// Write the final JSON line for a poll that failed, if there are JSON lines (see `JsonLines::fail`).
+    pub fn fail(&self, error: &ApiError) {
+        if let Some(ref json) = self.json {
+            json.fail(&error.to_string());
+        }
+    }
+}
+
+impl <'a, T> PollObserver<T> for CommandProgress<'a>
+    where ShowProgress<'a>: PollObserver<T>, JsonLines: PollObserver<T>
+{
+    fn on_status(&self, iteration_number: usize, state: &T) {
+        self.show.on_status(iteration_number, state);
+        if let Some(ref json) = self.json {
+            json.on_status(iteration_number, state);
+        }
+    }
+}
+
+/// Parse a duration like `500ms`, `5s`, `2m` or `1h` (or `5`, meaning seconds).
+pub fn parse_duration(raw: &str) -> Option<Duration> {
+    let raw = raw.trim();
+    let split = raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len());
+    let (number, unit) = raw.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    match unit {
+        "ms" => Some(Duration::from_millis(number)),
+        "" | "s" => Some(Duration::from_secs(number)),
+        "m" => number.checked_mul(60).map(Duration::from_secs),
+        "h" => number.checked_mul(3600).map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+fn duration_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+#[test]
+fn test_backoff_grows_then_stays_at_max(){
+    let backoff = ExponentialBackoff::new(Duration::from_secs(5), Duration::from_secs(120));
+    let waits: Vec<u64> = (1..8).map(|i| backoff.wait_for(i).as_secs()).collect();
+    assert_eq!(waits, vec![5, 10, 20, 40, 80, 120, 120]);
+    assert_eq!(backoff.wait_for(0), Duration::from_secs(5));
+}
+
+#[test]
+fn test_backoff_does_not_overflow(){
+    let backoff = ExponentialBackoff::new(Duration::from_secs(1), Duration::from_secs(60)).multiplier(10.0);
+    assert_eq!(backoff.wait_for(10_000), Duration::from_secs(60));
+    assert_eq!(backoff.wait_for(usize::max_value()), Duration::from_secs(60));
+
+    let huge = ExponentialBackoff::new(Duration::from_secs(u64::max_value() / 2), Duration::from_secs(u64::max_value()));
+    assert_eq!(huge.wait_for(5), Duration::from_secs(u64::max_value()));
+}
+
+#[test]
+fn test_backoff_fractional_multiplier(){
+    let backoff = ExponentialBackoff::new(Duration::from_millis(1000), Duration::from_secs(10)).multiplier(1.5);
+    assert_eq!(backoff.wait_for(2), Duration::from_millis(1500));
+    assert_eq!(backoff.wait_for(3), Duration::from_millis(2250));
+}
+
+#[test]
+fn test_parse_poll_interval(){
+    assert_eq!("2s".parse::<PollInterval>(), Ok(PollInterval::Fixed(Duration::from_secs(2))));
+    assert_eq!("750ms".parse::<PollInterval>(), Ok(PollInterval::Fixed(Duration::from_millis(750))));
+    assert_eq!("backoff:5s..2m".parse::<PollInterval>(), Ok(PollInterval::Backoff(ExponentialBackoff::new(Duration::from_secs(5), Duration::from_secs(120)))));
+    assert_eq!(
+        "backoff:1s..1h*1.5".parse::<PollInterval>(),
+        Ok(PollInterval::Backoff(ExponentialBackoff::new(Duration::from_secs(1), Duration::from_secs(3600)).multiplier(1.5)))
+    );
+    assert_eq!("backoff:2m..5s".parse::<PollInterval>(), Err(()));
+    assert_eq!("backoff:5s..2m*0.5".parse::<PollInterval>(), Err(()));
+    assert_eq!("backoff:5s".parse::<PollInterval>(), Err(()));
+    assert_eq!("2 weeks".parse::<PollInterval>(), Err(()));
+}
+
+#[test]
+fn test_bounded_poll_iteration_cap(){
+    let poll = BoundedPoll::new(Duration::from_millis(1)).max_iterations(3);
+    assert_eq!(poll.next_wait(1, &"queued"), Some(Duration::from_millis(1)));
+    assert_eq!(poll.next_wait(2, &"queued"), Some(Duration::from_millis(1)));
+    assert_eq!(poll.next_wait(3, &"queued"), None);
+}
+
+#[test]
+fn test_bounded_poll_wall_clock_cap(){
+    let poll = BoundedPoll::new(Duration::from_secs(60)).timeout(Duration::from_millis(50));
+    // the wait is cut short so the deadline isn't overslept
+    let wait = poll.next_wait(1, &"queued").unwrap();
+    assert!(wait <= Duration::from_millis(50));
+    ::std::thread::sleep(wait);
+    assert_eq!(poll.next_wait(2, &"queued"), None);
+
+    // the clock starts with the poll, not when the bound was set, and again with each new poll
+    let poll = BoundedPoll::new(Duration::from_secs(60)).timeout(Duration::from_millis(50));
+    ::std::thread::sleep(Duration::from_millis(80));
+    assert!(poll.next_wait(1, &"queued").is_some());
+    ::std::thread::sleep(Duration::from_millis(80));
+    assert_eq!(poll.next_wait(2, &"queued"), None);
+    assert!(poll.clone().next_wait(2, &"queued").is_some());
+    assert!(poll.next_wait(1, &"queued").is_some());
+}
+
+#[test]
+fn test_bounded_poll_stuck_queued_job(){
+    use client::JobStatus;
+    use test_server::*;
+    let server = TestServer::start(|_| TestResponse::json(200, json!({ "jobId": "stuck", "status": "queued" })));
+    let client = server.client();
+
+    let by_count = BoundedPoll::new(Duration::from_millis(1)).max_iterations(4);
+    assert_eq!(client.poll_job_completion("stuck", by_count).unwrap(), JobStatus::Queued);
+    assert_eq!(server.requests().len(), 4);
+
+    let started = Instant::now();
+    let by_time = BoundedPoll::new(Duration::from_millis(10)).timeout(Duration::from_millis(100));
+    assert_eq!(client.poll_job_completion("stuck", by_time).unwrap(), JobStatus::Queued);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_observed_poll(){
+    let seen = ::std::cell::RefCell::new(Vec::new());
+    {
+        let poll = Observed::new(BoundedPoll::new(Duration::from_millis(1)).max_iterations(2), |i: usize, state: &&str| {
+            seen.borrow_mut().push(format!("{} {}", i, state));
+        });
+        assert_eq!(poll.next_wait(1, &"queued"), Some(Duration::from_millis(1)));
+        assert_eq!(poll.next_wait(2, &"running"), None);
+    }
+    assert_eq!(*seen.borrow(), vec!["1 queued", "2 running"]);
+}
+
+#[test]
+fn test_json_lines(){
+    let lines = JsonLines::new(Some("1b9e4c2a"));
+    assert_eq!(lines.line(3, Ok(JobStatus::Running), Some(41.6), Duration::from_millis(95_400)),
+        r#"{"iteration":3,"jobId":"1b9e4c2a","status":"running","progress":42,"elapsedSeconds":95}"#);
+    assert_eq!(JsonLines::new(None).line(1, Ok(JobStatus::Completed), None, Duration::from_secs(0)),
+        r#"{"iteration":1,"jobId":null,"status":"completed","progress":null,"elapsedSeconds":0}"#);
+    assert_eq!(lines.line(4, Err("job 1b9e4c2a was not found"), None, Duration::from_secs(100)),
+        r#"{"iteration":4,"jobId":"1b9e4c2a","status":null,"progress":null,"elapsedSeconds":100,"error":"job 1b9e4c2a was not found"}"#);
+    assert_eq!("jsonl".parse::<PollOutput>(), Ok(PollOutput::JsonLines));
+    assert!("json".parse::<PollOutput>().is_err());
+}
+
+#[test]
+fn test_job_exit_code(){
+    assert_eq!(job_exit_code(JobStatus::Completed, false), 0);
+    assert_eq!(job_exit_code(JobStatus::Completed, true), 0);
+    assert_eq!(job_exit_code(JobStatus::Failed, true), EXIT_JOB_FAILED);
+    assert_eq!(job_exit_code(JobStatus::Cancelled, false), EXIT_CANCELLED);
+    assert_eq!(job_exit_code(JobStatus::Running, false), EXIT_JOB_PENDING);
+    assert_eq!(job_exit_code(JobStatus::Queued, false), EXIT_JOB_PENDING);
+    assert_eq!(job_exit_code(JobStatus::Running, true), EXIT_TIMED_OUT);
+    assert_eq!(job_exit_code(JobStatus::Unknown, true), EXIT_TIMED_OUT);
+    assert!(![EXIT_JOB_FAILED, EXIT_JOB_PENDING].contains(&1));
+}
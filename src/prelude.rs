@@ -0,0 +1,82 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Re-exports of the types most commonly needed when using this crate as a library.
+//!
+//! ```
+//! use codedx_client::prelude::*;
+//! ```
+
+pub use client::{
+    AnalysisStart,
+    ApiAnalysis,
+    ApiAnalysisInput,
+    ApiAnalysisJobResponse,
+    ApiAnalysisPrep,
+    ApiBranch,
+    ApiClient,
+    ApiError,
+    ApiErrorMessage,
+    ApiFinding,
+    ApiJob,
+    ApiPrepInput,
+    ApiPrepTag,
+    ApiPrepUploadResponse,
+    ApiProject,
+    ApiProjectField,
+    ApiProjectUpdate,
+    ApiProjectWithBranches,
+    ApiResponse,
+    ApiResult,
+    BranchSpec,
+    ConnectionReport,
+    CountGroup,
+    FindingExpand,
+    FindingFilter,
+    FindingQuery,
+    FindingSort,
+    FindingStatus,
+    GroupedCount,
+    JobProgress,
+    JobStatus,
+    JobStatusResponse,
+    MetadataMatch,
+    Pagination,
+    PdfReportOptions,
+    PdfSummaryMode,
+    PollingStrategy,
+    ProjectFilter,
+    ProjectMetadataField,
+    ReportKind,
+    ReportOptions,
+    ResponseMeta,
+    ReqBody,
+    SaveOptions,
+    SortDirection,
+    SystemInfo,
+};
+#[allow(deprecated)]
+pub use client::ApiProjectFilter;
+pub use api::CodeDxApi;
+#[cfg(feature = "async")] pub use async_client::{ApiFuture, AsyncApiClient};
+pub use check::ConnectionProblem;
+pub use config::{ClientAuth, ClientConfig, ConfigError, ErrorFormat, Verbosity};
+pub use gate::SeverityGate;
+pub use polling::{ExponentialBackoff, PollInterval};
+pub use targets::AnalysisTarget;
+pub use upload::{UploadFile, UploadInput};
+pub use StatusCode;
+pub use Url;
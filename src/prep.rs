@@ -0,0 +1,354 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checking analysis inputs with an analysis prep, before (or instead of) analyzing them.
+//!
+//! `prepare_inputs` uploads each file to a new prep (or `upload_inputs`, to one created already),
+//! waits for the server to identify it, and collects what the server had to say about it into a
+//! `PrepReport`. Unless that fails, the prep is left in place,
+//! so the caller can either run an analysis from it (`ApiClient::run_prep_analysis`) or throw it
+//! away (`ApiClient::delete_analysis_prep`). Before running it, `disable_tools` can keep some of
+//! the tools the server picked from analyzing anything.
+
+use client::*;
+use std::fmt::Write;
+use upload::UploadFile;
+
+/// What the server said about one uploaded file.
+#[derive(Debug)]
+pub struct InputReport {
+    /// The file, as it was given.
+    pub file: String,
+    /// The server's view of the input, or `None` if it couldn't be uploaded or identified.
+    pub input: Option<ApiPrepInput>,
+    /// Why the file couldn't be uploaded or identified.
+    pub failure: Option<String>,
+}
+
+impl InputReport {
+    pub fn warnings(&self) -> &[String] {
+        self.input.as_ref().map(|i| i.warnings.as_slice()).unwrap_or(&[])
+    }
+
+    /// The server's errors for this input, plus any failure to upload it.
+    pub fn errors(&self) -> Vec<&str> {
+        let mut errors: Vec<&str> = self.input.iter().flat_map(|i| i.errors.iter().map(|e| e.as_str())).collect();
+        errors.extend(self.failure.as_ref().map(|f| f.as_str()));
+        errors
+    }
+
+    /// The names of the tools that may analyze this input, and whether each one will.
+    pub fn tools(&self) -> Vec<(&str, bool)> {
+        self.input.iter()
+            .flat_map(|i| i.tags.iter())
+            .map(|tag| (tag.name.as_str(), tag.enabled))
+            .collect()
+    }
+
+    /// The names of the tools that will run on this input.
+    pub fn enabled_tools(&self) -> Vec<&str> {
+        self.input.iter()
+            .flat_map(|i| i.tags.iter())
+            .filter(|tag| tag.enabled)
+            .map(|tag| tag.name.as_str())
+            .collect()
+    }
+}
+
+/// The verification results for every input in a prep.
+#[derive(Debug)]
+pub struct PrepReport {
+    pub prep_id: String,
+    pub inputs: Vec<InputReport>,
+    /// Problems with the prep as a whole (see `ApiAnalysisPrep::verification_errors`).
+    pub prep_errors: Vec<String>,
+}
+
+impl PrepReport {
+    pub fn warning_count(&self) -> usize {
+        self.inputs.iter().map(|i| i.warnings().len()).sum()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.prep_errors.len() + self.inputs.iter().map(|i| i.errors().len()).sum::<usize>()
+    }
+
+    /// Whether the inputs are fit to analyze: no errors, and, if `strict`, no warnings either.
+    pub fn passes(&self, strict: bool) -> bool {
+        self.error_count() == 0 && (!strict || self.warning_count() == 0)
+    }
+
+    /// Describe each input: its recognized type, the tools that will run on it, and any warnings or errors.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for report in self.inputs.iter() {
+            let input_type = report.input.as_ref().and_then(|i| i.input_type.as_ref()).map(|t| t.as_str()).unwrap_or("unrecognized");
+            writeln!(out, "{}: {}", report.file, input_type).unwrap();
+            let tools: Vec<String> = report.tools().into_iter()
+                .map(|(name, enabled)| if enabled { name.to_string() } else { format!("{} (won't run)", name) })
+                .collect();
+            if !tools.is_empty() {
+                writeln!(out, "  tools: {}", tools.join(", ")).unwrap();
+            }
+            for warning in report.warnings() {
+                writeln!(out, "  warning: {}", warning).unwrap();
+            }
+            for error in report.errors() {
+                writeln!(out, "  error: {}", error).unwrap();
+            }
+        }
+        for error in self.prep_errors.iter() {
+            writeln!(out, "error: {}", error).unwrap();
+        }
+        writeln!(out, "{} input(s), {} warning(s), {} error(s)", self.inputs.len(), self.warning_count(), self.error_count()).unwrap();
+        out
+    }
+}
+
+/// Create a prep in a project, upload every file to it, and report what the server made of them.
+///
+/// A file that fails to upload (or that the server fails to identify) is reported as an error in its
+/// `InputReport` rather than ending the whole process; only failing to create or read the prep itself
+/// is an `Err`, in which case the prep is deleted again. Each input's identification job is polled
+/// with `polling_strategy`.
+pub fn prepare_inputs<'p, F, P>(client: &ApiClient, project_id: u32, files: Vec<F>, polling_strategy: P) -> ApiResult<PrepReport>
+    where F: Into<UploadFile<'p>>, P: PollingStrategy<JobStatus> + Copy
+{
+    let prep = client.create_analysis_prep(project_id)?;
+    upload_inputs(client, prep.prep_id, files, polling_strategy)
+}
+
+/// Like `prepare_inputs`, for a prep that's been created already (and is deleted if this fails).
+pub fn upload_inputs<'p, F, P>(client: &ApiClient, prep_id: String, files: Vec<F>, polling_strategy: P) -> ApiResult<PrepReport>
+    where F: Into<UploadFile<'p>>, P: PollingStrategy<JobStatus> + Copy
+{
+    let mut inputs = Vec::new();
+    for file in files {
+        let file = file.into();
+        let display = file.path.to_string_lossy().into_owned();
+        let uploaded = client.upload_prep_input(&prep_id, file).and_then(|response| {
+            let status = match response.job_id {
+                Some(ref job_id) => client.poll_job_completion(job_id, polling_strategy)?,
+                None => JobStatus::Completed,
+            };
+            Ok((status, client.get_prep_input(&prep_id, &response.input_id)?))
+        });
+        inputs.push(match uploaded {
+            Ok((JobStatus::Completed, input)) => InputReport { file: display, input: Some(input), failure: None },
+            Ok((status, input)) if status.is_ready() => InputReport {
+                file: display,
+                input: Some(input),
+                failure: Some(format!("the server couldn't identify it (its job ended as {:?})", status)),
+            },
+            Ok((_, input)) => InputReport { file: display, input: Some(input), failure: Some("gave up waiting for the server to identify it".to_string()) },
+            Err(e) => InputReport { file: display, input: None, failure: Some(e.to_string()) },
+        });
+    }
+
+    match client.get_analysis_prep(&prep_id) {
+        Ok(prep) => Ok(PrepReport { prep_id, inputs, prep_errors: prep.verification_errors }),
+        Err(e) => {
+            if let Err(delete_error) = client.delete_analysis_prep(&prep_id) {
+                client.warn(&format!("couldn't delete analysis prep {}: {}", prep_id, delete_error));
+            }
+            Err(e)
+        },
+    }
+}
+
+/// Keep the named tools (matched ignoring case) from analyzing any of a prep's inputs,
+/// and update the `report` to match.
+///
+/// Every name has to match a tool of at least one input; otherwise nothing is changed, and the
+/// result is an `ApiError::UnknownTools` listing the tools there are.
+pub fn disable_tools(client: &ApiClient, report: &mut PrepReport, tools: &[&str]) -> ApiResult<()> {
+    let mut available: Vec<String> = Vec::new();
+    for (name, _) in report.inputs.iter().flat_map(|i| i.tools()) {
+        if !available.iter().any(|a| a == name) {
+            available.push(name.to_string());
+        }
+    }
+    let unknown: Vec<String> = tools.iter()
+        .filter(|tool| !available.iter().any(|a| a.to_lowercase() == tool.to_lowercase()))
+        .map(|tool| tool.to_string())
+        .collect();
+    if !unknown.is_empty() {
+        return Err(ApiError::UnknownTools { unknown, available });
+    }
+
+    let prep_id = &report.prep_id;
+    for input in report.inputs.iter_mut().filter_map(|i| i.input.as_mut()) {
+        for tag in input.tags.iter_mut() {
+            if tag.enabled && tools.iter().any(|tool| tool.to_lowercase() == tag.name.to_lowercase()) {
+                client.set_prep_input_enabled(prep_id, &input.input_id, &tag.id, false)?;
+                tag.enabled = false;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_input(input_type: Option<&str>, warnings: &[&str], errors: &[&str]) -> ApiPrepInput {
+    ApiPrepInput {
+        input_id: "i1".to_string(),
+        name: None,
+        input_type: input_type.map(|t| t.to_string()),
+        tags: vec![
+            ApiPrepTag { id: "t1".to_string(), name: "FindBugs".to_string(), enabled: true },
+            ApiPrepTag { id: "t2".to_string(), name: "Dependency-Check".to_string(), enabled: false },
+        ],
+        warnings: warnings.iter().map(|w| w.to_string()).collect(),
+        errors: errors.iter().map(|e| e.to_string()).collect(),
+    }
+}
+
+#[test]
+fn test_report_strictness(){
+    let clean = PrepReport {
+        prep_id: "p".to_string(),
+        inputs: vec![InputReport { file: "app.jar".to_string(), input: Some(test_input(Some("Java bytecode"), &[], &[])), failure: None }],
+        prep_errors: vec![],
+    };
+    assert!(clean.passes(true));
+
+    let warned = PrepReport {
+        prep_id: "p".to_string(),
+        inputs: vec![InputReport { file: "app.jar".to_string(), input: Some(test_input(Some("Java bytecode"), &["no debug info"], &[])), failure: None }],
+        prep_errors: vec![],
+    };
+    assert!(warned.passes(false));
+    assert!(!warned.passes(true));
+
+    let failed = PrepReport {
+        prep_id: "p".to_string(),
+        inputs: vec![InputReport { file: "big.bin".to_string(), input: None, failure: Some("upload failed".to_string()) }],
+        prep_errors: vec![],
+    };
+    assert!(!failed.passes(false));
+}
+
+#[test]
+fn test_report_render(){
+    let report = PrepReport {
+        prep_id: "p".to_string(),
+        inputs: vec![
+            InputReport { file: "app.jar".to_string(), input: Some(test_input(Some("Java bytecode"), &["no debug info"], &[])), failure: None },
+            InputReport { file: "notes.txt".to_string(), input: Some(ApiPrepInput { tags: vec![], ..test_input(None, &[], &["unsupported input"]) }), failure: None },
+        ],
+        prep_errors: vec!["nothing to analyze".to_string()],
+    };
+    assert_eq!(report.render(), "\
+        app.jar: Java bytecode\n\
+        \x20 tools: FindBugs, Dependency-Check (won't run)\n\
+        \x20 warning: no debug info\n\
+        notes.txt: unrecognized\n\
+        \x20 error: unsupported input\n\
+        error: nothing to analyze\n\
+        2 input(s), 1 warning(s), 2 error(s)\n");
+}
+
+#[test]
+fn test_prepare_inputs(){
+    use test_server::*;
+    let server = TestServer::start(|req| {
+        match (req.method.as_str(), req.path.as_str()) {
+            ("POST", "/codedx/api/analysis-prep") => TestResponse::json(200, json!({ "prepId": "p1" })),
+            ("POST", "/codedx/api/analysis-prep/p1/upload") => TestResponse::json(202, json!({ "inputId": "i1", "jobId": "j1" })),
+            ("GET", "/codedx/api/jobs/j1") => TestResponse::json(200, json!({ "jobId": "j1", "status": "completed" })),
+            ("GET", "/codedx/api/analysis-prep/p1/i1") => TestResponse::json(200, json!({
+                "inputId": "i1",
+                "type": "Java bytecode",
+                "tags": [{ "id": "t1", "name": "FindBugs", "enabled": true }],
+                "warnings": ["no debug info"],
+            })),
+            ("GET", "/codedx/api/analysis-prep/p1") => TestResponse::json(200, json!({ "prepId": "p1", "inputIds": ["i1"], "verificationErrors": [] })),
+            _ => TestResponse::status(404),
+        }
+    });
+    let dir = ::test_dir::TestDir::new("prep");
+    let file = dir.file("app.jar", b"PK");
+
+    let report = prepare_inputs(&server.client(), 5, vec![file.as_path()], ::std::time::Duration::from_millis(10)).unwrap();
+
+    assert_eq!(report.prep_id, "p1");
+    assert_eq!(report.inputs.len(), 1);
+    assert_eq!(report.inputs[0].enabled_tools(), vec!["FindBugs"]);
+    assert_eq!(report.warning_count(), 1);
+    assert_eq!(report.error_count(), 0);
+    assert_eq!(server.requests()[0].json(), json!({ "projectId": 5 }));
+}
+
+#[test]
+fn test_upload_inputs_failures(){
+    use test_server::*;
+    let server = TestServer::start(|req| {
+        match (req.method.as_str(), req.path.as_str()) {
+            ("POST", path) if path.ends_with("/upload") => TestResponse::json(202, json!({ "inputId": "i1", "jobId": "j1" })),
+            ("GET", "/codedx/api/jobs/j1") => TestResponse::json(200, json!({ "jobId": "j1", "status": "failed" })),
+            ("GET", path) if path.ends_with("/i1") => TestResponse::json(200, json!({ "inputId": "i1", "tags": [] })),
+            ("GET", "/codedx/api/analysis-prep/p2") => TestResponse::json(200, json!({ "prepId": "p2", "inputIds": ["i1"] })),
+            ("GET", "/codedx/api/analysis-prep/p3") => TestResponse::json(500, json!({ "error": "database unavailable" })),
+            ("DELETE", "/codedx/api/analysis-prep/p3") => TestResponse::status(204),
+            _ => TestResponse::status(404),
+        }
+    });
+    let dir = ::test_dir::TestDir::new("prep-failures");
+    let file = dir.file("app.jar", b"PK");
+    let client = server.client();
+    let poll = ::std::time::Duration::from_millis(10);
+
+    // a failed identification job is an error for its input, rather than passing
+    let report = upload_inputs(&client, "p2".to_string(), vec![file.as_path()], poll).unwrap();
+    assert_eq!(report.inputs[0].errors(), vec!["the server couldn't identify it (its job ended as Failed)"]);
+    assert!(!report.passes(false));
+
+    // the prep is deleted when it can't be read back
+    let result = upload_inputs(&client, "p3".to_string(), vec![file.as_path()], poll);
+    assert_eq!(result.unwrap_err().status(), Some(::hyper::StatusCode::InternalServerError));
+    assert!(server.requests().iter().any(|req| req.method == "DELETE" && req.path == "/codedx/api/analysis-prep/p3"));
+}
+
+#[test]
+fn test_disable_tools(){
+    use test_server::*;
+    let server = TestServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+        ("PUT", "/codedx/api/analysis-prep/p/i1/tag/t1") => TestResponse::status(204),
+        _ => TestResponse::status(404),
+    });
+    let client = server.client();
+    let mut report = PrepReport {
+        prep_id: "p".to_string(),
+        inputs: vec![InputReport { file: "app.jar".to_string(), input: Some(test_input(Some("Java bytecode"), &[], &[])), failure: None }],
+        prep_errors: vec![],
+    };
+
+    match disable_tools(&client, &mut report, &["findbugs", "Checkmarx"]) {
+        Err(ApiError::UnknownTools { ref unknown, ref available }) => {
+            assert_eq!(unknown, &vec!["Checkmarx".to_string()]);
+            assert_eq!(available, &vec!["FindBugs".to_string(), "Dependency-Check".to_string()]);
+        },
+        other => panic!("expected UnknownTools, got {:?}", other),
+    }
+    assert!(server.requests().is_empty());
+
+    // an already disabled tool is left alone
+    disable_tools(&client, &mut report, &["findbugs", "DEPENDENCY-CHECK"]).unwrap();
+    assert_eq!(report.inputs[0].tools(), vec![("FindBugs", false), ("Dependency-Check", false)]);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].json(), json!({ "enabled": false }));
+}
@@ -0,0 +1,210 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Named server profiles, read from a config file (`--profile`, see `ClientConfig::from_matches`).
+//!
+//! The file is TOML, with one table per profile:
+//!
+//! ```toml
+//! [profiles.staging]
+//! base-url = "https://staging.example.com/codedx"
+//! username = "jdoe"          # the password can be left out, to be asked for
+//! cacert = "/etc/ssl/certs/internal-ca.pem"
+//! poll = "5s"
+//!
+//! [profiles.prod]
+//! base-url = "https://codedx.example.com/codedx"
+//! api-key = "8e218b38-fcdd-453d-8f78-185f7d1d9fa7"
+//! ```
+//!
+//! Tables other than `profiles` are left alone, for other uses of the file.
+
+use polling::PollInterval;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use toml::Value;
+use toml::value::Table;
+
+/// The settings of one profile. Anything left out of the file is `None` (or empty).
+#[derive(Clone, Default, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub base_url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub api_key: Option<String>,
+    pub cacerts: Vec<PathBuf>,
+    pub insecure: Option<bool>,
+    pub no_verify_hostname: Option<bool>,
+    /// The default for commands' `--poll` option.
+    pub poll: Option<PollInterval>,
+}
+
+/// Leaves out the credentials, like `ClientAuth`'s `Debug`.
+impl fmt::Debug for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Profile")
+            .field("name", &self.name)
+            .field("base_url", &self.base_url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+            .field("cacerts", &self.cacerts)
+            .field("insecure", &self.insecure)
+            .field("no_verify_hostname", &self.no_verify_hostname)
+            .field("poll", &self.poll)
+            .finish()
+    }
+}
+
+/// A problem with the config file: either it isn't valid TOML (and the message says where),
+/// or one of `profile`'s settings is wrong.
+#[derive(Debug, PartialEq)]
+pub struct ProfileError {
+    pub profile: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.profile {
+            Some(ref name) => write!(f, "profile \"{}\": {}", name, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Where the config file is looked for when `--config` isn't given: `$XDG_CONFIG_HOME/codedx/config.toml`,
+/// or `~/.config/codedx/config.toml` (`%APPDATA%\codedx\config.toml` on Windows).
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("codedx").join("config.toml"))
+}
+
+/// Parse a config file's contents into its profiles, sorted by name.
+pub fn parse_profiles(contents: &str) -> Result<Vec<Profile>, ProfileError> {
+    let error = |profile: Option<&str>, message: String| ProfileError { profile: profile.map(str::to_string), message };
+    let file: Table = ::toml::from_str(contents).map_err(|e| error(None, e.to_string()))?;
+    let tables = match file.get("profiles") {
+        None => return Ok(Vec::new()),
+        Some(&Value::Table(ref tables)) => tables,
+        Some(_) => return Err(error(None, "profiles should be tables, named like [profiles.NAME]".to_string())),
+    };
+    let mut profiles = Vec::new();
+    for (name, settings) in tables {
+        let settings = match *settings {
+            Value::Table(ref settings) => settings,
+            _ => return Err(error(Some(name), "a profile should be a table, named like [profiles.NAME]".to_string())),
+        };
+        let mut profile = Profile { name: name.clone(), ..Profile::default() };
+        for (key, value) in settings {
+            apply_setting(&mut profile, key, value).map_err(|message| error(Some(name), message))?;
+        }
+        profiles.push(profile);
+    }
+    Ok(profiles)
+}
+
+fn apply_setting(profile: &mut Profile, key: &str, value: &Value) -> Result<(), String> {
+    let key = key.replace('_', "-");
+    match (key.as_str(), value) {
+        ("base-url", &Value::String(ref s)) => profile.base_url = Some(s.clone()),
+        ("username", &Value::String(ref s)) => profile.username = Some(s.clone()),
+        ("password", &Value::String(ref s)) => profile.password = Some(s.clone()),
+        ("api-key", &Value::String(ref s)) => profile.api_key = Some(s.clone()),
+        ("cacert", &Value::String(ref s)) => profile.cacerts = vec![PathBuf::from(s)],
+        ("cacert", &Value::Array(ref list)) => {
+            profile.cacerts = list.iter()
+                .map(|item| item.as_str().map(PathBuf::from))
+                .collect::<Option<Vec<PathBuf>>>()
+                .ok_or_else(|| "cacert should be a string, or a list of strings".to_string())?;
+        },
+        ("insecure", &Value::Boolean(b)) => profile.insecure = Some(b),
+        ("no-verify-hostname", &Value::Boolean(b)) => profile.no_verify_hostname = Some(b),
+        ("poll", &Value::String(ref s)) => {
+            let poll = s.parse().map_err(|_| format!("poll should be an interval like \"2s\", or a backoff like \"backoff:5s..2m\", not \"{}\"", s))?;
+            profile.poll = Some(poll);
+        },
+        ("base-url", _) | ("username", _) | ("password", _) | ("api-key", _) | ("poll", _) =>
+            return Err(format!("{} should be a string, not {}", key, value.type_str())),
+        ("cacert", _) => return Err("cacert should be a string, or a list of strings".to_string()),
+        ("insecure", _) | ("no-verify-hostname", _) =>
+            return Err(format!("{} should be true or false, not {}", key, value.type_str())),
+        _ => return Err(format!(
+            "unknown setting \"{}\"; the settings are: base-url, username, password, api-key, cacert, insecure, no-verify-hostname, poll",
+            key
+        )),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_profiles(){
+    let contents = r#"
+# shared by the team
+[profiles.staging]
+base-url = "https://staging.example.com/codedx"   # not prod!
+username = "jdoe"
+cacert = ["/etc/ssl/a.pem", 'C:\certs\b.pem']
+no_verify_hostname = true
+poll = "backoff:5s..1m"
+
+[profiles."eu prod"]
+base-url = "https://codedx.example.eu/codedx"
+api-key = "8e218b38#fcdd"
+
+[aliases]
+anything = "goes here"
+"#;
+    let profiles = parse_profiles(contents).unwrap();
+    assert_eq!(profiles.len(), 2);
+    let staging = &profiles[1];
+    assert_eq!(staging.name, "staging");
+    assert_eq!(staging.base_url, Some("https://staging.example.com/codedx".to_string()));
+    assert_eq!(staging.username, Some("jdoe".to_string()));
+    assert_eq!(staging.password, None);
+    assert_eq!(staging.cacerts, vec![PathBuf::from("/etc/ssl/a.pem"), PathBuf::from("C:\\certs\\b.pem")]);
+    assert_eq!(staging.no_verify_hostname, Some(true));
+    assert_eq!(staging.insecure, None);
+    assert!(staging.poll.is_some());
+    assert_eq!(profiles[0].name, "eu prod");
+    assert_eq!(profiles[0].api_key, Some("8e218b38#fcdd".to_string()));
+    assert!(!format!("{:?}", profiles[0]).contains("8e218b38"));
+}
+
+#[test]
+fn test_profile_errors(){
+    let error = |contents: &str| parse_profiles(contents).unwrap_err();
+    // not TOML at all: toml's own message, which says where
+    let syntax = error("[profiles.dev]\nbase-url = https://localhost");
+    assert_eq!(syntax.profile, None);
+    assert!(syntax.message.contains("line 2"), "{}", syntax.message);
+    assert!(error("[profiles.dev]\n[profiles.dev]").profile.is_none());
+    assert!(error("[profiles.dev]\nbase_url = \"https://localhost\"\nusername = \"jdoe").profile.is_none());
+
+    assert_eq!(error("[profiles.dev]\n\ninsecure = \"yes\"").to_string(), "profile \"dev\": insecure should be true or false, not string");
+    assert_eq!(error("[profiles.dev]\npasword = \"x\"").message.split(';').next(), Some("unknown setting \"pasword\""));
+    assert_eq!(error("[profiles.dev]\npoll = \"often\"").profile, Some("dev".to_string()));
+    assert_eq!(error("[profiles.dev]\ncacert = [\"a.pem\", 2]").message, "cacert should be a string, or a list of strings");
+    assert!(error("[[profiles]]\nbase-url = \"https://localhost\"").message.starts_with("profiles should be tables"));
+    assert_eq!(parse_profiles("[aliases]\nx = 1").unwrap(), Vec::new());
+}
@@ -0,0 +1,155 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Deciding whether to follow a redirect from the server.
+//!
+//! The HTTP library is told never to follow redirects itself, since it would drop the credentials on the way.
+//! Instead, `ApiClient::api_request` consults the `RedirectMode` in its config after each 301/302/303/307/308,
+//! and sends the request again (with its credentials) to the new location if that's on the same host.
+//! A redirect to another host, or from `https` to `http`, is never followed, so the credentials don't go anywhere
+//! the base url didn't say. An `http` base url may be upgraded to `https` on the same host, though.
+//!
+//! A 301 or 302 is only followed for a `GET` (or `HEAD`): clients customarily turn those into a `GET`,
+//! which would send e.g. a project query somewhere it means something else. A 303 is followed with a `GET`,
+//! as it says. A redirect that isn't followed becomes an `ApiError::Redirected`, which says where it went.
+
+use hyper::{Method, StatusCode};
+use hyper::header::Headers;
+use reqwest;
+use std::str;
+use url::Url;
+
+/// How many redirects in a row are followed before giving up, in case the server redirects in a loop.
+pub const MAX_REDIRECTS: u32 = 10;
+
+/// Whether to follow the server's redirects.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RedirectMode {
+    /// Follow redirects to the same host (the default).
+    FollowSameHost,
+    /// Never follow a redirect (`--no-follow-redirects`).
+    Never,
+}
+
+impl Default for RedirectMode {
+    fn default() -> RedirectMode {
+        RedirectMode::FollowSameHost
+    }
+}
+
+impl RedirectMode {
+    /// Where (and how) to send a request again after a redirect response, or why not to.
+    ///
+    /// `redirects` is how many redirects the request has already followed, and `location` is the
+    /// response's `Location`, resolved against the request's `url` (see `location`).
+    pub fn follow(&self, redirects: u32, method: &Method, url: &Url, status: StatusCode, location: Option<&Url>) -> Result<(Method, Url), &'static str> {
+        if *self == RedirectMode::Never {
+            return Err("--no-follow-redirects was given");
+        }
+        let location = location.ok_or("the response had no usable Location header")?;
+        if redirects >= MAX_REDIRECTS {
+            return Err("there were too many redirects in a row");
+        }
+        if url.scheme() == "https" && location.scheme() != "https" {
+            return Err("it's from https to http");
+        }
+        if !is_same_host(url, location) {
+            return Err("it's to a different host");
+        }
+        let method = next_method(status, method).ok_or("a request other than a GET can't follow a 301 or 302 without turning into a GET")?;
+        Ok((method, location.clone()))
+    }
+}
+
+/// Whether a status is one of the redirects (unlike e.g. a 304, which is also 3xx).
+pub fn is_redirect(status: StatusCode) -> bool {
+    match status {
+        StatusCode::MovedPermanently |
+        StatusCode::Found |
+        StatusCode::SeeOther |
+        StatusCode::TemporaryRedirect |
+        StatusCode::PermanentRedirect => true,
+        _ => false,
+    }
+}
+
+/// The method to follow a redirect with, or `None` if it can't be followed without changing a request's meaning.
+pub fn next_method(status: StatusCode, method: &Method) -> Option<Method> {
+    match status {
+        StatusCode::TemporaryRedirect | StatusCode::PermanentRedirect => Some(method.clone()),
+        StatusCode::SeeOther => Some(Method::Get),
+        StatusCode::MovedPermanently | StatusCode::Found => match *method {
+            Method::Get | Method::Head => Some(method.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a redirect from `from` to `to` stays on the same server: the same host and port,
+/// or the same host going from `http` on the default port to `https` on the default port.
+pub fn is_same_host(from: &Url, to: &Url) -> bool {
+    if from.host_str().map(|host| host.to_lowercase()) != to.host_str().map(|host| host.to_lowercase()) {
+        return false;
+    }
+    match (from.scheme(), to.scheme()) {
+        ("http", "https") => from.port_or_known_default() == Some(80) && to.port_or_known_default() == Some(443),
+        (a, b) => a == b && from.port_or_known_default() == to.port_or_known_default(),
+    }
+}
+
+/// A redirect response's `Location`, resolved against the url of the request it answered.
+pub fn location(url: &Url, response: &reqwest::Response) -> Option<Url> {
+    location_in(url, response.headers())
+}
+
+/// Like `location`, for a response that's known by its `headers`.
+pub fn location_in(url: &Url, headers: &Headers) -> Option<Url> {
+    let raw = headers.get_raw("Location")?.one()?;
+    url.join(str::from_utf8(raw).ok()?.trim()).ok()
+}
+
+#[test]
+fn test_is_same_host(){
+    let url = |s: &str| Url::parse(s).unwrap();
+    assert!(is_same_host(&url("https://codedx.example.com/codedx"), &url("https://CODEDX.example.com:443/codedx/")));
+    assert!(is_same_host(&url("http://codedx.example.com/codedx"), &url("https://codedx.example.com/codedx")));
+    assert!(!is_same_host(&url("http://codedx.example.com:8080/codedx"), &url("https://codedx.example.com/codedx")));
+    assert!(!is_same_host(&url("https://codedx.example.com/codedx"), &url("https://codedx.example.com:8443/codedx")));
+    assert!(!is_same_host(&url("https://codedx.example.com/codedx"), &url("https://sso.example.com/login")));
+}
+
+#[test]
+fn test_next_method(){
+    assert_eq!(next_method(StatusCode::MovedPermanently, &Method::Get), Some(Method::Get));
+    assert_eq!(next_method(StatusCode::Found, &Method::Post), None);
+    assert_eq!(next_method(StatusCode::SeeOther, &Method::Post), Some(Method::Get));
+    assert_eq!(next_method(StatusCode::TemporaryRedirect, &Method::Post), Some(Method::Post));
+    assert_eq!(next_method(StatusCode::PermanentRedirect, &Method::Put), Some(Method::Put));
+}
+
+#[test]
+fn test_redirect_mode_follow(){
+    let from = Url::parse("https://codedx.example.com/codedx/api/projects").unwrap();
+    let moved = from.join("/codedx2/api/projects").unwrap();
+    let follow = RedirectMode::default();
+    assert_eq!(follow.follow(0, &Method::Get, &from, StatusCode::Found, Some(&moved)), Ok((Method::Get, moved.clone())));
+    assert!(follow.follow(MAX_REDIRECTS, &Method::Get, &from, StatusCode::Found, Some(&moved)).is_err());
+    assert!(follow.follow(0, &Method::Get, &from, StatusCode::Found, None).is_err());
+    let downgrade = Url::parse("http://codedx.example.com/codedx/api/projects").unwrap();
+    assert!(follow.follow(0, &Method::Get, &from, StatusCode::Found, Some(&downgrade)).is_err());
+    assert!(RedirectMode::Never.follow(0, &Method::Get, &from, StatusCode::Found, Some(&moved)).is_err());
+}
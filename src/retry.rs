@@ -0,0 +1,306 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Deciding whether (and when) to send a request again after a transient failure.
+//!
+//! A load balancer in front of Code Dx may answer with a 502/503/504 while the server restarts,
+//! and a busy server may answer with a 429; neither means the request itself was wrong. The same
+//! goes for a connection that was reset or timed out. `ApiClient::api_request` consults the
+//! `RetryPolicy` in its config after each such failure.
+//!
+//! Only requests that are safe to repeat are retried: `GET`s always, and `POST`s/`PUT`s with a JSON
+//! body only if `retry_writes` is set. File uploads are never retried, since their body is streamed
+//! from disk as it's sent and can't be replayed.
+//!
+//! Separately, `retry_while` repeats a whole API call while it fails in a way that's known to be a race
+//! with the server (e.g. naming an analysis before the server has finished creating it).
+
+use client::{ApiError, ApiResult, PollingStrategy};
+use hyper::{self, Method, StatusCode};
+use hyper::header::Headers;
+use polling::ExponentialBackoff;
+use reqwest;
+use std::io;
+use std::cmp;
+use std::str;
+use std::thread;
+use std::time::Duration;
+use time;
+
+/// How many times to retry a request, and how long to wait in between.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// How many times to send a request again after the first attempt; `0` disables retries.
+    pub max_retries: u32,
+    /// The wait before each retry, unless the server asked for a specific one with `Retry-After`
+    /// (which is still held to the backoff's `max`, so a server can't stall the client for an hour).
+    pub backoff: ExponentialBackoff,
+    /// Whether `POST` and `PUT` requests are retried too. They are only safe to repeat if the
+    /// server won't act on them twice, so this is off by default.
+    pub retry_writes: bool,
+}
+
+impl RetryPolicy {
+    /// Retry up to `max_retries` times, waiting 1 second, then 2, 4, etc. (up to 30) in between.
+    pub fn new(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            backoff: ExponentialBackoff::new(Duration::from_secs(1), Duration::from_secs(30)),
+            retry_writes: false,
+        }
+    }
+
+    pub fn disabled() -> RetryPolicy {
+        RetryPolicy::new(0)
+    }
+
+    /// Whether requests with the given method may be retried under this policy.
+    pub fn allows(&self, method: &Method) -> bool {
+        match *method {
+            Method::Get | Method::Head => true,
+            Method::Post | Method::Put => self.retry_writes,
+            _ => false,
+        }
+    }
+
+    /// How long to wait before the given (1-based) retry of a request that got a `response`,
+    /// or `None` if the response isn't worth retrying.
+    pub fn wait_after_response(&self, retry_number: u32, response: &reqwest::Response) -> Option<Duration> {
+        self.wait_after_status(retry_number, response.status(), response.headers())
+    }
+
+    /// Like `wait_after_response`, for a response that's known by its `status` and `headers`
+    /// (e.g. one from reqwest's async client).
+    pub fn wait_after_status(&self, retry_number: u32, status: StatusCode, headers: &Headers) -> Option<Duration> {
+        if !is_transient_status(status) {
+            return None;
+        }
+        let requested = match status {
+            StatusCode::TooManyRequests | StatusCode::ServiceUnavailable => retry_after(headers),
+            _ => None,
+        };
+        Some(match requested {
+            Some(requested) => cmp::min(requested, self.backoff.max),
+            None => self.backoff.wait_for(retry_number as usize),
+        })
+    }
+
+    /// How long to wait before the given (1-based) retry of a request that couldn't be sent,
+    /// or `None` if the error isn't worth retrying.
+    pub fn wait_after_error(&self, retry_number: u32, error: &reqwest::Error) -> Option<Duration> {
+        if is_transient_error(error) {
+            Some(self.backoff.wait_for(retry_number as usize))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new(3)
+    }
+}
+
+/// Statuses a load balancer or a busy server gives for a request that may well work a bit later.
+pub fn is_transient_status(status: StatusCode) -> bool {
+    match status {
+        StatusCode::TooManyRequests |
+        StatusCode::BadGateway |
+        StatusCode::ServiceUnavailable |
+        StatusCode::GatewayTimeout => true,
+        _ => false,
+    }
+}
+
+/// Whether a request failed because the connection was dropped or timed out,
+/// as opposed to e.g. a certificate problem, which a retry won't fix.
+pub fn is_transient_error(error: &reqwest::Error) -> bool {
+    let is_incomplete = match error.get_ref().and_then(|cause| cause.downcast_ref::<hyper::Error>()) {
+        Some(&hyper::Error::Incomplete) => true,
+        _ => false,
+    };
+    is_incomplete || is_timeout(error) || match io_error_kind(error) {
+        Some(io::ErrorKind::ConnectionReset) |
+        Some(io::ErrorKind::ConnectionAborted) |
+        Some(io::ErrorKind::BrokenPipe) |
+        Some(io::ErrorKind::UnexpectedEof) => true,
+        _ => false,
+    }
+}
+
+/// Whether an upload failed in the transfer, before the server said it had started anything: the connection
+/// was dropped or timed out (see `is_transient_error`), or a gateway gave up waiting for the server (502, 504).
+/// Uploading the same files again may well work (see `ApiClient::start_analysis_with_retry`).
+pub fn is_failed_transfer(error: &ApiError) -> bool {
+    match *error {
+        ApiError::Protocol { cause: ref e, .. } => is_transient_error(e),
+        ApiError::NonSuccess { status: StatusCode::BadGateway, .. } | ApiError::NonSuccess { status: StatusCode::GatewayTimeout, .. } => true,
+        _ => false,
+    }
+}
+
+/// Whether a request failed because it took longer than the client's timeout.
+pub fn is_timeout(error: &reqwest::Error) -> bool {
+    match io_error_kind(error) {
+        Some(io::ErrorKind::TimedOut) => true,
+        // reqwest reports its own timeouts this way on unix
+        Some(io::ErrorKind::WouldBlock) => true,
+        _ => false,
+    }
+}
+
+/// The kind of I/O error behind a failed request, if that's what it was.
+pub fn io_error_kind(error: &reqwest::Error) -> Option<io::ErrorKind> {
+    let cause = error.get_ref()?;
+    if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+        return Some(io_error.kind());
+    }
+    match cause.downcast_ref::<hyper::Error>() {
+        Some(&hyper::Error::Io(ref io_error)) => Some(io_error.kind()),
+        _ => None,
+    }
+}
+
+/// Call `attempt` until it succeeds, or fails with an error that `should_retry` doesn't accept, or the
+/// `polling_strategy` (which is shown each error worth retrying) gives up. The last result is returned either way.
+///
+/// ```no_run
+/// # use codedx_client::prelude::*;
+/// # use codedx_client::polling::BoundedPoll;
+/// # use codedx_client::retry;
+/// # use std::time::Duration;
+/// # fn example(client: &ApiClient) -> ApiResult<()> {
+/// let backoff = BoundedPoll::new(Duration::from_secs(1)).timeout(Duration::from_secs(30));
+/// retry::retry_while(|| client.set_analysis_name(5, 81, "nightly"), retry::is_not_available_yet, backoff)
+/// # }
+/// ```
+pub fn retry_while<T, A, R, P>(mut attempt: A, should_retry: R, polling_strategy: P) -> ApiResult<T>
+    where A: FnMut() -> ApiResult<T>, R: Fn(&ApiError) -> bool, P: PollingStrategy<ApiError>
+{
+    let mut iteration_number: usize = 0;
+    loop {
+        iteration_number += 1;
+        let error = match attempt() {
+            Err(e) => e,
+            ok => return ok,
+        };
+        if !should_retry(&error) {
+            return Err(error);
+        }
+        match polling_strategy.next_wait(iteration_number, &error) {
+            Some(wait_dur) => thread::sleep(wait_dur),
+            None => return Err(error),
+        }
+    }
+}
+
+/// Whether an error is how the server answers a request about something it hasn't finished creating:
+/// a 404, or a 400 from servers that check the id before looking it up.
+///
+/// A freshly started analysis is like that for a moment, until the server commits its record.
+pub fn is_not_available_yet(error: &ApiError) -> bool {
+    match *error {
+        ApiError::NonSuccess { status: StatusCode::NotFound, .. } | ApiError::NonSuccess { status: StatusCode::BadRequest, .. } => true,
+        _ => false,
+    }
+}
+
+/// The wait requested by a response's `Retry-After` header, if it has one.
+fn retry_after(headers: &Headers) -> Option<Duration> {
+    let raw = headers.get_raw("Retry-After")?.one()?;
+    parse_retry_after(str::from_utf8(raw).ok()?, time::now_utc())
+}
+
+/// Parse a `Retry-After` value: either a number of seconds, or an HTTP date (a date in the past means no wait).
+pub fn parse_retry_after(value: &str, now: time::Tm) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = time::strptime(value, "%a, %d %b %Y %H:%M:%S GMT").ok()?;
+    Some((date.to_timespec() - now.to_timespec()).to_std().unwrap_or(Duration::from_secs(0)))
+}
+
+#[test]
+fn test_parse_retry_after(){
+    let now = time::strptime("Thu, 05 Oct 2017 12:00:00 GMT", "%a, %d %b %Y %H:%M:%S GMT").unwrap();
+    assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+    assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::from_secs(0)));
+    assert_eq!(parse_retry_after("Thu, 05 Oct 2017 12:01:30 GMT", now), Some(Duration::from_secs(90)));
+    assert_eq!(parse_retry_after("Thu, 05 Oct 2017 11:00:00 GMT", now), Some(Duration::from_secs(0)));
+    assert_eq!(parse_retry_after("soon", now), None);
+}
+
+#[test]
+fn test_retry_policy_methods(){
+    let policy = RetryPolicy::default();
+    assert!(policy.allows(&Method::Get));
+    assert!(!policy.allows(&Method::Post));
+    assert!(!policy.allows(&Method::Delete));
+    let writes = RetryPolicy { retry_writes: true, ..policy };
+    assert!(writes.allows(&Method::Post));
+    assert!(writes.allows(&Method::Put));
+}
+
+#[test]
+fn test_transient_statuses(){
+    assert!(is_transient_status(StatusCode::BadGateway));
+    assert!(is_transient_status(StatusCode::TooManyRequests));
+    assert!(!is_transient_status(StatusCode::InternalServerError));
+    assert!(!is_transient_status(StatusCode::NotFound));
+}
+
+#[test]
+fn test_retry_while_analysis_appears(){
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use test_server::*;
+    let attempts = AtomicUsize::new(0);
+    let server = TestServer::start(move |req| match (req.method.as_str(), req.path.as_str()) {
+        ("PUT", "/codedx/api/projects/5/analyses/81") => match attempts.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => TestResponse::json(404, json!({ "error": "Analysis not found" })),
+            _ => TestResponse::json(200, json!({})),
+        },
+        ("PUT", "/codedx/api/projects/5/analyses/82") => TestResponse::json(403, json!({ "error": "Forbidden" })),
+        _ => TestResponse::status(404),
+    });
+    let client = server.client();
+    let tries = AtomicUsize::new(0);
+    let named = retry_while(|| { tries.fetch_add(1, Ordering::SeqCst); client.set_analysis_name(5, 81, "nightly") }, is_not_available_yet, Duration::from_millis(10));
+    assert!(named.is_ok());
+    assert_eq!(tries.load(Ordering::SeqCst), 3);
+
+    // a genuine error isn't retried
+    let tries = AtomicUsize::new(0);
+    let forbidden = retry_while(|| { tries.fetch_add(1, Ordering::SeqCst); client.set_analysis_name(5, 82, "nightly") }, is_not_available_yet, Duration::from_millis(10));
+    match forbidden {
+        Err(ApiError::NonSuccess { status: StatusCode::Forbidden, .. }) => (),
+        other => panic!("expected the 403, got {:?}", other),
+    }
+    assert_eq!(tries.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_retry_while_gives_up(){
+    let tries = ::std::cell::Cell::new(0);
+    let result: ApiResult<()> = retry_while(
+        || { tries.set(tries.get() + 1); Err(ApiError::JobNotFound("j1".to_string())) },
+        |_| true,
+        |iteration_number: usize, _: &ApiError| if iteration_number < 3 { Some(Duration::from_millis(1)) } else { None },
+    );
+    assert!(result.is_err());
+    assert_eq!(tries.get(), 3);
+}
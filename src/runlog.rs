@@ -0,0 +1,183 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A timestamped record of everything significant that happened during one run of the program,
+//! written to the file given by `--log-file`.
+//!
+//! Each line of the log is a JSON object with at least a `ts` (RFC 3339 UTC, with milliseconds)
+//! and an `event` field, e.g.
+//!
+//! ```text
+//! {"elapsedMs":41,"event":"http","request":"GET /codedx/api/jobs/1234","status":200,"ts":"2017-10-05T12:00:00.123Z"}
+//! ```
+//!
+//! The file is opened in append mode, and each line is handed to the OS with a single write
+//! as soon as the event happens, so a crashed or killed process still leaves every line
+//! it got to, and concurrent runs logging to the same file don't interleave within a line.
+
+use config::{self, ClientAuth, ClientConfig, ErrorFormat, Verbosity};
+use redirect::RedirectMode;
+use serde_json::{self, Value};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use time;
+
+/// Destination for log events. Logging to a disabled `RunLog` does nothing.
+pub struct RunLog {
+    file: Option<Mutex<File>>,
+    /// How to print the warning about a line that can't be written (see `config::print_warning`).
+    verbosity: Verbosity,
+    error_format: ErrorFormat,
+}
+
+impl RunLog {
+    /// A log that discards every event.
+    pub fn disabled() -> RunLog {
+        RunLog { file: None, verbosity: Verbosity::Normal, error_format: ErrorFormat::Text }
+    }
+
+    /// Open `path` for appending (creating it if necessary).
+    pub fn open(path: &Path) -> io::Result<RunLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(RunLog { file: Some(Mutex::new(file)), verbosity: Verbosity::Normal, error_format: ErrorFormat::Text })
+    }
+
+    /// Like `open`, warning about a line that can't be written the way `ApiClient::warn` would with `config`
+    /// (so not at all with `--quiet`, and as JSON with `--error-format json`).
+    pub fn open_for(path: &Path, config: &ClientConfig) -> io::Result<RunLog> {
+        let log = RunLog::open(path)?;
+        Ok(RunLog { verbosity: config.verbosity(), error_format: config.error_format, ..log })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Append a line for `event`, with the given `fields` (which should be a JSON object).
+    ///
+    /// Failing to write the log never fails the operation being logged; if a line can't be written,
+    /// a warning goes to STDERR instead.
+    pub fn event(&self, event: &str, fields: Value) {
+        if let Some(ref file) = self.file {
+            let line = format_line(&now_timestamp(), event, fields);
+            if let Ok(mut file) = file.lock() {
+                if let Err(e) = file.write_all(line.as_bytes()).and_then(|_| file.flush()) {
+                    config::print_warning(self.verbosity, self.error_format, &format!("couldn't write to the log file: {}", e));
+                }
+            }
+        }
+    }
+}
+
+/// Format one log line, including the trailing `\n`.
+///
+/// `fields` that aren't a JSON object are recorded under a `detail` field.
+pub fn format_line(timestamp: &str, event: &str, fields: Value) -> String {
+    let mut line = match fields {
+        Value::Object(map) => map,
+        Value::Null => serde_json::Map::new(),
+        other => {
+            let mut map = serde_json::Map::new();
+            map.insert("detail".to_string(), other);
+            map
+        },
+    };
+    line.insert("ts".to_string(), json!(timestamp));
+    line.insert("event".to_string(), json!(event));
+    serde_json::to_string(&line).unwrap() + "\n"
+}
+
+/// Fields describing a `ClientConfig`, with credentials left out.
+pub fn config_fields(config: &ClientConfig) -> Value {
+    let auth = match config.auth_info {
+        ClientAuth::Basic { ref username, .. } => json!({ "type": "basic", "username": username }),
+        ClientAuth::ApiKey(_) => json!({ "type": "api-key" }),
+    };
+    json!({
+        "baseUrl": config.base_url.as_str(),
+        "auth": auth,
+        "insecure": config.insecure,
+        "clientCert": config.client_identity.as_ref().map(|identity| identity.source.display().to_string()),
+        "caCerts": config.ca_certs.iter().map(|cert| cert.source.display().to_string()).collect::<Vec<_>>(),
+        "noPrompt": config.no_prompt,
+        "quiet": config.verbosity() == Verbosity::Quiet,
+        "verbosity": format!("{:?}", config.verbosity()).to_lowercase(),
+        "proxyAuth": config.proxy_auth.as_ref().map(|auth| &auth.username),
+        "retries": config.retry.max_retries,
+        "retryWrites": config.retry.retry_writes,
+        "followRedirects": config.redirects == RedirectMode::FollowSameHost,
+        "maxConcurrentRequests": config.max_concurrent_requests,
+        "dryRun": config.dry_run,
+        "userAgent": config.user_agent(),
+    })
+}
+
+fn now_timestamp() -> String {
+    let now = time::now_utc();
+    format!("{}.{:03}Z", now.strftime("%Y-%m-%dT%H:%M:%S").unwrap(), now.tm_nsec / 1_000_000)
+}
+
+#[test]
+fn test_format_line(){
+    let line = format_line("2017-10-05T12:00:00.123Z", "job-status", json!({ "jobId": "abc", "status": "running" }));
+    assert!(line.ends_with("}\n"));
+    assert_eq!(line.matches('\n').count(), 1);
+    let parsed: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(parsed, json!({
+        "ts": "2017-10-05T12:00:00.123Z",
+        "event": "job-status",
+        "jobId": "abc",
+        "status": "running",
+    }));
+
+    let detail: Value = serde_json::from_str(&format_line("t", "exit", json!(3))).unwrap();
+    assert_eq!(detail["detail"], json!(3));
+}
+
+#[test]
+fn test_config_fields_are_redacted(){
+    let base_url = ::url::Url::parse("https://localhost/codedx").unwrap();
+    let basic = ClientConfig::new(base_url.clone(), ClientAuth::Basic { username: "admin".to_string(), password: "hunter2".to_string() });
+    let keyed = ClientConfig::new(base_url, ClientAuth::ApiKey("8e218b38-fcdd".to_string()));
+    let basic_text = config_fields(&basic).to_string();
+    assert!(basic_text.contains("admin"));
+    assert!(!basic_text.contains("hunter2"));
+    assert!(!config_fields(&keyed).to_string().contains("8e218b38"));
+}
+
+#[test]
+fn test_run_log_appends_lines(){
+    let dir = ::test_dir::TestDir::new("runlog");
+    let path = dir.join("run.log");
+    {
+        let log = RunLog::open(&path).unwrap();
+        log.event("start", json!({}));
+        log.event("exit", json!({ "code": 0 }));
+    }
+    // reopening appends rather than truncating
+    RunLog::open(&path).unwrap().event("start", json!({}));
+
+    let mut contents = String::new();
+    io::Read::read_to_string(&mut File::open(&path).unwrap(), &mut contents).unwrap();
+
+    let events: Vec<String> = contents.lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap()["event"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(events, vec!["start", "exit", "start"]);
+}
@@ -0,0 +1,301 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Counts of a project's open findings in each category of a security standard,
+//! such as the OWASP Top 10.
+//!
+//! Code Dx's public API doesn't offer a "group by standard" count, so the bucketing happens here:
+//! we ask the server for counts grouped by CWE (a single request, no matter how many findings
+//! the project has), then add each CWE's count to every category that lists that CWE.
+//! Findings whose CWE isn't part of the standard, or that have no CWE, are counted as "unmapped".
+
+use client::*;
+use serde_json;
+use std::fmt::Write;
+use std::str::FromStr;
+use summary::SEVERITIES;
+
+/// Finding statuses that count as "open" for the purposes of a standards report.
+pub const OPEN_STATUSES: [&'static str; 5] = ["new", "unresolved", "reopened", "escalated", "assigned"];
+
+/// One category of a standard, and the CWEs that belong to it.
+pub struct Category {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub cwes: &'static [u32],
+}
+
+/// The standards that findings can be bucketed into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Standard {
+    /// OWASP Top 10 (2021), using the CWE mappings published alongside it.
+    Owasp2021,
+    /// The 2021 CWE Top 25 Most Dangerous Software Weaknesses; each CWE is its own category.
+    CweTop25,
+}
+
+impl Standard {
+    /// The name used to pick this standard on the command line, and in JSON output.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Standard::Owasp2021 => "owasp2021",
+            Standard::CweTop25 => "cwe-top25",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match *self {
+            Standard::Owasp2021 => "OWASP Top 10 (2021)",
+            Standard::CweTop25 => "CWE Top 25 (2021)",
+        }
+    }
+
+    /// Every category in the standard, in the standard's own order.
+    pub fn categories(&self) -> &'static [Category] {
+        match *self {
+            Standard::Owasp2021 => &OWASP_2021,
+            Standard::CweTop25 => &CWE_TOP_25,
+        }
+    }
+}
+
+impl FromStr for Standard {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Standard, ()> {
+        match s.to_lowercase().as_str() {
+            "owasp2021" | "owasp" => Ok(Standard::Owasp2021),
+            "cwe-top25" | "cwetop25" | "cwe25" => Ok(Standard::CweTop25),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How many findings fell into one category.
+pub struct CategoryCount {
+    pub category: &'static Category,
+    pub count: u64,
+}
+
+/// Finding counts for every category of a standard, including the empty ones.
+pub struct Coverage {
+    pub standard: Standard,
+    pub categories: Vec<CategoryCount>,
+    /// Findings that didn't fall into any category.
+    pub unmapped: u64,
+}
+
+impl Coverage {
+    /// Render the counts as an aligned, human-readable table.
+    pub fn render_table(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "{:<12}{:<48}{:>8}", "Category", "Name", "Findings").unwrap();
+        for c in self.categories.iter() {
+            writeln!(out, "{:<12}{:<48}{:>8}", c.category.id, c.category.name, c.count).unwrap();
+        }
+        writeln!(out, "{:<12}{:<48}{:>8}", "-", "(not in this standard)", self.unmapped).unwrap();
+        out
+    }
+
+    /// Render the counts as CSV, with a header row.
+    ///
+    /// Unmapped findings aren't included; they aren't a category of the standard.
+    pub fn render_csv(&self) -> String {
+        let mut out = String::from("category,name,count\n");
+        for c in self.categories.iter() {
+            writeln!(out, "{},{},{}", csv_field(c.category.id), csv_field(c.category.name), c.count).unwrap();
+        }
+        out
+    }
+}
+
+/// Get the severities at or above `floor` (case-insensitive), or `None` if it isn't a severity.
+pub fn severities_at_least(floor: &str) -> Option<Vec<&'static str>> {
+    let floor = floor.to_lowercase();
+    SEVERITIES.iter()
+        .position(|s| s.to_lowercase() == floor)
+        .map(|index| SEVERITIES[..index + 1].to_vec())
+}
+
+/// Get a finding filter that selects open findings, optionally limited to some severities.
+pub fn open_findings_filter(severities: Option<&[&str]>) -> serde_json::Value {
+    let mut filter = json!({ "status": OPEN_STATUSES });
+    if let Some(severities) = severities {
+        filter["severity"] = json!(severities);
+    }
+    filter
+}
+
+/// Add up CWE-grouped finding counts into the categories of a standard.
+///
+/// A CWE that belongs to more than one category is counted in each of them.
+pub fn bucket(standard: Standard, cwe_counts: &[GroupedCount]) -> Coverage {
+    let mut categories: Vec<CategoryCount> = standard.categories().iter()
+        .map(|category| CategoryCount { category, count: 0 })
+        .collect();
+    let mut unmapped = 0;
+    for group in cwe_counts {
+        let mut mapped = false;
+        if let Some(cwe) = parse_cwe_id(&group.name) {
+            for c in categories.iter_mut() {
+                if c.category.cwes.contains(&cwe) {
+                    c.count += group.count;
+                    mapped = true;
+                }
+            }
+        }
+        if !mapped {
+            unmapped += group.count;
+        }
+    }
+    Coverage { standard, categories, unmapped }
+}
+
+/// Count a project's open findings in each category of `standard`.
+///
+/// This makes exactly one request to the server, regardless of the number of findings.
+pub fn gather_coverage(client: &ApiClient, project_id: u32, standard: Standard, severities: Option<&[&str]>) -> ApiResult<Coverage> {
+    let filter = open_findings_filter(severities);
+    client.get_finding_counts(project_id, CountGroup::Cwe, Some(filter))
+        .map(|counts| bucket(standard, &counts))
+}
+
+/// Get the CWE number from a count group's name, e.g. `"CWE-79"` or `"79"`.
+fn parse_cwe_id(name: &str) -> Option<u32> {
+    let name = name.trim();
+    let digits = if name.to_lowercase().starts_with("cwe-") { &name[4..] } else { name };
+    let end = digits.find(|c: char| !c.is_digit(10)).unwrap_or(digits.len());
+    digits[..end].parse().ok()
+}
+
+fn csv_field(raw: &str) -> String {
+    if raw.contains(|c: char| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", raw.replace('"', "\"\""))
+    } else {
+        raw.to_string()
+    }
+}
+
+static OWASP_2021: [Category; 10] = [
+    Category { id: "A01:2021", name: "Broken Access Control", cwes: &[
+        22, 23, 35, 59, 200, 201, 219, 264, 275, 276, 284, 285, 352, 359, 377, 402, 425, 441, 497, 538,
+        540, 548, 552, 566, 601, 639, 651, 668, 706, 862, 863, 913, 922, 1275,
+    ] },
+    Category { id: "A02:2021", name: "Cryptographic Failures", cwes: &[
+        261, 296, 310, 319, 321, 322, 323, 324, 325, 326, 327, 328, 329, 330, 331, 335, 336, 337, 338, 340,
+        347, 523, 720, 757, 759, 760, 780, 818, 916,
+    ] },
+    Category { id: "A03:2021", name: "Injection", cwes: &[
+        20, 74, 75, 77, 78, 79, 80, 83, 87, 88, 89, 90, 91, 93, 94, 95, 96, 97, 98, 99,
+        100, 113, 116, 138, 184, 470, 471, 564, 610, 643, 644, 652, 917,
+    ] },
+    Category { id: "A04:2021", name: "Insecure Design", cwes: &[
+        73, 183, 209, 213, 235, 256, 257, 266, 269, 280, 311, 312, 313, 316, 419, 430, 434, 444, 451, 472,
+        501, 522, 525, 539, 579, 598, 602, 642, 646, 650, 653, 656, 657, 799, 807, 840, 841, 927, 1021, 1173,
+    ] },
+    Category { id: "A05:2021", name: "Security Misconfiguration", cwes: &[
+        2, 11, 13, 15, 16, 260, 315, 520, 526, 537, 541, 547, 611, 614, 756, 776, 942, 1004, 1032, 1174,
+    ] },
+    Category { id: "A06:2021", name: "Vulnerable and Outdated Components", cwes: &[937, 1035, 1104] },
+    Category { id: "A07:2021", name: "Identification and Authentication Failures", cwes: &[
+        255, 259, 287, 288, 290, 294, 295, 297, 300, 302, 304, 306, 307, 346, 384, 521, 613, 620, 640, 798,
+        940, 1216,
+    ] },
+    Category { id: "A08:2021", name: "Software and Data Integrity Failures", cwes: &[
+        345, 353, 426, 494, 502, 565, 784, 829, 830, 915,
+    ] },
+    Category { id: "A09:2021", name: "Security Logging and Monitoring Failures", cwes: &[117, 223, 532, 778] },
+    Category { id: "A10:2021", name: "Server-Side Request Forgery (SSRF)", cwes: &[918] },
+];
+
+static CWE_TOP_25: [Category; 25] = [
+    Category { id: "CWE-787", name: "Out-of-bounds Write", cwes: &[787] },
+    Category { id: "CWE-79", name: "Cross-site Scripting", cwes: &[79] },
+    Category { id: "CWE-125", name: "Out-of-bounds Read", cwes: &[125] },
+    Category { id: "CWE-20", name: "Improper Input Validation", cwes: &[20] },
+    Category { id: "CWE-78", name: "OS Command Injection", cwes: &[78] },
+    Category { id: "CWE-89", name: "SQL Injection", cwes: &[89] },
+    Category { id: "CWE-416", name: "Use After Free", cwes: &[416] },
+    Category { id: "CWE-22", name: "Path Traversal", cwes: &[22] },
+    Category { id: "CWE-352", name: "Cross-Site Request Forgery (CSRF)", cwes: &[352] },
+    Category { id: "CWE-434", name: "Unrestricted Upload of File with Dangerous Type", cwes: &[434] },
+    Category { id: "CWE-306", name: "Missing Authentication for Critical Function", cwes: &[306] },
+    Category { id: "CWE-190", name: "Integer Overflow or Wraparound", cwes: &[190] },
+    Category { id: "CWE-502", name: "Deserialization of Untrusted Data", cwes: &[502] },
+    Category { id: "CWE-287", name: "Improper Authentication", cwes: &[287] },
+    Category { id: "CWE-476", name: "NULL Pointer Dereference", cwes: &[476] },
+    Category { id: "CWE-798", name: "Use of Hard-coded Credentials", cwes: &[798] },
+    Category { id: "CWE-119", name: "Improper Restriction of Operations within the Bounds of a Memory Buffer", cwes: &[119] },
+    Category { id: "CWE-862", name: "Missing Authorization", cwes: &[862] },
+    Category { id: "CWE-276", name: "Incorrect Default Permissions", cwes: &[276] },
+    Category { id: "CWE-200", name: "Exposure of Sensitive Information to an Unauthorized Actor", cwes: &[200] },
+    Category { id: "CWE-522", name: "Insufficiently Protected Credentials", cwes: &[522] },
+    Category { id: "CWE-732", name: "Incorrect Permission Assignment for Critical Resource", cwes: &[732] },
+    Category { id: "CWE-611", name: "Improper Restriction of XML External Entity Reference", cwes: &[611] },
+    Category { id: "CWE-918", name: "Server-Side Request Forgery (SSRF)", cwes: &[918] },
+    Category { id: "CWE-77", name: "Command Injection", cwes: &[77] },
+];
+
+#[cfg(test)]
+fn test_count(name: &str, count: u64) -> GroupedCount {
+    GroupedCount { name: name.to_string(), count }
+}
+
+#[test]
+fn test_bucket_includes_empty_categories(){
+    let coverage = bucket(Standard::Owasp2021, &[test_count("CWE-89", 4), test_count("79", 2), test_count("CWE-918", 1)]);
+    let counts: Vec<(&str, u64)> = coverage.categories.iter().map(|c| (c.category.id, c.count)).collect();
+    assert_eq!(counts, vec![
+        ("A01:2021", 0), ("A02:2021", 0), ("A03:2021", 6), ("A04:2021", 0), ("A05:2021", 0),
+        ("A06:2021", 0), ("A07:2021", 0), ("A08:2021", 0), ("A09:2021", 0), ("A10:2021", 1),
+    ]);
+    assert_eq!(coverage.unmapped, 0);
+}
+
+#[test]
+fn test_bucket_unmapped(){
+    let coverage = bucket(Standard::CweTop25, &[test_count("CWE-89", 4), test_count("CWE-1004", 3), test_count("No CWE", 2)]);
+    assert_eq!(coverage.categories.len(), 25);
+    assert_eq!(coverage.categories.iter().map(|c| c.count).sum::<u64>(), 4);
+    assert_eq!(coverage.unmapped, 5);
+}
+
+#[test]
+fn test_parse_cwe_id(){
+    assert_eq!(parse_cwe_id("CWE-79"), Some(79));
+    assert_eq!(parse_cwe_id("cwe-1004: Sensitive Cookie Without 'HttpOnly' Flag"), Some(1004));
+    assert_eq!(parse_cwe_id("89"), Some(89));
+    assert_eq!(parse_cwe_id("No CWE"), None);
+    assert_eq!(parse_cwe_id(""), None);
+}
+
+#[test]
+fn test_severities_at_least(){
+    assert_eq!(severities_at_least("high"), Some(vec!["Critical", "High"]));
+    assert_eq!(severities_at_least("Critical"), Some(vec!["Critical"]));
+    assert_eq!(severities_at_least("severe"), None);
+}
+
+#[test]
+fn test_render_csv(){
+    let coverage = bucket(Standard::Owasp2021, &[test_count("CWE-918", 1)]);
+    let csv = coverage.render_csv();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 11);
+    assert_eq!(lines[0], "category,name,count");
+    assert_eq!(lines[1], "A01:2021,Broken Access Control,0");
+    assert_eq!(lines[10], "A10:2021,Server-Side Request Forgery (SSRF),1");
+    assert_eq!(csv_field("a, \"b\""), "\"a, \"\"b\"\"\"");
+}
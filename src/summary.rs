@@ -0,0 +1,515 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! One-page summaries of a project's findings, for sharing with people who don't use Code Dx directly.
+
+use client::*;
+use std::collections::HashSet;
+use std::fmt::Write;
+use time;
+use url::Url;
+
+/// Severities Code Dx assigns to findings, from most to least severe.
+pub const SEVERITIES: [&'static str; 6] = ["Critical", "High", "Medium", "Low", "Info", "Unspecified"];
+
+/// Page size used when a summary (or the `findings` command) needs to load every matching finding.
+pub const FINDINGS_PAGE_SIZE: u32 = 500;
+
+/// Everything that goes into a summary of a project's findings.
+pub struct Summary {
+    pub project: ApiProject,
+
+    /// The analysis being summarized, or `None` if the project has never been analyzed.
+    ///
+    /// When present, the counts and findings in the summary are limited to that analysis.
+    pub analysis: Option<ApiAnalysis>,
+
+    /// Finding counts for each severity, most severe first.
+    pub severity_counts: Vec<GroupedCount>,
+
+    /// The most severe findings, most severe first.
+    pub top_findings: Vec<ApiFinding>,
+
+    /// Differences from a baseline analysis, if one was requested.
+    pub comparison: Option<Comparison>,
+
+    /// When the summary was generated (RFC 3339, UTC).
+    pub generated_at: String,
+
+    /// Base url of the Code Dx web UI, used to generate links.
+    pub web_url: Url,
+}
+
+/// Differences between the summarized analysis and an earlier "baseline" analysis.
+pub struct Comparison {
+    pub baseline: ApiAnalysis,
+
+    /// Finding counts for each severity in the baseline, most severe first.
+    pub baseline_counts: Vec<GroupedCount>,
+
+    /// Findings present in the summarized analysis, but not in the baseline.
+    pub new_findings: Vec<ApiFinding>,
+
+    /// Findings present in the baseline, but not in the summarized analysis.
+    pub resolved_findings: Vec<ApiFinding>,
+}
+
+impl Summary {
+    /// Link to the summarized project in the Code Dx web UI.
+    pub fn project_link(&self) -> String {
+        web_link(&self.web_url, &["projects", &self.project.id.to_string()])
+    }
+
+    /// Link to a finding in the Code Dx web UI.
+    pub fn finding_link(&self, finding: &ApiFinding) -> String {
+        web_link(&self.web_url, &["findings", &finding.id.to_string()])
+    }
+
+    /// When the summarized analysis ran, if known.
+    pub fn scan_time(&self) -> Option<&str> {
+        self.analysis.as_ref().and_then(analysis_time)
+    }
+
+    /// The severity count for `severity`, and the change since the baseline (if comparing).
+    pub fn severity_total(&self, severity: &str) -> (u64, Option<i64>) {
+        let current = count_for(&self.severity_counts, severity);
+        let delta = self.comparison.as_ref().map(|c| {
+            current as i64 - count_for(&c.baseline_counts, severity) as i64
+        });
+        (current, delta)
+    }
+}
+
+/// Load everything needed for a `Summary` of a project.
+///
+/// If `analysis_id` is `None`, the project's most recent analysis is summarized.
+/// If `compare_to` is given, the summary includes a `Comparison` against that analysis.
+/// The summary includes up to `top_n` of the most severe findings.
+pub fn gather_summary(client: &ApiClient, project_id: u32, analysis_id: Option<u32>, compare_to: Option<u32>, top_n: u32) -> ApiResult<Summary> {
+    let project = find_project(client, project_id)?;
+
+    let analysis = match analysis_id {
+        Some(id) => Some(client.get_analysis(project_id, id)?),
+        None => latest_analysis(client.get_analyses(project_id)?),
+    };
+    let filter = analysis.as_ref().map(|a| analysis_filter(a.id));
+
+    let severity_counts = sorted_by_severity(client.get_finding_counts(project_id, CountGroup::Severity, filter.clone())?);
+
+    let top_findings = client.query_findings(project_id, &FindingQuery {
+        filter: filter.clone(),
+        sort: Some(FindingSort { by: "severity".to_string(), direction: SortDirection::Descending }),
+        pagination: Some(Pagination { page: 1, per_page: top_n }),
+    })?;
+
+    let comparison = match compare_to {
+        Some(baseline_id) => Some(compare(client, project_id, filter, baseline_id)?),
+        None => None,
+    };
+
+    Ok(Summary {
+        project,
+        analysis,
+        severity_counts,
+        top_findings,
+        comparison,
+        generated_at: time::now_utc().rfc3339().to_string(),
+        web_url: client.get_config().base_url.clone(),
+    })
+}
+
+fn compare(client: &ApiClient, project_id: u32, current_filter: Option<::serde_json::Value>, baseline_id: u32) -> ApiResult<Comparison> {
+    let baseline = client.get_analysis(project_id, baseline_id)?;
+    let baseline_filter = Some(analysis_filter(baseline_id));
+    let baseline_counts = sorted_by_severity(client.get_finding_counts(project_id, CountGroup::Severity, baseline_filter.clone())?);
+
+    let current = client.query_all_findings(project_id, &FindingQuery { filter: current_filter, ..FindingQuery::default() }, FINDINGS_PAGE_SIZE)?;
+    let previous = client.query_all_findings(project_id, &FindingQuery { filter: baseline_filter, ..FindingQuery::default() }, FINDINGS_PAGE_SIZE)?;
+
+    let current_ids: HashSet<u64> = current.iter().map(|f| f.id).collect();
+    let previous_ids: HashSet<u64> = previous.iter().map(|f| f.id).collect();
+
+    let mut new_findings: Vec<ApiFinding> = current.into_iter().filter(|f| !previous_ids.contains(&f.id)).collect();
+    let mut resolved_findings: Vec<ApiFinding> = previous.into_iter().filter(|f| !current_ids.contains(&f.id)).collect();
+    new_findings.sort_by_key(|f| severity_rank(f.severity.as_ref().map(|s| s.as_str()).unwrap_or("")));
+    resolved_findings.sort_by_key(|f| severity_rank(f.severity.as_ref().map(|s| s.as_str()).unwrap_or("")));
+
+    Ok(Comparison { baseline, baseline_counts, new_findings, resolved_findings })
+}
+
+/// Find the project with the given id.
+///
+/// If it isn't in the list of projects visible to the current user, a placeholder is used instead,
+/// since the rest of the summary's requests will report any real permissions problems.
+fn find_project(client: &ApiClient, project_id: u32) -> ApiResult<ApiProject> {
+    let found = client.get_projects()?.into_iter().find(|p| p.id == project_id);
+    Ok(found.unwrap_or_else(|| ApiProject {
+        id: project_id,
+        name: format!("Project {}", project_id),
+        parent_id: None,
+    }))
+}
+
+/// Pick the most recent analysis from a list (by id, since ids are assigned in order).
+pub fn latest_analysis(analyses: Vec<ApiAnalysis>) -> Option<ApiAnalysis> {
+    analyses.into_iter().max_by_key(|a| a.id)
+}
+
+/// The best available timestamp for when an analysis ran.
+pub fn analysis_time(analysis: &ApiAnalysis) -> Option<&str> {
+    analysis.finish_time.as_ref()
+        .or(analysis.start_time.as_ref())
+        .or(analysis.creation_time.as_ref())
+        .map(|s| s.as_str())
+}
+
+/// Position of a severity name in `SEVERITIES`; unrecognized names sort last.
+pub fn severity_rank(severity: &str) -> usize {
+    SEVERITIES.iter()
+        .position(|s| s.eq_ignore_ascii_case(severity))
+        .unwrap_or(SEVERITIES.len())
+}
+
+fn sorted_by_severity(mut counts: Vec<GroupedCount>) -> Vec<GroupedCount> {
+    counts.sort_by_key(|c| severity_rank(&c.name));
+    counts
+}
+
+/// Find the count for the group with the given name, or 0 if there is no such group.
+pub fn count_for(counts: &[GroupedCount], name: &str) -> u64 {
+    counts.iter()
+        .find(|c| c.name.eq_ignore_ascii_case(name))
+        .map(|c| c.count)
+        .unwrap_or(0)
+}
+
+/// Describe a finding's location as `path:line`, or just `path` if the line is unknown.
+pub fn location_text(finding: &ApiFinding) -> Option<String> {
+    finding.location.as_ref().and_then(|loc| {
+        loc.path.as_ref().map(|path| {
+            match loc.line_range {
+                Some(ref lines) => format!("{}:{}", path, lines.start),
+                None => path.clone(),
+            }
+        })
+    })
+}
+
+/// Link to a page of the Code Dx web UI, e.g. `["findings", "12"]` under the server's base url.
+pub fn web_link(web_url: &Url, segments: &[&str]) -> String {
+    let mut url = web_url.clone();
+    if let Ok(mut url_segments) = url.path_segments_mut() {
+        url_segments.pop_if_empty();
+        for segment in segments {
+            url_segments.push(segment);
+        }
+    }
+    url.into_string()
+}
+
+/// Format a delta like `+2`, `-1`, or `0`.
+pub fn format_delta(delta: i64) -> String {
+    if delta > 0 { format!("+{}", delta) } else { format!("{}", delta) }
+}
+
+fn escape_html(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &'static str = "
+body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; color: #222; margin: 2em auto; max-width: 960px; }
+h1 { font-size: 1.6em; margin-bottom: 0.2em; }
+.meta { color: #666; margin-bottom: 1.5em; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 2em; }
+th, td { text-align: left; padding: 0.4em 0.8em; border-bottom: 1px solid #ddd; }
+th { background: #f4f4f4; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+.sev-critical { color: #a50026; font-weight: bold; }
+.sev-high { color: #d73027; font-weight: bold; }
+.sev-medium { color: #f46d43; }
+.sev-low { color: #4575b4; }
+.worse { color: #d73027; }
+.better { color: #1a9850; }
+a { color: #1f5fa8; }
+";
+
+/// Render a summary as a self-contained HTML document (no external stylesheets, scripts, or images).
+pub fn render_html(summary: &Summary) -> String {
+    let mut html = String::new();
+    let project_name = escape_html(&summary.project.name);
+
+    writeln!(html, "<!DOCTYPE html>").unwrap();
+    writeln!(html, "<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(html, "<title>{} - Code Dx Summary</title>", project_name).unwrap();
+    writeln!(html, "<style>{}</style>\n</head>\n<body>", HTML_STYLE).unwrap();
+
+    writeln!(html, "<h1><a href=\"{}\">{}</a></h1>", escape_html(&summary.project_link()), project_name).unwrap();
+    write!(html, "<div class=\"meta\">").unwrap();
+    match summary.analysis {
+        Some(ref analysis) => {
+            write!(html, "Analysis {}", analysis.id).unwrap();
+            if let Some(ref name) = analysis.name {
+                write!(html, " &ldquo;{}&rdquo;", escape_html(name)).unwrap();
+            }
+            if let Some(time) = summary.scan_time() {
+                write!(html, ", scanned {}", escape_html(time)).unwrap();
+            }
+        },
+        None => write!(html, "No analyses").unwrap(),
+    }
+    if let Some(ref comparison) = summary.comparison {
+        write!(html, ", compared to analysis {}", comparison.baseline.id).unwrap();
+    }
+    writeln!(html, "<br>Generated {}</div>", escape_html(&summary.generated_at)).unwrap();
+
+    // severity totals
+    writeln!(html, "<h2>Findings by Severity</h2>\n<table>").unwrap();
+    write!(html, "<tr><th>Severity</th><th>Count</th>").unwrap();
+    if summary.comparison.is_some() {
+        write!(html, "<th>Change</th>").unwrap();
+    }
+    writeln!(html, "</tr>").unwrap();
+    for severity in SEVERITIES.iter() {
+        let (count, delta) = summary.severity_total(severity);
+        write!(html, "<tr><td class=\"sev-{}\">{}</td><td class=\"num\">{}</td>", severity.to_lowercase(), severity, count).unwrap();
+        if let Some(delta) = delta {
+            let class = if delta > 0 { "worse" } else if delta < 0 { "better" } else { "" };
+            write!(html, "<td class=\"num {}\">{}</td>", class, format_delta(delta)).unwrap();
+        }
+        writeln!(html, "</tr>").unwrap();
+    }
+    writeln!(html, "</table>").unwrap();
+
+    if let Some(ref comparison) = summary.comparison {
+        writeln!(html, "<h2>Since Analysis {}</h2>", comparison.baseline.id).unwrap();
+        writeln!(html, "<p><span class=\"worse\">{} new</span>, <span class=\"better\">{} resolved</span></p>",
+            comparison.new_findings.len(), comparison.resolved_findings.len()).unwrap();
+        if !comparison.new_findings.is_empty() {
+            writeln!(html, "<h3>New Findings</h3>").unwrap();
+            write_findings_table(&mut html, summary, &comparison.new_findings);
+        }
+    }
+
+    writeln!(html, "<h2>Top Findings</h2>").unwrap();
+    if summary.top_findings.is_empty() {
+        writeln!(html, "<p>No findings.</p>").unwrap();
+    } else {
+        write_findings_table(&mut html, summary, &summary.top_findings);
+    }
+
+    writeln!(html, "</body>\n</html>").unwrap();
+    html
+}
+
+fn write_findings_table(html: &mut String, summary: &Summary, findings: &[ApiFinding]) {
+    writeln!(html, "<table>\n<tr><th>Severity</th><th>Finding</th><th>Location</th><th>Status</th></tr>").unwrap();
+    for finding in findings {
+        let severity = finding.severity.as_ref().map(|s| s.as_str()).unwrap_or("");
+        writeln!(html, "<tr><td class=\"sev-{}\">{}</td><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td></tr>",
+            escape_html(&severity.to_lowercase()),
+            escape_html(severity),
+            escape_html(&summary.finding_link(finding)),
+            escape_html(finding.title()),
+            escape_html(&location_text(finding).unwrap_or_default()),
+            escape_html(finding.status.as_ref().map(|s| s.as_str()).unwrap_or("")),
+        ).unwrap();
+    }
+    writeln!(html, "</table>").unwrap();
+}
+
+/// Default size limit for `render_markdown`; GitHub rejects comments longer than 65536 characters.
+pub const DEFAULT_MARKDOWN_LIMIT: usize = 65536;
+
+fn escape_markdown(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A one-line verdict for the summary, e.g. "2 new findings (1 Critical, 1 High)".
+pub fn verdict(summary: &Summary) -> String {
+    let (findings, label): (Vec<&ApiFinding>, &str) = match summary.comparison {
+        Some(ref c) => (c.new_findings.iter().collect(), "new finding"),
+        None => (Vec::new(), "finding"),
+    };
+    let total: u64 = match summary.comparison {
+        Some(_) => findings.len() as u64,
+        None => summary.severity_counts.iter().map(|c| c.count).sum(),
+    };
+    if total == 0 {
+        return format!("No {}s", label);
+    }
+    let mut breakdown = Vec::new();
+    for severity in SEVERITIES.iter() {
+        let count = match summary.comparison {
+            Some(_) => findings.iter().filter(|f| f.severity.as_ref().map(|s| s.eq_ignore_ascii_case(severity)).unwrap_or(false)).count() as u64,
+            None => count_for(&summary.severity_counts, severity),
+        };
+        if count > 0 {
+            breakdown.push(format!("{} {}", count, severity));
+        }
+    }
+    let plural = if total == 1 { "" } else { "s" };
+    format!("{} {}{} ({})", total, label, plural, breakdown.join(", "))
+}
+
+/// Render a summary as compact Markdown, e.g. for posting as a pull request comment.
+///
+/// The listing of findings is truncated (with an "and N more" link to the project) as
+/// necessary to keep the whole document within `max_length` bytes.
+pub fn render_markdown(summary: &Summary, max_length: usize) -> String {
+    let mut head = String::new();
+    writeln!(head, "### Code Dx: [{}]({})", escape_markdown(&summary.project.name), summary.project_link()).unwrap();
+    if let Some(ref analysis) = summary.analysis {
+        write!(head, "Analysis {}", analysis.id).unwrap();
+        if let Some(time) = summary.scan_time() {
+            write!(head, ", scanned {}", time).unwrap();
+        }
+        if let Some(ref comparison) = summary.comparison {
+            write!(head, ", compared to analysis {}", comparison.baseline.id).unwrap();
+        }
+        writeln!(head).unwrap();
+    }
+    writeln!(head).unwrap();
+
+    // severity totals, skipping rows where there's nothing to report
+    let comparing = summary.comparison.is_some();
+    writeln!(head, "{}", if comparing { "| Severity | Count | Change |\n|---|--:|--:|" } else { "| Severity | Count |\n|---|--:|" }).unwrap();
+    for severity in SEVERITIES.iter() {
+        match summary.severity_total(severity) {
+            (0, None) | (0, Some(0)) => (),
+            (count, Some(delta)) => writeln!(head, "| {} | {} | {} |", severity, count, format_delta(delta)).unwrap(),
+            (count, None) => writeln!(head, "| {} | {} |", severity, count).unwrap(),
+        }
+    }
+
+    let tail = format!("\n**Result:** {}\n", verdict(summary));
+
+    let (list_title, findings) = match summary.comparison {
+        Some(ref c) => ("New findings", &c.new_findings[..]),
+        None => ("Top findings", &summary.top_findings[..]),
+    };
+    if findings.is_empty() {
+        return head + &tail;
+    }
+
+    let details_open = format!("\n<details>\n<summary>{} ({})</summary>\n\n", list_title, findings.len());
+    let details_close = "\n</details>\n";
+    let project_link = summary.project_link();
+    let more_line = |n: usize| format!("- ...and [{} more]({})\n", n, project_link);
+
+    let fixed_length = head.len() + details_open.len() + details_close.len() + tail.len();
+    let mut budget = max_length.saturating_sub(fixed_length);
+    let mut lines = String::new();
+    for (index, finding) in findings.iter().enumerate() {
+        let location = location_text(finding).map(|l| format!(" (`{}`)", l)).unwrap_or_default();
+        let line = format!("- {} \u{2014} [{}]({}){}\n",
+            finding.severity.as_ref().map(|s| s.as_str()).unwrap_or("Unspecified"),
+            escape_markdown(finding.title()),
+            summary.finding_link(finding),
+            location,
+        );
+        let remaining = findings.len() - index - 1;
+        // always leave room for the "and N more" line unless this is the last finding
+        let reserve = if remaining > 0 { more_line(remaining).len() } else { 0 };
+        if line.len() + reserve > budget {
+            lines.push_str(&more_line(findings.len() - index));
+            break;
+        }
+        budget -= line.len();
+        lines.push_str(&line);
+    }
+
+    head + &details_open + &lines + details_close + &tail
+}
+
+#[cfg(test)]
+fn test_summary() -> Summary {
+    let finding: ApiFinding = ::serde_json::from_value(json!({
+        "id": 12,
+        "severity": { "name": "High" },
+        "status": "New",
+        "descriptor": { "name": "SQL <Injection>" },
+        "primaryLocation": { "path": "src/db.rs", "lineRange": { "start": 40, "end": 42 } },
+    })).unwrap();
+    Summary {
+        project: ApiProject { id: 5, name: "WebGoat & Friends".to_string(), parent_id: None },
+        analysis: None,
+        severity_counts: vec![GroupedCount { name: "High".to_string(), count: 3 }],
+        top_findings: vec![finding],
+        comparison: None,
+        generated_at: "2017-10-05T12:00:00Z".to_string(),
+        web_url: Url::parse("https://localhost/codedx").unwrap(),
+    }
+}
+
+#[test]
+fn test_render_html_escapes_and_links(){
+    let html = render_html(&test_summary());
+    assert!(html.contains("WebGoat &amp; Friends"));
+    assert!(html.contains("SQL &lt;Injection&gt;"));
+    assert!(html.contains("https://localhost/codedx/findings/12"));
+    assert!(html.contains("src/db.rs:40"));
+    assert!(!html.contains("<link"));
+    assert!(!html.contains("<script"));
+}
+
+#[test]
+fn test_severity_rank(){
+    assert!(severity_rank("critical") < severity_rank("High"));
+    assert!(severity_rank("Info") < severity_rank("something else"));
+}
+
+#[test]
+fn test_render_markdown_lists_findings(){
+    let md = render_markdown(&test_summary(), DEFAULT_MARKDOWN_LIMIT);
+    assert!(md.contains("| High | 3 |"));
+    assert!(md.contains("<details>"));
+    assert!(md.contains("- High \u{2014} [SQL \\<Injection\\>](https://localhost/codedx/findings/12) (`src/db.rs:40`)"));
+    assert!(md.contains("**Result:** 3 findings (3 High)"));
+}
+
+#[test]
+fn test_render_markdown_truncates(){
+    let mut summary = test_summary();
+    for id in 100..200 {
+        summary.top_findings.push(::serde_json::from_value(json!({ "id": id, "severity": "Low" })).unwrap());
+    }
+    let limit = 2000;
+    let md = render_markdown(&summary, limit);
+    assert!(md.len() <= limit);
+    assert!(md.contains("more](https://localhost/codedx/projects/5)"));
+    assert!(md.ends_with("**Result:** 3 findings (3 High)\n"));
+}
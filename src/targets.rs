@@ -0,0 +1,459 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Analyzing several projects ("targets") in one go, e.g. one per service in a monorepo.
+//!
+//! A target is written as `project=<NAME OR ID>,file=<PATH>[,file=<PATH>...]` as the value of an
+//! `analyze --target` argument, or as an entry in the YAML list of an `analyze --targets-file`
+//! (see `parse_targets_file`), where it can also have a `--fail-on` gate of its own.
+//!
+//! Uploads happen on a limited number of threads at once; the resulting jobs are then polled together
+//! with `ApiClient::poll_jobs_completion`, and each completed analysis is checked against its gate.
+//! A target that fails at any step doesn't stop the others.
+
+use client::*;
+use gate::{self, GateReport, SeverityGate};
+use polling;
+use serde_yaml;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+
+/// One project to analyze, and the files to analyze in it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalysisTarget {
+    /// The project's name or id.
+    pub project: String,
+    pub files: Vec<PathBuf>,
+    /// The thresholds for this target's findings, instead of the ones given to `analyze_targets` for every target.
+    pub fail_on: Option<SeverityGate>,
+}
+
+impl AnalysisTarget {
+    /// Parse a target like `project=web,file=web/build/scan.xml`.
+    pub fn parse(raw: &str) -> Result<AnalysisTarget, String> {
+        let mut project = None;
+        let mut files = Vec::new();
+        for entry in raw.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()) {
+            let (key, value) = match entry.find('=') {
+                Some(index) => (&entry[..index], entry[index + 1..].trim()),
+                None => return Err(format!("expected key=value in target \"{}\", found \"{}\"", raw, entry)),
+            };
+            match key.trim() {
+                "project" => project = Some(value.to_string()),
+                "file" => files.push(PathBuf::from(value)),
+                other => return Err(format!("unknown key \"{}\" in target \"{}\"; expected project or file", other, raw)),
+            }
+        }
+        match project {
+            None => Err(format!("target \"{}\" is missing project=", raw)),
+            Some(_) if files.is_empty() => Err(format!("target \"{}\" needs at least one file=", raw)),
+            Some(project) => Ok(AnalysisTarget { project, files, fail_on: None }),
+        }
+    }
+}
+
+/// One entry in a targets file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetEntry {
+    project: ProjectRef,
+    files: Vec<PathBuf>,
+    #[serde(rename = "fail-on", default)]
+    fail_on: Option<String>,
+    #[serde(rename = "fail-on-new-only", default)]
+    fail_on_new_only: bool,
+}
+
+/// A target's project: YAML reads `project: 12` as a number, and `project: web` as a string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ProjectRef {
+    Id(u64),
+    Name(String),
+}
+
+/// Parse the contents of a targets file: a YAML list of targets, like
+///
+/// ```yaml
+/// # services
+/// - project: web
+///   files: [web/build/scan.xml]
+///   fail-on: critical:0,high:5
+/// - project: 12
+///   files:
+///     - api/scan.xml
+///     - api/deps.json
+/// ```
+///
+/// A target's `fail-on` is parsed like `analyze --fail-on`, and `fail-on-new-only: true` goes with it.
+pub fn parse_targets_file(contents: &str) -> Result<Vec<AnalysisTarget>, String> {
+    let entries: Vec<TargetEntry> = serde_yaml::from_str(contents).map_err(|e| e.to_string())?;
+    entries.into_iter().enumerate().map(|(index, entry)| {
+        let project = match entry.project {
+            ProjectRef::Id(id) => id.to_string(),
+            ProjectRef::Name(name) => name,
+        };
+        if entry.files.is_empty() {
+            return Err(format!("target {} ({}) needs at least one file", index + 1, project));
+        }
+        let fail_on = match entry.fail_on {
+            Some(raw) => {
+                let mut gate: SeverityGate = raw.parse().map_err(|e| format!("target {} ({}) has an invalid fail-on: {}", index + 1, project, e))?;
+                gate.new_only = entry.fail_on_new_only;
+                Some(gate)
+            },
+            None if entry.fail_on_new_only => return Err(format!("target {} ({}) has fail-on-new-only without fail-on", index + 1, project)),
+            None => None,
+        };
+        Ok(AnalysisTarget { project, files: entry.files, fail_on })
+    }).collect()
+}
+
+/// How one target turned out.
+#[derive(Debug)]
+pub enum TargetOutcome {
+    /// The analysis ran, and finished with this status.
+    Finished(JobStatus),
+    /// The analysis completed, but had more findings than the target's gate allows.
+    GateFailed(GateReport),
+    /// Something went wrong before the analysis finished; `stage` says what the client was doing.
+    Error { stage: &'static str, message: String },
+}
+
+impl TargetOutcome {
+    pub fn is_success(&self) -> bool {
+        match *self {
+            TargetOutcome::Finished(status) => status.is_success(),
+            TargetOutcome::GateFailed(_) | TargetOutcome::Error { .. } => false,
+        }
+    }
+
+    /// The exit code that a single `analyze` would have for this outcome: `0` for success, `1` for a failed
+    /// analysis or an error, `polling::EXIT_TIMED_OUT` for giving up on it, and `gate::EXIT_GATE_FAILED`.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            TargetOutcome::Finished(status) if status.is_success() => 0,
+            TargetOutcome::Finished(status) if !status.is_ready() => polling::EXIT_TIMED_OUT,
+            TargetOutcome::Finished(_) => 1,
+            TargetOutcome::GateFailed(ref report) => report.exit_code(),
+            TargetOutcome::Error { .. } => 1,
+        }
+    }
+
+    /// A short description, e.g. `completed`, `too many findings (Critical: 2, at most 0)` or `error (upload): ...`.
+    pub fn describe(&self) -> String {
+        match *self {
+            TargetOutcome::Finished(status) => format!("{:?}", status).to_lowercase(),
+            TargetOutcome::GateFailed(ref report) => {
+                let exceeded: Vec<String> = report.exceeded().iter().map(|row| format!("{}: {}, at most {}", row.severity, row.count, row.max)).collect();
+                format!("too many findings ({})", exceeded.join("; "))
+            },
+            TargetOutcome::Error { stage, ref message } => format!("error ({}): {}", stage, message),
+        }
+    }
+}
+
+/// The result of analyzing one target.
+#[derive(Debug)]
+pub struct TargetResult {
+    pub target: AnalysisTarget,
+    pub project_id: Option<u32>,
+    pub analysis_id: Option<u32>,
+    pub job_id: Option<String>,
+    pub outcome: TargetOutcome,
+}
+
+/// Upload and analyze every target, at most `concurrency` uploads at a time, then wait for all of them,
+/// and check each completed analysis against its target's `fail_on` gate (or else `gate`, if given).
+///
+/// Results are in the same order as `targets`. A target whose job record expired before it
+/// could be polled is checked by the state of its analysis instead.
+pub fn analyze_targets<P: PollingStrategy<JobStatus> + Clone + Sync>(client: &ApiClient, targets: &[AnalysisTarget], gate: Option<&SeverityGate>, concurrency: usize, polling_strategy: P) -> Vec<TargetResult> {
+    // phase 1: resolve each project and upload its files, on a few threads
+    let pending: Mutex<Vec<usize>> = Mutex::new((0..targets.len()).rev().collect());
+    let started: Mutex<Vec<Option<TargetResult>>> = Mutex::new(targets.iter().map(|_| None).collect());
+    let thread_count = cmp::max(1, cmp::min(concurrency, targets.len()));
+    thread::scope(|scope| {
+        for _ in 0..thread_count {
+            scope.spawn(|| {
+                loop {
+                    let next = pending.lock().unwrap().pop();
+                    match next {
+                        Some(index) => {
+                            let result = start_target(client, &targets[index], polling_strategy.clone());
+                            started.lock().unwrap()[index] = Some(result);
+                        },
+                        None => break,
+                    }
+                }
+            });
+        }
+    });
+    let mut results: Vec<TargetResult> = started.into_inner().unwrap().into_iter().map(|r| r.unwrap()).collect();
+
+    // phase 2: wait for every job that started
+    let polling: Vec<usize> = results.iter().enumerate()
+        .filter(|&(_, r)| r.job_id.is_some())
+        .map(|(index, _)| index)
+        .collect();
+    let (mut finished, mut failed, mut gave_up) = {
+        let job_ids: Vec<&str> = polling.iter().map(|&index| results[index].job_id.as_ref().unwrap().as_str()).collect();
+        match client.poll_jobs_completion(&job_ids, polling_strategy.clone()) {
+            Ok(finished) => (finished, HashMap::new(), HashMap::new()),
+            Err(ApiError::JobsUnfinished { finished, failed, gave_up }) => (finished, failed, gave_up),
+            Err(e) => {
+                let message = e.to_string();
+                for &index in polling.iter() {
+                    results[index].outcome = TargetOutcome::Error { stage: "polling", message: message.clone() };
+                }
+                return results;
+            },
+        }
+    };
+    for index in polling.into_iter() {
+        // a target that the poll gave up on keeps its last status, like a single job's poll
+        let status = {
+            let job_id = results[index].job_id.as_ref().unwrap();
+            match (finished.remove(job_id), failed.remove(job_id), gave_up.remove(job_id)) {
+                (Some(status), _, _) | (_, _, Some(status)) => Ok(status),
+                (_, Some(e), _) => Err(e),
+                (None, None, None) => Ok(JobStatus::Unknown),
+            }
+        };
+        let status = match (status, results[index].project_id, results[index].analysis_id) {
+            (Err(ApiError::JobNotFound(_)), Some(project_id), Some(analysis_id)) => {
+                client.poll_analysis_completion(project_id, analysis_id, polling_strategy.clone())
+            },
+            (other, _, _) => other,
+        };
+        results[index].outcome = match status {
+            Ok(status) => TargetOutcome::Finished(status),
+            Err(e) => TargetOutcome::Error { stage: "polling", message: e.to_string() },
+        };
+    }
+
+    // phase 3: count the findings of each completed analysis that has a gate
+    for result in results.iter_mut() {
+        let gate = match result.target.fail_on.as_ref().or(gate) {
+            Some(gate) if result.outcome.is_success() => gate,
+            _ => continue,
+        };
+        if let (Some(project_id), Some(analysis_id)) = (result.project_id, result.analysis_id) {
+            match gate.check(client, project_id, analysis_id) {
+                Ok(ref report) if report.exit_code() == 0 => (),
+                Ok(report) => result.outcome = TargetOutcome::GateFailed(report),
+                Err(e) => result.outcome = TargetOutcome::Error { stage: "gate", message: e.to_string() },
+            }
+        }
+    }
+    results
+}
+
+/// Resolve a target's project and start its analysis.
+///
+/// Until its job has been polled, a target that started successfully has the outcome `Finished(Queued)`;
+/// one recovered from a failed upload (see `ApiClient::start_analysis_or_recover`) is polled right here,
+/// since it has no job to poll along with the others.
+fn start_target<P: PollingStrategy<JobStatus>>(client: &ApiClient, target: &AnalysisTarget, polling_strategy: P) -> TargetResult {
+    let mut result = TargetResult {
+        target: target.clone(),
+        project_id: None,
+        analysis_id: None,
+        job_id: None,
+        outcome: TargetOutcome::Finished(JobStatus::Queued),
+    };
+
+    // by id or by (case-insensitive) exact name
+    let project_id = match client.resolve_project(&target.project) {
+        Ok(project) => project.id,
+        Err(e) => {
+            result.outcome = TargetOutcome::Error { stage: "project", message: e.to_string() };
+            return result;
+        },
+    };
+    result.project_id = Some(project_id);
+
+    let files: Vec<&::std::path::Path> = target.files.iter().map(|f| f.as_path()).collect();
+    match client.start_analysis_or_recover(project_id, files) {
+        Ok(AnalysisStart::Started(response)) => {
+            client.progress(&format!("Started analysis {} of project {} with job id {}", response.analysis_id, project_id, response.job_id));
+            result.analysis_id = Some(response.analysis_id);
+            result.job_id = Some(response.job_id);
+        },
+        Ok(AnalysisStart::Recovered(analysis)) => {
+            client.progress(&format!("The upload to project {} reported an error, but analysis {} started anyway", project_id, analysis.id));
+            result.analysis_id = Some(analysis.id);
+            result.outcome = match client.poll_analysis_completion(project_id, analysis.id, polling_strategy) {
+                Ok(status) => TargetOutcome::Finished(status),
+                Err(e) => TargetOutcome::Error { stage: "polling", message: e.to_string() },
+            };
+        },
+        Err(e) => result.outcome = TargetOutcome::Error { stage: "upload", message: e.to_string() },
+    }
+    result
+}
+
+/// The exit code for a batch of targets: the worst of their `TargetOutcome::exit_code`s, where a failure (`1`)
+/// is worse than giving up on an analysis (`polling::EXIT_TIMED_OUT`), which is worse than a failed gate.
+pub fn exit_code(results: &[TargetResult]) -> i32 {
+    // from best to worst
+    let ranking = [0, gate::EXIT_GATE_FAILED, polling::EXIT_TIMED_OUT, 1];
+    results.iter()
+        .map(|r| r.outcome.exit_code())
+        .max_by_key(|code| ranking.iter().position(|c| c == code))
+        .unwrap_or(0)
+}
+
+/// Which targets didn't succeed, e.g. `2 of 5 analyses didn't succeed: api (failed), 12 (error (upload): ...)`,
+/// or `None` if they all did.
+pub fn failure_summary(results: &[TargetResult]) -> Option<String> {
+    let failures: Vec<String> = results.iter()
+        .filter(|r| !r.outcome.is_success())
+        .map(|r| format!("{} ({})", r.target.project, r.outcome.describe()))
+        .collect();
+    if failures.is_empty() {
+        None
+    } else {
+        Some(format!("{} of {} analyses didn't succeed: {}", failures.len(), results.len(), failures.join(", ")))
+    }
+}
+
+/// Render the results as an aligned table, one row per target.
+pub fn render_table(results: &[TargetResult]) -> String {
+    let width = results.iter().map(|r| r.target.project.len()).max().unwrap_or(0);
+    let width = cmp::max(width, "Project".len());
+    let mut out = String::new();
+    writeln!(out, "{:<width$}  {:>8}  {}", "Project", "Analysis", "Outcome", width = width).unwrap();
+    for r in results {
+        let analysis = r.analysis_id.map(|id| id.to_string()).unwrap_or_else(|| "-".to_string());
+        writeln!(out, "{:<width$}  {:>8}  {}", r.target.project, analysis, r.outcome.describe(), width = width).unwrap();
+    }
+    out
+}
+
+#[test]
+fn test_parse_target(){
+    assert_eq!(AnalysisTarget::parse("project=web, file=web/scan.xml,file=web/deps.json"), Ok(AnalysisTarget {
+        project: "web".to_string(),
+        files: vec![PathBuf::from("web/scan.xml"), PathBuf::from("web/deps.json")],
+        fail_on: None,
+    }));
+    assert!(AnalysisTarget::parse("file=web/scan.xml").is_err());
+    assert!(AnalysisTarget::parse("project=web").is_err());
+    assert!(AnalysisTarget::parse("project=web,path=scan.xml").is_err());
+    assert!(AnalysisTarget::parse("project=web,scan.xml").is_err());
+}
+
+#[test]
+fn test_parse_targets_file(){
+    let targets = parse_targets_file("\
+        # services\n\
+        - project: web\n  files: [web.xml]\n  fail-on: critical:0,high:5\n  fail-on-new-only: true\n\
+        - project: 12\n  files:\n    - api.xml\n    - deps.json\n").unwrap();
+    assert_eq!(targets.len(), 2);
+    let gate = targets[0].fail_on.as_ref().unwrap();
+    assert_eq!((gate.thresholds.len(), gate.new_only), (2, true));
+    assert_eq!(targets[1], AnalysisTarget {
+        project: "12".to_string(),
+        files: vec![PathBuf::from("api.xml"), PathBuf::from("deps.json")],
+        fail_on: None,
+    });
+
+    assert!(parse_targets_file("- project: api\n  files: []\n").unwrap_err().contains("needs at least one file"));
+    assert!(parse_targets_file("- project: api\n  files: [api.xml]\n  fail-on: severe:1\n").unwrap_err().contains("isn't a severity"));
+    assert!(parse_targets_file("- project: api\n  file: api.xml\n").is_err());
+    assert!(parse_targets_file("project=web,file=web.xml\n").is_err());
+}
+
+#[test]
+fn test_exit_code_and_table(){
+    let target = |project: &str| AnalysisTarget { project: project.to_string(), files: vec![], fail_on: None };
+    let results = vec![
+        TargetResult { target: target("web"), project_id: Some(1), analysis_id: Some(10), job_id: None, outcome: TargetOutcome::Finished(JobStatus::Completed) },
+        TargetResult { target: target("api"), project_id: None, analysis_id: None, job_id: None, outcome: TargetOutcome::Error { stage: "project", message: "no project named \"api\"".to_string() } },
+    ];
+    assert_eq!(exit_code(&results), 1);
+    assert_eq!(exit_code(&results[..1]), 0);
+    let stuck = || TargetResult { target: target("db"), project_id: Some(2), analysis_id: Some(11), job_id: None, outcome: TargetOutcome::Finished(JobStatus::Queued) };
+    let gated = || TargetResult {
+        target: target("ui"),
+        project_id: Some(3),
+        analysis_id: Some(12),
+        job_id: None,
+        outcome: TargetOutcome::GateFailed(GateReport { rows: vec![gate::GateRow { severity: "Critical", count: 2, max: 0 }] }),
+    };
+    assert_eq!(exit_code(&[stuck()]), polling::EXIT_TIMED_OUT);
+    assert_eq!(exit_code(&[gated()]), gate::EXIT_GATE_FAILED);
+    // the worst outcome wins, whatever the order
+    assert_eq!(exit_code(&[gated(), stuck()]), polling::EXIT_TIMED_OUT);
+    assert_eq!(exit_code(&[stuck(), gated()]), polling::EXIT_TIMED_OUT);
+    let failed = TargetResult { target: target("db"), project_id: Some(2), analysis_id: Some(11), job_id: None, outcome: TargetOutcome::Finished(JobStatus::Failed) };
+    assert_eq!(exit_code(&[gated(), failed, stuck()]), 1);
+    assert_eq!(gated().outcome.describe(), "too many findings (Critical: 2, at most 0)");
+    assert_eq!(failure_summary(&results[..1]), None);
+    assert_eq!(failure_summary(&results).unwrap(), "1 of 2 analyses didn't succeed: api (error (project): no project named \"api\")");
+    assert_eq!(render_table(&results), "\
+        Project  Analysis  Outcome\n\
+        web            10  completed\n\
+        api             -  error (project): no project named \"api\"\n");
+}
+
+#[test]
+fn test_analyze_targets_one_failure_does_not_stop_others(){
+    use test_server::*;
+    let server = TestServer::start(|req| {
+        match (req.method.as_str(), req.path.as_str()) {
+            ("POST", "/codedx/api/projects/query") => TestResponse::json(200, json!([
+                { "id": 1, "name": "api", "parentId": null },
+                { "id": 2, "name": "web", "parentId": null },
+            ])),
+            ("POST", "/codedx/api/projects/1/analysis") => TestResponse::json(202, json!({ "analysisId": 10, "jobId": "job-10" })),
+            ("POST", "/codedx/api/projects/2/analysis") => TestResponse::json(500, json!({ "error": "disk full" })),
+            ("POST", "/codedx/api/projects/3/analysis") => TestResponse::json(202, json!({ "analysisId": 30, "jobId": "job-30" })),
+            ("GET", "/codedx/api/jobs/job-10") => TestResponse::json(200, json!({ "jobId": "job-10", "status": "completed" })),
+            ("GET", "/codedx/api/jobs/job-30") => TestResponse::json(200, json!({ "jobId": "job-30", "status": "completed" })),
+            ("POST", "/codedx/api/projects/1/findings/grouped-counts") => TestResponse::json(200, json!([{ "name": "High", "count": 5 }])),
+            ("POST", "/codedx/api/projects/3/findings/grouped-counts") => TestResponse::json(200, json!([{ "name": "Critical", "count": 2 }])),
+            (_, path) if path.ends_with("/analyses") => TestResponse::json(200, json!([])),
+            _ => TestResponse::status(404),
+        }
+    });
+    let dir = ::test_dir::TestDir::new("targets");
+    let file = dir.file("scan.xml", b"<xml/>");
+
+    let targets = vec![
+        AnalysisTarget { project: "1".to_string(), files: vec![file.clone()], fail_on: None },
+        AnalysisTarget { project: "2".to_string(), files: vec![file.clone()], fail_on: None },
+        AnalysisTarget { project: "3".to_string(), files: vec![file.clone()], fail_on: Some("critical:0".parse().unwrap()) },
+    ];
+    let every_target: SeverityGate = "high:5".parse().unwrap();
+    let results = analyze_targets(&server.client(), &targets, Some(&every_target), 2, ::std::time::Duration::from_millis(10));
+
+    assert_eq!(results[0].outcome.describe(), "completed");
+    assert_eq!(results[0].analysis_id, Some(10));
+    match results[1].outcome {
+        TargetOutcome::Error { stage: "upload", .. } => (),
+        ref other => panic!("expected an upload error, got {:?}", other),
+    }
+    // project 3's own gate replaces the one for every target
+    assert_eq!(results[2].outcome.describe(), "too many findings (Critical: 2, at most 0)");
+    assert_eq!(exit_code(&results), 1);
+    assert_eq!(exit_code(&results[2..]), gate::EXIT_GATE_FAILED);
+}
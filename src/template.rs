@@ -0,0 +1,202 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Templates for analysis names, like `build-{env:BUILD_NUMBER} ({date})`.
+//!
+//! A template is checked when it's parsed, so that a typo in a placeholder fails before anything is
+//! uploaded, and filled in once the analysis has started (since `{analysis-id}` isn't known before).
+//! The placeholders are:
+//!
+//! - `{date}` today's date, e.g. `2024-01-15`, and `{time}` the time of day, e.g. `09:30:00` (both local)
+//! - `{project}` the project's name, and `{project-id}` its id
+//! - `{analysis-id}` the new analysis's id
+//! - `{env:VAR}` the value of the environment variable `VAR`
+//!
+//! `{{` and `}}` stand for a literal `{` and `}`.
+
+use std::env;
+use std::str::FromStr;
+use time;
+
+/// One piece of a template.
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Date,
+    Time,
+    Project,
+    ProjectId,
+    AnalysisId,
+    Env(String),
+}
+
+/// A parsed analysis name template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+}
+
+/// What a template's placeholders are filled in with.
+pub struct NameValues<'a> {
+    /// The project's name, if it's been looked up (see `NameTemplate::needs_project_name`).
+    pub project: Option<&'a str>,
+    pub project_id: u32,
+    pub analysis_id: u32,
+    /// When `{date}` and `{time}` are, usually `time::now()`.
+    pub now: time::Tm,
+}
+
+impl <'a> NameValues<'a> {
+    /// Values for a project, with `now` being the current local time (and the analysis id still to be filled in).
+    pub fn new(project: Option<&'a str>, project_id: u32) -> NameValues<'a> {
+        NameValues { project, project_id, analysis_id: 0, now: time::now() }
+    }
+}
+
+impl NameTemplate {
+    /// Whether the template has a `{project}`, so its name has to be looked up before `render`.
+    pub fn needs_project_name(&self) -> bool {
+        self.parts.contains(&Part::Project)
+    }
+
+    /// Fill in the placeholders, reading `{env:VAR}`s from the environment.
+    ///
+    /// Fails if an environment variable isn't set, or if there's a `{project}` but `values` has no project name.
+    pub fn render(&self, values: &NameValues) -> Result<String, String> {
+        self.render_with(values, |name| env::var(name).ok())
+    }
+
+    /// Like `render`, but with `lookup` standing in for the environment.
+    pub fn render_with<F: Fn(&str) -> Option<String>>(&self, values: &NameValues, lookup: F) -> Result<String, String> {
+        let mut out = String::new();
+        for part in self.parts.iter() {
+            match *part {
+                Part::Literal(ref text) => out.push_str(text),
+                Part::Date => out.push_str(&format!("{:04}-{:02}-{:02}", values.now.tm_year + 1900, values.now.tm_mon + 1, values.now.tm_mday)),
+                Part::Time => out.push_str(&format!("{:02}:{:02}:{:02}", values.now.tm_hour, values.now.tm_min, values.now.tm_sec)),
+                Part::Project => out.push_str(values.project.ok_or("the project's name isn't known")?),
+                Part::ProjectId => out.push_str(&values.project_id.to_string()),
+                Part::AnalysisId => out.push_str(&values.analysis_id.to_string()),
+                Part::Env(ref name) => match lookup(name) {
+                    Some(value) => out.push_str(&value),
+                    None => return Err(format!("the environment variable {} isn't set", name)),
+                },
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<NameTemplate, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = raw.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                },
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(format!("unclosed placeholder {{{} (write {{{{ for a literal {{)", placeholder)),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(literal.split_off(0)));
+                    }
+                    parts.push(parse_placeholder(&placeholder)?);
+                },
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(NameTemplate { parts })
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Part, String> {
+    match placeholder {
+        "date" => Ok(Part::Date),
+        "time" => Ok(Part::Time),
+        "project" => Ok(Part::Project),
+        "project-id" => Ok(Part::ProjectId),
+        "analysis-id" => Ok(Part::AnalysisId),
+        env if env.starts_with("env:") && env.len() > 4 => Ok(Part::Env(env[4..].to_string())),
+        other => Err(format!("unknown placeholder {{{}}}; the placeholders are {{date}}, {{time}}, {{project}}, {{project-id}}, {{analysis-id}} and {{env:VAR}}", other)),
+    }
+}
+
+#[cfg(test)]
+fn test_values() -> NameValues<'static> {
+    NameValues {
+        project: Some("WebGoat"),
+        project_id: 5,
+        analysis_id: 81,
+        now: time::strptime("2024-01-15 09:05:03", "%Y-%m-%d %H:%M:%S").unwrap(),
+    }
+}
+
+#[cfg(test)]
+fn test_render(template: &str) -> Result<String, String> {
+    let lookup = |name: &str| if name == "BUILD_NUMBER" { Some("1234".to_string()) } else { None };
+    NameTemplate::from_str(template)?.render_with(&test_values(), lookup)
+}
+
+#[test]
+fn test_placeholders(){
+    assert_eq!(test_render("{date}"), Ok("2024-01-15".to_string()));
+    assert_eq!(test_render("{time}"), Ok("09:05:03".to_string()));
+    assert_eq!(test_render("{project}"), Ok("WebGoat".to_string()));
+    assert_eq!(test_render("{project-id}"), Ok("5".to_string()));
+    assert_eq!(test_render("{analysis-id}"), Ok("81".to_string()));
+    assert_eq!(test_render("{env:BUILD_NUMBER}"), Ok("1234".to_string()));
+    assert_eq!(test_render("build-{env:BUILD_NUMBER} ({date})"), Ok("build-1234 (2024-01-15)".to_string()));
+    assert_eq!(test_render("plain name"), Ok("plain name".to_string()));
+}
+
+#[test]
+fn test_escapes(){
+    assert_eq!(test_render("{{literal}} {project-id}"), Ok("{literal} 5".to_string()));
+    assert_eq!(test_render("a } b"), Ok("a } b".to_string()));
+}
+
+#[test]
+fn test_template_errors(){
+    assert!(test_render("{dat}").unwrap_err().starts_with("unknown placeholder {dat};"));
+    assert!(test_render("{env:}").unwrap_err().starts_with("unknown placeholder {env:};"));
+    assert!(test_render("build {date").unwrap_err().starts_with("unclosed placeholder {date"));
+    assert_eq!(test_render("{env:NOPE}"), Err("the environment variable NOPE isn't set".to_string()));
+
+    let template: NameTemplate = "{project} #{analysis-id}".parse().unwrap();
+    assert!(template.needs_project_name());
+    let values = NameValues { project: None, ..test_values() };
+    assert_eq!(template.render_with(&values, |_| None), Err("the project's name isn't known".to_string()));
+    assert!(!"{date}".parse::<NameTemplate>().unwrap().needs_project_name());
+}
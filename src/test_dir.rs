@@ -0,0 +1,68 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+
+//! A directory for a test's files, which is removed (with everything in it) when the test is done with it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tells apart the directories made by one test process, since its tests run at the same time.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory under the system's temporary directory, removed when this is dropped,
+/// so that a test's files go away even if one of its assertions fails.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Make a new directory, whose name starts with `name` (e.g. the test's).
+    pub fn new(name: &str) -> TestDir {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let path = ::std::env::temp_dir().join(format!("codedx-client-{}-test-{}-{}", name, process::id(), id));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path of `name` in the directory, which doesn't have to exist.
+    pub fn join<P: AsRef<Path>>(&self, name: P) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// Write a file to `name` in the directory (making any directories in between), returning its path.
+    pub fn file<P: AsRef<Path>, C: AsRef<[u8]>>(&self, name: P, contents: C) -> PathBuf {
+        let path = self.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,225 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A tiny HTTP server that runs inside the test process, for testing `ApiClient` against canned responses.
+//!
+//! It's public so that programs using this crate can test their own code against it too.
+//!
+//! ```
+//! # #[macro_use] extern crate serde_json;
+//! # extern crate codedx_client;
+//! # use codedx_client::test_server::{TestResponse, TestServer};
+//! # fn main() {
+//! let server = TestServer::start(|req| match req.path.as_str() {
+//!     "/codedx/api/projects/query" => TestResponse::json(200, json!([])),
+//!     _ => TestResponse::status(404),
+//! });
+//! let projects = server.client().get_projects().unwrap();
+//! assert!(projects.is_empty());
+//! assert_eq!(server.requests().len(), 1);
+//! # }
+//! ```
+//!
+//! Every response is sent with `Connection: close`, so each request arrives on its own connection.
+
+use client::ApiClient;
+use config::{ClientAuth, ClientConfig};
+use polling::ExponentialBackoff;
+use proxy::ProxyMode;
+use serde_json::{self, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+/// A request received by a `TestServer`.
+#[derive(Clone, Debug)]
+pub struct TestRequest {
+    pub method: String,
+    /// The path of the request, without the query string.
+    pub path: String,
+    /// The query string, if there was one (without the `?`).
+    pub query: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestRequest {
+    /// Get the value of a header (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter()
+            .find(|&&(ref n, _)| n.to_lowercase() == name)
+            .map(|&(_, ref v)| v.as_str())
+    }
+
+    /// Parse the body as JSON, or `Value::Null` if it isn't.
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+/// A response for a `TestServer` to send.
+pub struct TestResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    /// An empty response with the given status.
+    pub fn status(status: u16) -> TestResponse {
+        TestResponse { status, headers: Vec::new(), body: Vec::new() }
+    }
+
+    pub fn json(status: u16, body: Value) -> TestResponse {
+        TestResponse::status(status)
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> TestResponse {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> TestResponse {
+        self.body = body.into();
+        self
+    }
+}
+
+/// A server listening on a random local port, answering every request with `handler`.
+///
+/// The server runs until the test process exits.
+pub struct TestServer {
+    pub base_url: Url,
+    requests: Arc<Mutex<Vec<TestRequest>>>,
+}
+
+impl TestServer {
+    pub fn start<F>(handler: F) -> TestServer
+        where F: Fn(&TestRequest) -> TestResponse + Send + Sync + 'static
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+
+        let server_requests = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    let requests = server_requests.clone();
+                    let handler = handler.clone();
+                    thread::spawn(move || serve(stream, &*handler, &requests));
+                }
+            }
+        });
+
+        TestServer {
+            base_url: Url::parse(&format!("http://127.0.0.1:{}/codedx", port)).unwrap(),
+            requests,
+        }
+    }
+
+    /// A config for connecting to this server with an API key (and never through a proxy),
+    /// retrying after only a few milliseconds.
+    pub fn config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new(self.base_url.clone(), ClientAuth::ApiKey("test-key".to_string()));
+        config.proxy = ProxyMode::Disabled;
+        config.retry.backoff = ExponentialBackoff::new(Duration::from_millis(1), Duration::from_millis(10));
+        config
+    }
+
+    pub fn client(&self) -> ApiClient {
+        ApiClient::new(Box::new(self.config())).unwrap()
+    }
+
+    /// Every request received so far, in the order they arrived.
+    pub fn requests(&self) -> Vec<TestRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn serve<F>(stream: TcpStream, handler: &F, requests: &Mutex<Vec<TestRequest>>)
+    where F: Fn(&TestRequest) -> TestResponse
+{
+    let mut reader = BufReader::new(stream);
+    let request = match read_request(&mut reader) {
+        Some(request) => request,
+        None => return,
+    };
+    let response = handler(&request);
+    requests.lock().unwrap().push(request);
+
+    let mut out = format!("HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, response.body.len());
+    for &(ref name, ref value) in response.headers.iter() {
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str("\r\n");
+
+    let mut stream = reader.into_inner();
+    let _ = stream.write_all(out.as_bytes()).and_then(|_| stream.write_all(&response.body));
+    let _ = stream.flush();
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> Option<TestRequest> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut request_parts = request_line.trim_end().splitn(3, ' ');
+    let method = request_parts.next()?.to_string();
+    let target = request_parts.next()?.to_string();
+    let (path, query) = match target.find('?') {
+        Some(index) => (target[..index].to_string(), Some(target[index + 1..].to_string())),
+        None => (target.clone(), None),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let colon = line.find(':')?;
+        headers.push((line[..colon].trim().to_string(), line[colon + 1..].trim().to_string()));
+    }
+
+    let mut request = TestRequest { method, path, query, headers, body: Vec::new() };
+    let is_chunked = request.header("Transfer-Encoding").map(|te| te.to_lowercase().contains("chunked")).unwrap_or(false);
+    if is_chunked {
+        loop {
+            let mut size_line = String::new();
+            reader.read_line(&mut size_line).ok()?;
+            let size = usize::from_str_radix(size_line.trim().split(';').next()?, 16).ok()?;
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).ok()?;
+            if size == 0 {
+                break;
+            }
+            request.body.extend_from_slice(&chunk[..size]);
+        }
+    } else if let Some(len) = request.header("Content-Length").and_then(|len| len.parse::<usize>().ok()) {
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body).ok()?;
+        request.body = body;
+    }
+    Some(request)
+}
@@ -0,0 +1,193 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Severity counts over a project's recent analyses.
+
+use client::*;
+use std::cmp;
+use std::fmt::Write;
+use summary::{self, SEVERITIES};
+
+/// The severity counts for one analysis.
+pub struct TrendPoint {
+    pub analysis: ApiAnalysis,
+    pub counts: Vec<GroupedCount>,
+}
+
+/// Severity counts for a project's most recent completed analyses.
+pub struct Trend {
+    /// One point per analysis, oldest first.
+    pub points: Vec<TrendPoint>,
+
+    /// Analyses within the window that were skipped because they failed or haven't finished.
+    pub skipped: Vec<ApiAnalysis>,
+}
+
+impl Trend {
+    /// The count for `severity` in each analysis, oldest first.
+    pub fn series(&self, severity: &str) -> Vec<u64> {
+        self.points.iter().map(|p| summary::count_for(&p.counts, severity)).collect()
+    }
+
+    /// The latest count for `severity`, and how it changed since the previous analysis.
+    ///
+    /// The change is `None` if there is only one analysis to look at.
+    pub fn latest(&self, severity: &str) -> Option<(u64, Option<i64>)> {
+        let series = self.series(severity);
+        let n = series.len();
+        match n {
+            0 => None,
+            1 => Some((series[0], None)),
+            _ => Some((series[n - 1], Some(series[n - 1] as i64 - series[n - 2] as i64))),
+        }
+    }
+
+    /// A one-line description of the latest counts, e.g. `Critical 3 (-1), High 12 (+2)`.
+    ///
+    /// Severities with no findings (and no change) are left out.
+    pub fn delta_line(&self) -> String {
+        let mut parts = Vec::new();
+        for severity in SEVERITIES.iter() {
+            match self.latest(severity) {
+                Some((0, None)) | Some((0, Some(0))) | None => (),
+                Some((count, Some(delta))) => parts.push(format!("{} {} ({})", severity, count, summary::format_delta(delta))),
+                Some((count, None)) => parts.push(format!("{} {}", severity, count)),
+            }
+        }
+        if parts.is_empty() {
+            "No findings".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+
+    /// Render the trend as a table with one column per analysis, a sparkline, and the latest change.
+    pub fn render_table(&self) -> String {
+        let mut out = String::new();
+        write!(out, "{:<12}", "Severity").unwrap();
+        for point in self.points.iter() {
+            write!(out, " {:>6}", format!("#{}", point.analysis.id)).unwrap();
+        }
+        writeln!(out, "  {:<width$}  Change", "Trend", width = cmp::max(self.points.len(), 5)).unwrap();
+
+        for severity in SEVERITIES.iter() {
+            let series = self.series(severity);
+            write!(out, "{:<12}", severity).unwrap();
+            for count in series.iter() {
+                write!(out, " {:>6}", count).unwrap();
+            }
+            let change = match self.latest(severity) {
+                Some((_, Some(delta))) => summary::format_delta(delta),
+                _ => "-".to_string(),
+            };
+            writeln!(out, "  {:<width$}  {}", sparkline(&series), change, width = cmp::max(self.points.len(), 5)).unwrap();
+        }
+        out
+    }
+}
+
+/// Whether an analysis finished successfully, and so has meaningful finding counts.
+pub fn is_complete(analysis: &ApiAnalysis) -> bool {
+    match analysis.state {
+        Some(ref state) => {
+            let state = state.to_lowercase();
+            state == "complete" || state == "completed"
+        },
+        // older servers don't report a state; a finished analysis will at least have a finish time
+        None => analysis.finish_time.is_some(),
+    }
+}
+
+/// Load the severity counts for up to `window` of a project's most recent completed analyses.
+///
+/// Working back from the newest analysis, any analysis that failed or is still running is
+/// recorded in `Trend::skipped` instead of being counted against the window.
+pub fn gather_trend(client: &ApiClient, project_id: u32, window: usize) -> ApiResult<Trend> {
+    let mut analyses = client.get_analyses(project_id)?;
+    analyses.sort_by(|a, b| b.id.cmp(&a.id));
+
+    let mut points = Vec::new();
+    let mut skipped = Vec::new();
+    for analysis in analyses {
+        if points.len() >= window {
+            break;
+        }
+        if is_complete(&analysis) {
+            let counts = client.get_finding_counts(project_id, CountGroup::Severity, Some(analysis_filter(analysis.id)))?;
+            points.push(TrendPoint { analysis, counts });
+        } else {
+            skipped.push(analysis);
+        }
+    }
+    points.reverse();
+
+    Ok(Trend { points, skipped })
+}
+
+/// Draw a series of numbers as a tiny bar chart, one character per number.
+pub fn sparkline(values: &[u64]) -> String {
+    const BARS: [char; 8] = ['\u{2581}', '\u{2582}', '\u{2583}', '\u{2584}', '\u{2585}', '\u{2586}', '\u{2587}', '\u{2588}'];
+    let min = values.iter().cloned().min().unwrap_or(0);
+    let max = values.iter().cloned().max().unwrap_or(0);
+    values.iter().map(|&v| {
+        if max == min {
+            BARS[0]
+        } else {
+            BARS[((v - min) * 7 / (max - min)) as usize]
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+fn test_point(id: u32, critical: u64, high: u64) -> TrendPoint {
+    TrendPoint {
+        analysis: ::serde_json::from_value(json!({ "id": id, "state": "complete" })).unwrap(),
+        counts: vec![
+            GroupedCount { name: "Critical".to_string(), count: critical },
+            GroupedCount { name: "High".to_string(), count: high },
+        ],
+    }
+}
+
+#[test]
+fn test_trend_delta_line(){
+    let trend = Trend { points: vec![test_point(1, 4, 10), test_point(2, 3, 12)], skipped: vec![] };
+    assert_eq!(trend.delta_line(), "Critical 3 (-1), High 12 (+2)");
+}
+
+#[test]
+fn test_trend_single_analysis(){
+    let trend = Trend { points: vec![test_point(1, 4, 0)], skipped: vec![] };
+    assert_eq!(trend.latest("Critical"), Some((4, None)));
+    assert_eq!(trend.delta_line(), "Critical 4");
+}
+
+#[test]
+fn test_sparkline(){
+    assert_eq!(sparkline(&[0, 7, 14]), "\u{2581}\u{2584}\u{2588}");
+    assert_eq!(sparkline(&[3, 3]), "\u{2581}\u{2581}");
+    assert_eq!(sparkline(&[]), "");
+}
+
+#[test]
+fn test_is_complete(){
+    let running: ApiAnalysis = ::serde_json::from_value(json!({ "id": 1, "state": "running" })).unwrap();
+    let failed: ApiAnalysis = ::serde_json::from_value(json!({ "id": 2, "state": "failed" })).unwrap();
+    let old_server: ApiAnalysis = ::serde_json::from_value(json!({ "id": 3, "finishTime": "2017-10-05T12:00:00Z" })).unwrap();
+    assert!(!is_complete(&running));
+    assert!(!is_complete(&failed));
+    assert!(is_complete(&old_server));
+}
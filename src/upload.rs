@@ -0,0 +1,790 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! `multipart/form-data` request bodies for uploading files to Code Dx.
+//!
+//! reqwest's own multipart support sends the filename of any part that isn't plain ASCII
+//! (even one with just a space in it) *only* as an RFC 5987 `filename*` parameter,
+//! which RFC 7578 forbids and which many servers ignore. This module writes the
+//! `Content-Disposition` of each part itself:
+//!
+//!  - The filename is always just the final component of the path, whether the path
+//!    uses `/` or `\` as its separator.
+//!  - `filename` is the UTF-8 name, with `"`, CR and LF percent-encoded (as browsers do).
+//!  - If the name isn't ASCII, a `filename*=UTF-8''...` parameter follows it,
+//!    for the benefit of servers that only decode that form.
+//!
+//! File contents are never loaded into memory: each part reads from its file as the request
+//! body is sent (with a `Content-Length` worked out from the file sizes up front), so uploading
+//! a multi-GB file takes no more memory than uploading a small one. An `UploadInput::Stream` has no
+//! size to go by until it's been read, so it's copied to a temporary file first.
+//!
+//! A form can also report how much of each file has been sent (`UploadForm::on_progress`),
+//! since uploading a large binary can take minutes; `ProgressPrinter` shows that on STDERR.
+
+use reqwest;
+use std::fmt;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+use time;
+
+/// A file to upload, and optionally the name to give it on the server instead of its own.
+#[derive(Copy, Clone, Debug)]
+pub struct UploadFile<'a> {
+    pub path: &'a Path,
+    pub name: Option<&'a str>,
+}
+impl <'a> From<&'a Path> for UploadFile<'a> {
+    fn from(path: &'a Path) -> UploadFile<'a> {
+        UploadFile { path, name: None }
+    }
+}
+
+/// Something to upload: a file, or content read from somewhere else (e.g. STDIN),
+/// which has no path to take a name from, so it has to be given one.
+pub enum UploadInput<'a> {
+    File(UploadFile<'a>),
+    Stream { name: String, content: Box<Read + Send> },
+}
+impl <'a> From<UploadFile<'a>> for UploadInput<'a> {
+    fn from(file: UploadFile<'a>) -> UploadInput<'a> {
+        UploadInput::File(file)
+    }
+}
+impl <'a> From<&'a Path> for UploadInput<'a> {
+    fn from(path: &'a Path) -> UploadInput<'a> {
+        UploadInput::File(path.into())
+    }
+}
+
+impl <'a> UploadInput<'a> {
+    /// The name the input will be sent under (without any directories).
+    pub fn name(&self) -> String {
+        match *self {
+            UploadInput::File(UploadFile { name: Some(name), .. }) => normalize_file_name(name).to_string(),
+            UploadInput::File(UploadFile { path, name: None }) => normalize_file_name(&path.to_string_lossy()).to_string(),
+            UploadInput::Stream { ref name, .. } => normalize_file_name(name).to_string(),
+        }
+    }
+
+    /// A part for the input, named as given by `name`.
+    pub fn into_part(self) -> io::Result<UploadPart> {
+        let name = self.name();
+        let part = match self {
+            UploadInput::File(file) => UploadPart::file(file.path)?,
+            UploadInput::Stream { name, content } => UploadPart::stream(&name, content)?,
+        };
+        Ok(part.file_name(&name))
+    }
+}
+
+/// Why a file can't be uploaded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileProblem {
+    Missing,
+    Directory,
+    Unreadable(String),
+    Empty,
+}
+
+impl fmt::Display for FileProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FileProblem::Missing => f.write_str("doesn't exist"),
+            FileProblem::Directory => f.write_str("is a directory (zip it up to upload it)"),
+            FileProblem::Unreadable(ref reason) => write!(f, "can't be read: {}", reason),
+            FileProblem::Empty => f.write_str("is empty"),
+        }
+    }
+}
+
+/// A file that can't be uploaded, and why.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputProblem {
+    pub path: PathBuf,
+    pub problem: FileProblem,
+}
+
+/// Check that every one of `paths` is a readable, non-empty file, and get their total size.
+///
+/// Fails with every problem found, not just the first, so that they can all be fixed at once.
+pub fn check_files(paths: &[&Path]) -> Result<u64, Vec<InputProblem>> {
+    let mut total = 0;
+    let mut problems = Vec::new();
+    for &path in paths {
+        let problem = match fs::metadata(path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Some(FileProblem::Missing),
+            Err(e) => Some(FileProblem::Unreadable(e.to_string())),
+            Ok(ref metadata) if metadata.is_dir() => Some(FileProblem::Directory),
+            Ok(metadata) => match File::open(path) {
+                Err(e) => Some(FileProblem::Unreadable(e.to_string())),
+                Ok(_) if metadata.len() == 0 => Some(FileProblem::Empty),
+                Ok(_) => {
+                    total += metadata.len();
+                    None
+                },
+            },
+        };
+        if let Some(problem) = problem {
+            problems.push(InputProblem { path: path.to_path_buf(), problem });
+        }
+    }
+    if problems.is_empty() { Ok(total) } else { Err(problems) }
+}
+
+/// One part of an `UploadForm`.
+pub struct UploadPart {
+    file_name: Option<String>,
+    content: Box<Read + Send>,
+    len: u64,
+}
+
+impl UploadPart {
+    /// A part containing `len` bytes read from `content`, with no filename.
+    pub fn new(content: Box<Read + Send>, len: u64) -> UploadPart {
+        UploadPart { file_name: None, content, len }
+    }
+
+    /// A part containing the file at `path`, named after the file.
+    pub fn file(path: &Path) -> io::Result<UploadPart> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let part = UploadPart::new(Box::new(file), len);
+        Ok(part.file_name(&path.to_string_lossy()))
+    }
+
+    /// A part containing everything that can be read from `content`, named `name`.
+    ///
+    /// The form needs to know every part's size before sending anything, so the content is copied
+    /// to a temporary file first (removed again when the part is dropped), rather than held in memory.
+    /// Fails if there's nothing to read, e.g. because STDIN was closed or nothing was piped into it,
+    /// rather than uploading an empty file.
+    pub fn stream(name: &str, mut content: Box<Read + Send>) -> io::Result<UploadPart> {
+        let mut spooled = SpooledFile::create()?;
+        let len = io::copy(&mut content, &mut spooled.file)?;
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("there was nothing to read for {}", name)));
+        }
+        spooled.file.seek(SeekFrom::Start(0))?;
+        Ok(UploadPart::new(Box::new(spooled), len).file_name(name))
+    }
+
+    /// Set the filename sent to the server; any directories in `name` are dropped.
+    pub fn file_name(mut self, name: &str) -> UploadPart {
+        self.file_name = Some(normalize_file_name(name).to_string());
+        self
+    }
+}
+
+/// A temporary file that `UploadPart::stream` copies its content to, deleted when it's dropped.
+struct SpooledFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl SpooledFile {
+    fn create() -> io::Result<SpooledFile> {
+        let path = env::temp_dir().join(format!("codedx-client-stdin-{}-{:x}.tmp", process::id(), time::precise_time_ns()));
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        Ok(SpooledFile { file, path })
+    }
+}
+
+impl Read for SpooledFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Drop for SpooledFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// How far along an upload is, as given to an `UploadForm::on_progress` callback.
+///
+/// Only the contents of the parts are counted, not the multipart headers around them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UploadProgress {
+    /// Which part is being sent (0-based).
+    pub file_index: usize,
+    pub file_count: usize,
+    /// The filename of the part being sent, if it has one.
+    pub file_name: Option<String>,
+    pub file_sent: u64,
+    pub file_len: u64,
+    pub total_sent: u64,
+    pub total_len: u64,
+}
+
+impl UploadProgress {
+    pub fn file_percent(&self) -> u32 {
+        percent(self.file_sent, self.file_len)
+    }
+
+    pub fn total_percent(&self) -> u32 {
+        percent(self.total_sent, self.total_len)
+    }
+
+    /// Whether every byte of every part has been sent.
+    pub fn is_done(&self) -> bool {
+        self.total_sent == self.total_len
+    }
+}
+
+fn percent(sent: u64, len: u64) -> u32 {
+    if len == 0 { 100 } else { (sent.saturating_mul(100) / len) as u32 }
+}
+
+/// A callback for `UploadForm::on_progress`; it's called after every read from the body.
+pub type ProgressCallback = Box<FnMut(&UploadProgress) + Send>;
+
+/// A `multipart/form-data` body whose file contents are streamed rather than loaded into memory.
+pub struct UploadForm {
+    boundary: String,
+    parts: Vec<(String, UploadPart)>,
+    progress: Option<ProgressCallback>,
+}
+
+impl UploadForm {
+    pub fn new() -> UploadForm {
+        let seed = time::precise_time_ns() ^ ((process::id() as u64) << 40);
+        UploadForm::with_boundary(format!("codedx-client-{:016x}", seed))
+    }
+
+    /// A form with a specific boundary; mainly useful to get predictable output in tests.
+    pub fn with_boundary(boundary: String) -> UploadForm {
+        UploadForm { boundary, parts: Vec::new(), progress: None }
+    }
+
+    /// Add a part, builder style.
+    pub fn part(mut self, name: &str, part: UploadPart) -> UploadForm {
+        self.parts.push((name.to_string(), part));
+        self
+    }
+
+    /// Call `callback` as the body is read, with how much of it has been sent so far.
+    pub fn on_progress(mut self, callback: ProgressCallback) -> UploadForm {
+        self.progress = Some(callback);
+        self
+    }
+
+    /// The value for the request's `Content-Type` header.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Each part's name, filename (if it has one) and size, e.g. `file: app.zip (2048 bytes)`, for showing what would be sent.
+    pub fn describe_parts(&self) -> Vec<String> {
+        self.parts.iter().map(|&(ref name, ref part)| match part.file_name {
+            Some(ref file_name) => format!("{}: {} ({} bytes)", name, file_name, part.len),
+            None => format!("{}: ({} bytes)", name, part.len),
+        }).collect()
+    }
+
+    /// The total size of the body, in bytes.
+    pub fn len(&self) -> u64 {
+        let headers: u64 = self.parts.iter()
+            .map(|&(ref name, ref part)| (self.part_head(name, part).len() + 2) as u64 + part.len)
+            .sum();
+        headers + self.tail().len() as u64
+    }
+
+    /// Get a reader for the whole body.
+    pub fn into_reader(self) -> Box<Read + Send> {
+        let tail = self.tail();
+        let mut reader: Box<Read + Send> = Box::new(io::empty());
+        let heads: Vec<String> = self.parts.iter().map(|&(ref name, ref part)| self.part_head(name, part)).collect();
+
+        // where each part's contents start and end in the body, for the progress callback
+        let mut spans = Vec::new();
+        let mut offset = 0;
+        for (head, &(_, ref part)) in heads.iter().zip(self.parts.iter()) {
+            offset += head.len() as u64;
+            spans.push(PartSpan { file_name: part.file_name.clone(), start: offset, len: part.len });
+            offset += part.len + 2;
+        }
+
+        for (head, (_, part)) in heads.into_iter().zip(self.parts.into_iter()) {
+            reader = Box::new(reader
+                .chain(Cursor::new(head.into_bytes()))
+                .chain(part.content.take(part.len))
+                .chain(Cursor::new(b"\r\n".to_vec())));
+        }
+        let reader = Box::new(reader.chain(Cursor::new(tail.into_bytes())));
+        match self.progress {
+            Some(callback) => Box::new(ProgressReader { inner: reader, read: 0, spans, callback }),
+            None => reader,
+        }
+    }
+
+    pub fn into_body(self) -> reqwest::Body {
+        let len = self.len();
+        reqwest::Body::sized(self.into_reader(), len)
+    }
+
+    fn part_head(&self, name: &str, part: &UploadPart) -> String {
+        format!(
+            "--{}\r\nContent-Disposition: {}\r\nContent-Type: application/octet-stream\r\n\r\n",
+            self.boundary,
+            content_disposition(name, part.file_name.as_ref().map(|s| s.as_str()))
+        )
+    }
+
+    fn tail(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+}
+
+impl Default for UploadForm {
+    fn default() -> UploadForm {
+        UploadForm::new()
+    }
+}
+
+struct PartSpan {
+    file_name: Option<String>,
+    start: u64,
+    len: u64,
+}
+
+/// Counts the bytes read from a form's body, and reports them to a progress callback.
+struct ProgressReader {
+    inner: Box<Read + Send>,
+    read: u64,
+    spans: Vec<PartSpan>,
+    callback: ProgressCallback,
+}
+
+impl ProgressReader {
+    fn progress(&self) -> UploadProgress {
+        let sent = |span: &PartSpan| ::std::cmp::min(self.read.saturating_sub(span.start), span.len);
+        // the last part that has started, or the first if none has
+        let file_index = self.spans.iter().rposition(|span| self.read > span.start).unwrap_or(0);
+        let current = self.spans.get(file_index);
+        UploadProgress {
+            file_index,
+            file_count: self.spans.len(),
+            file_name: current.and_then(|span| span.file_name.clone()),
+            file_sent: current.map(&sent).unwrap_or(0),
+            file_len: current.map(|span| span.len).unwrap_or(0),
+            total_sent: self.spans.iter().map(&sent).sum(),
+            total_len: self.spans.iter().map(|span| span.len).sum(),
+        }
+    }
+}
+
+impl Read for ProgressReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.read += n as u64;
+            let progress = self.progress();
+            (self.callback)(&progress);
+        }
+        Ok(n)
+    }
+}
+
+/// Shows an upload's progress on STDERR: as a bar that redraws itself when STDERR is a terminal,
+/// and otherwise (e.g. in a CI log) as a plain line every few seconds, and whenever a file finishes.
+pub struct ProgressPrinter {
+    interactive: bool,
+    interval: Duration,
+    last_printed: Option<Instant>,
+    last_file: Option<usize>,
+    finished_file: Option<usize>,
+    done: bool,
+}
+
+impl ProgressPrinter {
+    /// A printer that draws a bar if STDERR is a terminal.
+    pub fn new() -> ProgressPrinter {
+        ProgressPrinter::with_style(stderr_is_terminal())
+    }
+
+    pub fn with_style(interactive: bool) -> ProgressPrinter {
+        let interval = if interactive { Duration::from_millis(100) } else { Duration::from_secs(5) };
+        ProgressPrinter { interactive, interval, last_printed: None, last_file: None, finished_file: None, done: false }
+    }
+
+    /// Use this printer as a form's progress callback.
+    pub fn into_callback(mut self) -> ProgressCallback {
+        Box::new(move |progress: &UploadProgress| self.report(progress))
+    }
+
+    pub fn report(&mut self, progress: &UploadProgress) {
+        if self.done || progress.file_count == 0 {
+            return;
+        }
+        let file_finished = progress.file_sent == progress.file_len && self.finished_file != Some(progress.file_index);
+        let due = self.last_printed.map_or(true, |at| at.elapsed() >= self.interval);
+        if !due && !file_finished {
+            return;
+        }
+        self.done = file_finished && progress.file_index + 1 == progress.file_count;
+        if self.interactive {
+            // leave the previous file's bar behind, and start a new one
+            if self.last_file.map_or(false, |last| last != progress.file_index) {
+                eprintln!();
+            }
+            eprint!("\r{}", render_bar(progress));
+            if self.done {
+                eprintln!();
+            }
+        } else {
+            eprintln!("{}", render_line(progress));
+        }
+        if file_finished {
+            self.finished_file = Some(progress.file_index);
+        }
+        self.last_printed = Some(Instant::now());
+        self.last_file = Some(progress.file_index);
+    }
+}
+
+impl Default for ProgressPrinter {
+    fn default() -> ProgressPrinter {
+        ProgressPrinter::new()
+    }
+}
+
+const BAR_WIDTH: usize = 30;
+
+/// A one-line progress bar, e.g. `# [==========>          ] 34% scan.zip, 12% overall`.
+pub fn render_bar(progress: &UploadProgress) -> String {
+    let filled = BAR_WIDTH * progress.file_percent() as usize / 100;
+    let mut bar = "=".repeat(filled);
+    if filled < BAR_WIDTH {
+        bar.push('>');
+        bar.push_str(&" ".repeat(BAR_WIDTH - filled - 1));
+    }
+    format!("# [{}] {:>3}% {}, {}% overall", bar, progress.file_percent(), describe_file(progress), progress.total_percent())
+}
+
+/// A progress line for logs, e.g. `# Uploading scan.zip (file 1 of 2): 34% of 1.2 MB, 12% overall`.
+pub fn render_line(progress: &UploadProgress) -> String {
+    format!(
+        "# Uploading {} (file {} of {}): {}% of {}, {}% overall",
+        describe_file(progress), progress.file_index + 1, progress.file_count,
+        progress.file_percent(), format_size(progress.file_len), progress.total_percent()
+    )
+}
+
+fn describe_file(progress: &UploadProgress) -> String {
+    progress.file_name.clone().unwrap_or_else(|| format!("part {}", progress.file_index + 1))
+}
+
+/// A byte count in the largest unit that keeps it at least 1, e.g. `1.2 MB`.
+pub fn format_size(bytes: u64) -> String {
+    let units = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+/// Parse a size like `5G`, `500MB`, `64k` or `1024` (bytes); as with `format_size`, a kilobyte is 1024 bytes.
+pub fn parse_size(raw: &str) -> Option<u64> {
+    let raw = raw.trim();
+    let digits = raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len());
+    let number: u64 = raw[..digits].parse().ok()?;
+    let shift = match raw[digits..].trim().to_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return None,
+    };
+    number.checked_mul(1 << shift)
+}
+
+/// Whether STDERR is a terminal (as opposed to a file or pipe).
+///
+/// Off unix this can't be told without more dependencies, so it's treated as not a terminal,
+/// which gets the plain progress lines.
+pub fn stderr_is_terminal() -> bool {
+    is_terminal(2)
+}
+
+/// Whether STDIN is a terminal, i.e. whether there's someone to answer a question; never, off unix.
+pub fn stdin_is_terminal() -> bool {
+    is_terminal(0)
+}
+
+#[cfg(unix)]
+fn is_terminal(fd: ::std::os::raw::c_int) -> bool {
+    extern "C" {
+        fn isatty(fd: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+    }
+    unsafe { isatty(fd) == 1 }
+}
+
+#[cfg(not(unix))]
+fn is_terminal(_fd: i32) -> bool {
+    false
+}
+
+/// Get the final component of a path, treating both `/` and `\` as separators
+/// regardless of the platform we're running on.
+pub fn normalize_file_name(path: &str) -> &str {
+    let trimmed = path.trim_end_matches(|c: char| c == '/' || c == '\\');
+    match trimmed.rfind(|c: char| c == '/' || c == '\\') {
+        Some(index) => &trimmed[index + 1..],
+        None => trimmed,
+    }
+}
+
+/// Extensions of compressed files, which go with the extension before them (e.g. `.tar.gz`, `.xml.bz2`).
+const COMPRESSED_EXTENSIONS: &[&str] = &[".gz", ".bz2", ".xz", ".zst", ".z"];
+
+/// `name`, or if it's already one of `taken`, the name with the lowest number added that isn't,
+/// e.g. `scan (2).xml` for a second `scan.xml`, or `scan (2).tar.gz` for a second `scan.tar.gz`.
+pub fn unique_file_name(name: &str, taken: &[String]) -> String {
+    if !taken.iter().any(|t| t == name) {
+        return name.to_string();
+    }
+    // keep the extension at the end, so the server still recognizes the file type
+    let extension_start = |name: &str| match name.rfind('.') {
+        Some(index) if index > 0 => Some(index),
+        _ => None,
+    };
+    let split = match extension_start(name) {
+        Some(index) if COMPRESSED_EXTENSIONS.contains(&name[index..].to_lowercase().as_str()) => {
+            Some(extension_start(&name[..index]).unwrap_or(index))
+        },
+        index => index,
+    };
+    let (stem, extension) = match split {
+        Some(index) => (&name[..index], &name[index..]),
+        None => (name, ""),
+    };
+    (2..).map(|n| format!("{} ({}){}", stem, n, extension))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
+/// The value of a part's `Content-Disposition` header (without the header name).
+pub fn content_disposition(name: &str, file_name: Option<&str>) -> String {
+    let mut value = format!("form-data; name=\"{}\"", quote_parameter(name));
+    if let Some(file_name) = file_name {
+        value.push_str(&format!("; filename=\"{}\"", quote_parameter(file_name)));
+        if !file_name.is_ascii() {
+            value.push_str(&format!("; filename*=UTF-8''{}", ext_value(file_name)));
+        }
+    }
+    value
+}
+
+/// Escape a parameter value to go between double quotes, the way RFC 7578 (section 4.2) suggests.
+fn quote_parameter(raw: &str) -> String {
+    raw.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Percent-encode a value for an RFC 5987 extended parameter.
+fn ext_value(raw: &str) -> String {
+    let mut encoded = String::new();
+    for byte in raw.bytes() {
+        match byte {
+            b'a' ... b'z' | b'A' ... b'Z' | b'0' ... b'9' |
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[test]
+fn test_windows_path_file_name(){
+    let name = normalize_file_name("target\\reports\\scan.xml");
+    assert_eq!(name, "scan.xml");
+    assert_eq!(content_disposition("file0", Some(name)), "form-data; name=\"file0\"; filename=\"scan.xml\"");
+    assert_eq!(normalize_file_name("C:\\work/mixed\\dir/findings.json"), "findings.json");
+    assert_eq!(normalize_file_name("target/reports/"), "reports");
+}
+
+#[test]
+fn test_unique_file_name(){
+    let taken = vec!["scan.xml".to_string(), "scan (2).xml".to_string(), "README".to_string()];
+    assert_eq!(unique_file_name("other.xml", &taken), "other.xml");
+    assert_eq!(unique_file_name("scan.xml", &taken), "scan (3).xml");
+    assert_eq!(unique_file_name("README", &taken), "README (2)");
+    assert_eq!(unique_file_name(".hidden", &[".hidden".to_string()]), ".hidden (2)");
+    assert_eq!(unique_file_name("scan.tar.gz", &["scan.tar.gz".to_string()]), "scan (2).tar.gz");
+    assert_eq!(unique_file_name("results.xml.BZ2", &["results.xml.BZ2".to_string()]), "results (2).xml.BZ2");
+    assert_eq!(unique_file_name("log.gz", &["log.gz".to_string()]), "log (2).gz");
+    assert_eq!(unique_file_name("v1.2.zip", &["v1.2.zip".to_string()]), "v1.2 (2).zip");
+}
+
+#[test]
+fn test_file_name_with_spaces(){
+    let name = normalize_file_name("/home/me/My Scans/scan results.xml");
+    assert_eq!(content_disposition("file1", Some(name)), "form-data; name=\"file1\"; filename=\"scan results.xml\"");
+}
+
+#[test]
+fn test_cjk_file_name(){
+    let name = normalize_file_name("reports\\スキャン結果.xml");
+    assert_eq!(
+        content_disposition("file0", Some(name)),
+        "form-data; name=\"file0\"; filename=\"スキャン結果.xml\"; filename*=UTF-8''%E3%82%B9%E3%82%AD%E3%83%A3%E3%83%B3%E7%B5%90%E6%9E%9C.xml"
+    );
+}
+
+#[test]
+fn test_file_name_quotes_and_newlines(){
+    assert_eq!(content_disposition("file0", Some("a\"b\r\n.xml")), "form-data; name=\"file0\"; filename=\"a%22b%0D%0A.xml\"");
+}
+
+#[test]
+fn test_upload_form_body(){
+    let part = UploadPart::new(Box::new(Cursor::new(b"<xml/>".to_vec())), 6).file_name("target\\scan.xml");
+    let form = UploadForm::with_boundary("XYZ".to_string()).part("file0", part);
+    assert_eq!(form.content_type(), "multipart/form-data; boundary=XYZ");
+    let len = form.len();
+
+    let mut body = String::new();
+    form.into_reader().read_to_string(&mut body).unwrap();
+    assert_eq!(body, "--XYZ\r\n\
+        Content-Disposition: form-data; name=\"file0\"; filename=\"scan.xml\"\r\n\
+        Content-Type: application/octet-stream\r\n\r\n\
+        <xml/>\r\n\
+        --XYZ--\r\n");
+    assert_eq!(len, body.len() as u64);
+}
+
+#[test]
+fn test_check_files(){
+    let dir = ::test_dir::TestDir::new("check-files");
+    fs::create_dir_all(dir.join("classes")).unwrap();
+    let (war, zip, empty) = (dir.file("webapp.war", b"war!"), dir.file("source.zip", b"zip"), dir.file("empty.xml", b""));
+    let (classes, missing) = (dir.join("classes"), dir.join("missing.jar"));
+
+    let good = check_files(&[&war, &zip]);
+    let bad = check_files(&[&war, &missing, &classes, &empty]);
+
+    assert_eq!(good, Ok(7));
+    assert_eq!(bad, Err(vec![
+        InputProblem { path: missing, problem: FileProblem::Missing },
+        InputProblem { path: classes, problem: FileProblem::Directory },
+        InputProblem { path: empty, problem: FileProblem::Empty },
+    ]));
+}
+
+#[test]
+fn test_parse_size(){
+    assert_eq!(parse_size("1024"), Some(1024));
+    assert_eq!(parse_size("64k"), Some(64 * 1024));
+    assert_eq!(parse_size("500MB"), Some(500 * 1024 * 1024));
+    assert_eq!(parse_size("5 G"), Some(5 * 1024 * 1024 * 1024));
+    assert_eq!(parse_size("5X"), None);
+    assert_eq!(parse_size("G"), None);
+}
+
+#[test]
+fn test_stream_part(){
+    let mut part = UploadPart::stream("results.zip", Box::new(Cursor::new(b"PK..".to_vec()))).unwrap();
+    assert_eq!((part.file_name.as_ref().map(|s| s.as_str()), part.len), (Some("results.zip"), 4));
+    let mut content = Vec::new();
+    part.content.read_to_end(&mut content).unwrap();
+    assert_eq!(content, b"PK..");
+
+    let empty = UploadPart::stream("results.zip", Box::new(io::empty())).err().unwrap();
+    assert_eq!(empty.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(empty.to_string(), "there was nothing to read for results.zip");
+}
+
+#[test]
+fn test_upload_form_streams_contents(){
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // a part far too big to buffer, which counts how much has been read from it
+    struct Counting { read: Arc<AtomicUsize>, inner: io::Take<io::Repeat> }
+    impl Read for Counting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read.fetch_add(n, Ordering::SeqCst);
+            Ok(n)
+        }
+    }
+    let read = Arc::new(AtomicUsize::new(0));
+    let len: u64 = 4 << 30;
+    let content = Counting { read: read.clone(), inner: io::repeat(b'x').take(len) };
+    let form = UploadForm::with_boundary("XYZ".to_string()).part("file0", UploadPart::new(Box::new(content), len).file_name("huge.bin"));
+    assert!(form.len() > len);
+
+    let mut body = form.into_reader();
+    assert_eq!(read.load(Ordering::SeqCst), 0);
+    let mut buffer = vec![0; 1 << 20];
+    let mut sent = 0;
+    for _ in 0..16 {
+        sent += body.read(&mut buffer).unwrap();
+    }
+    // the file is only read as fast as the body is sent
+    assert!(sent > 0);
+    assert!(read.load(Ordering::SeqCst) <= sent);
+}
+
+#[test]
+fn test_upload_form_progress(){
+    use std::sync::{Arc, Mutex};
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let recorder = reports.clone();
+    let form = UploadForm::with_boundary("XYZ".to_string())
+        .part("file0", UploadPart::new(Box::new(Cursor::new(b"12345678".to_vec())), 8).file_name("a.zip"))
+        .part("file1", UploadPart::new(Box::new(Cursor::new(b"abcd".to_vec())), 4).file_name("b.xml"))
+        .on_progress(Box::new(move |progress: &UploadProgress| recorder.lock().unwrap().push(progress.clone())));
+
+    let mut body = Vec::new();
+    form.into_reader().read_to_end(&mut body).unwrap();
+
+    let reports = reports.lock().unwrap();
+    let last = reports.last().unwrap();
+    assert_eq!(*last, UploadProgress {
+        file_index: 1, file_count: 2, file_name: Some("b.xml".to_string()),
+        file_sent: 4, file_len: 4, total_sent: 12, total_len: 12,
+    });
+    assert!(last.is_done());
+    assert!(reports.iter().any(|p| p.file_index == 0 && p.file_sent == 8 && p.total_sent == 8));
+    assert!(reports.windows(2).all(|pair| pair[0].total_sent <= pair[1].total_sent));
+}
+
+#[test]
+fn test_render_progress(){
+    let progress = UploadProgress {
+        file_index: 0, file_count: 2, file_name: Some("scan.zip".to_string()),
+        file_sent: 512 * 1024, file_len: 2 * 1024 * 1024, total_sent: 512 * 1024, total_len: 4 * 1024 * 1024,
+    };
+    assert_eq!(render_line(&progress), "# Uploading scan.zip (file 1 of 2): 25% of 2.0 MB, 12% overall");
+    assert_eq!(render_bar(&progress), "# [=======>                      ]  25% scan.zip, 12% overall");
+    assert_eq!(format_size(100), "100 B");
+    assert_eq!(format_size(1536), "1.5 KB");
+    assert_eq!(percent(0, 0), 100);
+}
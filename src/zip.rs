@@ -0,0 +1,470 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Zipping up a source directory to upload it (`analyze --zip-dir`).
+//!
+//! Code Dx wants source code as a zip file. The entries are deflated as they're written (source code
+//! compresses well, and the zip has to go over the network). Names inside the zip are relative to
+//! the directory, with `/` separators.
+//!
+//! Some things are left out of the zip:
+//!
+//!  - `.git` directories, always.
+//!  - Anything matching one of `ZipOptions::excludes`. A pattern with a `/` in it (like `node_modules/**`)
+//!    is matched against the whole path from the directory; one without (like `*.log`) is matched
+//!    against the names of files and directories at any depth. Excluding a directory excludes everything in it.
+//!  - With `ZipOptions::use_gitignore`, anything matched by a `.gitignore` file in the directory
+//!    or one of its subdirectories (including `!` rules that bring a file back).
+//!  - Symbolic links, unless `ZipOptions::follow_symlinks` is set.
+//!
+//! The format has no ZIP64 support, so a zip can have at most 65535 entries and 4 GiB in total
+//! (as can each file in it); a bigger one is an error, rather than a zip with the sizes cut off.
+
+use glob;
+use libflate::deflate;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::UNIX_EPOCH;
+use time;
+use upload::normalize_file_name;
+
+/// What to leave out of a zip, and what to do about symbolic links.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ZipOptions {
+    /// Patterns for paths (relative to the directory) to leave out.
+    pub excludes: Vec<String>,
+    pub use_gitignore: bool,
+    /// Zip up what symbolic links point to, instead of skipping them.
+    pub follow_symlinks: bool,
+}
+
+/// A file to put in a zip, and its name in the zip.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZipEntry {
+    pub path: PathBuf,
+    pub name: String,
+}
+
+/// What went into a zip.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZipSummary {
+    pub files: usize,
+    /// The total size of the files, before zipping.
+    pub bytes: u64,
+}
+
+/// A rule from `--zip-exclude` or a `.gitignore` file.
+#[derive(Clone, Debug)]
+struct IgnoreRule {
+    /// The directory the rule applies in, relative to the one being zipped (and ending in `/`, unless it's that one).
+    base: String,
+    pattern: String,
+    /// Matched against the whole path from `base`, rather than just the last name.
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl IgnoreRule {
+    fn parse(base: &str, line: &str) -> Option<IgnoreRule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = if line.starts_with('!') { (true, &line[1..]) } else { (false, line) };
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').to_string();
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(IgnoreRule { base: base.to_string(), pattern, anchored, dir_only, negated })
+    }
+
+    /// Whether the rule applies to a path (relative to the directory being zipped).
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if (self.dir_only && !is_dir) || !path.starts_with(&self.base) {
+            return false;
+        }
+        let path = &path[self.base.len()..];
+        if self.anchored {
+            glob::matches_path(&self.pattern, path)
+        } else {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            glob::matches_component(&self.pattern, name)
+        }
+    }
+}
+
+/// Whether the last of `rules` that applies to a path says to leave it out.
+fn is_ignored(rules: &[IgnoreRule], path: &str, is_dir: bool) -> bool {
+    rules.iter().rev().find(|rule| rule.matches(path, is_dir)).map_or(false, |rule| !rule.negated)
+}
+
+/// Split a `--zip-dir` value like `web/src:web-source.zip` into the directory and the name to upload its zip as.
+///
+/// Without a `:<NAME>.zip` at the end, the zip is named after the directory, e.g. `src.zip`.
+pub fn parse_dir_spec(raw: &str) -> (PathBuf, String) {
+    if let Some(index) = raw.rfind(':') {
+        let name = &raw[index + 1..];
+        // so that a Windows path like C:\src isn't mistaken for a name
+        if name.to_lowercase().ends_with(".zip") && !name.contains(|c: char| c == '/' || c == '\\') {
+            return (PathBuf::from(&raw[..index]), name.to_string());
+        }
+    }
+    let dir = PathBuf::from(raw);
+    // canonical, so that `.` is named after the directory it stands for
+    let full = fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone());
+    let base = match normalize_file_name(&full.to_string_lossy()) {
+        "" | "." | ".." => "source".to_string(),
+        base => base.to_string(),
+    };
+    (dir, format!("{}.zip", base))
+}
+
+/// Find the files in `root` that should go into its zip, in order by name.
+pub fn collect_entries(root: &Path, options: &ZipOptions) -> io::Result<Vec<ZipEntry>> {
+    if !root.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't a directory", root.display())));
+    }
+    let mut rules: Vec<IgnoreRule> = options.excludes.iter().filter_map(|pattern| IgnoreRule::parse("", pattern)).collect();
+    let mut entries = Vec::new();
+    let mut visited = Vec::new();
+    collect_dir(root, "", options, &mut rules, &mut visited, &mut entries)?;
+    Ok(entries)
+}
+
+fn collect_dir(dir: &Path, prefix: &str, options: &ZipOptions, rules: &mut Vec<IgnoreRule>, visited: &mut Vec<PathBuf>, entries: &mut Vec<ZipEntry>) -> io::Result<()> {
+    // a followed link back up the tree would go around forever
+    let canonical = fs::canonicalize(dir)?;
+    if visited.contains(&canonical) {
+        return Ok(());
+    }
+    visited.push(canonical);
+
+    let rule_count = rules.len();
+    if options.use_gitignore {
+        let gitignore = dir.join(".gitignore");
+        if gitignore.is_file() {
+            let mut contents = String::new();
+            File::open(&gitignore)?.read_to_string(&mut contents)?;
+            rules.extend(contents.lines().filter_map(|line| IgnoreRule::parse(prefix, line)));
+        }
+    }
+
+    let mut children: Vec<(String, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        children.push((entry.file_name().to_string_lossy().into_owned(), entry.path()));
+    }
+    children.sort();
+
+    for (name, path) in children {
+        let link = fs::symlink_metadata(&path)?.file_type().is_symlink();
+        if link && !options.follow_symlinks {
+            continue;
+        }
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            // a link to nothing
+            Err(ref e) if link && e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let relative = format!("{}{}", prefix, name);
+        if name == ".git" || is_ignored(rules, &relative, metadata.is_dir()) {
+            continue;
+        }
+        if metadata.is_dir() {
+            collect_dir(&path, &format!("{}/", relative), options, rules, visited, entries)?;
+        } else if metadata.is_file() {
+            entries.push(ZipEntry { path, name: relative });
+        }
+    }
+
+    // a .gitignore only applies within its own directory
+    rules.truncate(rule_count);
+    Ok(())
+}
+
+/// Write a zip of `entries` to `out`.
+pub fn write_zip<W: Write + Seek>(entries: &[ZipEntry], mut out: W) -> io::Result<ZipSummary> {
+    if entries.len() > 0xFFFF {
+        return Err(too_big(format!("{} files is more than a zip can hold (65535)", entries.len())));
+    }
+    let mut central = Vec::new();
+    let mut bytes = 0;
+    for entry in entries {
+        let offset = out.seek(SeekFrom::Current(0))?;
+        let (time, date) = dos_date_time(&entry.path);
+        let header = LocalHeader { name: &entry.name, time, date, crc: 0, compressed_len: 0, len: 0 };
+
+        // the header has to come first, but its checksum and sizes are only known after the contents
+        header.write(&mut out)?;
+        let start = out.seek(SeekFrom::Current(0))?;
+        let (crc, len) = {
+            let mut encoder = deflate::Encoder::new(&mut out);
+            let copied = copy_with_crc(&mut File::open(&entry.path)?, &mut encoder)?;
+            encoder.finish().into_result()?;
+            copied
+        };
+        let end = out.seek(SeekFrom::Current(0))?;
+        if len > MAX_SIZE {
+            return Err(too_big(format!("{} is over 4 GiB, which is more than a zip can hold", entry.name)));
+        }
+        if end > MAX_SIZE {
+            return Err(too_big("the zip would be over 4 GiB, which is more than it can hold".to_string()));
+        }
+        out.seek(SeekFrom::Start(offset))?;
+        let header = LocalHeader { crc, compressed_len: (end - start) as u32, len: len as u32, ..header };
+        header.write(&mut out)?;
+        out.seek(SeekFrom::Start(end))?;
+
+        header.write_central(offset as u32, &mut central)?;
+        bytes += len;
+    }
+    let central_offset = out.seek(SeekFrom::Current(0))?;
+    if central_offset + central.len() as u64 + 22 > MAX_SIZE {
+        return Err(too_big("the zip would be over 4 GiB, which is more than it can hold".to_string()));
+    }
+    out.write_all(&central)?;
+    // end of central directory record
+    out.write_all(&le32(0x0605_4b50))?;
+    out.write_all(&[0, 0, 0, 0])?;
+    out.write_all(&le16(entries.len() as u16))?;
+    out.write_all(&le16(entries.len() as u16))?;
+    out.write_all(&le32(central.len() as u32))?;
+    out.write_all(&le32(central_offset as u32))?;
+    out.write_all(&[0, 0])?;
+    out.flush()?;
+    Ok(ZipSummary { files: entries.len(), bytes })
+}
+
+/// The most a zip without ZIP64 can hold: both the size of each file, and the size of the whole zip.
+const MAX_SIZE: u64 = 0xFFFF_FFFF;
+
+fn too_big(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// A zip file in the temp directory, which is deleted when this is dropped
+/// (so it's cleaned up whether or not the upload works).
+pub struct TempZip {
+    pub path: PathBuf,
+    pub summary: ZipSummary,
+}
+
+impl TempZip {
+    /// Zip up `root` into a new file in the temp directory.
+    pub fn create(root: &Path, options: &ZipOptions) -> io::Result<TempZip> {
+        let entries = collect_entries(root, options)?;
+        let path = ::std::env::temp_dir().join(format!("codedx-client-{}-{}.zip", process::id(), time::precise_time_ns()));
+        let file = File::create(&path)?;
+        // from here on, dropping the TempZip (even on an error) deletes the file
+        let mut zip = TempZip { path, summary: ZipSummary { files: 0, bytes: 0 } };
+        zip.summary = write_zip(&entries, io::BufWriter::new(file))?;
+        Ok(zip)
+    }
+}
+
+impl Drop for TempZip {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The fields of an entry's local header that aren't always the same.
+#[derive(Copy, Clone)]
+struct LocalHeader<'a> {
+    name: &'a str,
+    time: u16,
+    date: u16,
+    crc: u32,
+    compressed_len: u32,
+    len: u32,
+}
+
+/// Entries are "version 2.0" (deflated), with UTF-8 names.
+const VERSION: u16 = 20;
+const FLAG_UTF8: u16 = 0x0800;
+const METHOD_DEFLATE: u16 = 8;
+
+impl <'a> LocalHeader<'a> {
+    /// The parts both headers have: version needed, flags, method, time, date, CRC, sizes, name length.
+    fn common(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&le16(VERSION));
+        out.extend_from_slice(&le16(FLAG_UTF8));
+        out.extend_from_slice(&le16(METHOD_DEFLATE));
+        out.extend_from_slice(&le16(self.time));
+        out.extend_from_slice(&le16(self.date));
+        out.extend_from_slice(&le32(self.crc));
+        out.extend_from_slice(&le32(self.compressed_len));
+        out.extend_from_slice(&le32(self.len));
+        out.extend_from_slice(&le16(self.name.len() as u16));
+        out
+    }
+
+    fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&le32(0x0403_4b50))?;
+        out.write_all(&self.common())?;
+        out.write_all(&[0, 0])?; // no extra field
+        out.write_all(self.name.as_bytes())
+    }
+
+    fn write_central<W: Write>(&self, offset: u32, out: &mut W) -> io::Result<()> {
+        out.write_all(&le32(0x0201_4b50))?;
+        out.write_all(&le16(VERSION))?; // version made by
+        out.write_all(&self.common())?;
+        // extra field and comment lengths, disk number, and attributes
+        out.write_all(&[0; 12])?;
+        out.write_all(&le32(offset))?;
+        out.write_all(self.name.as_bytes())
+    }
+}
+
+fn le16(n: u16) -> [u8; 2] {
+    [n as u8, (n >> 8) as u8]
+}
+
+fn le32(n: u32) -> [u8; 4] {
+    [n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8]
+}
+
+/// Copy everything from `from` to `to`, and get its CRC-32 and length.
+fn copy_with_crc<R: Read, W: Write>(from: &mut R, to: &mut W) -> io::Result<(u32, u64)> {
+    let table = crc_table();
+    let mut crc = 0xFFFF_FFFFu32;
+    let mut len = 0u64;
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let n = match from.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for &byte in &buffer[..n] {
+            crc = table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+        to.write_all(&buffer[..n])?;
+        len += n as u64;
+    }
+    Ok((!crc, len))
+}
+
+fn crc_table() -> Vec<u32> {
+    (0..256u32).map(|n| {
+        (0..8).fold(n, |c, _| if c & 1 == 1 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 })
+    }).collect()
+}
+
+/// A file's modification time, as the MS-DOS time and date a zip wants (in UTC, since a zip has no time zone).
+fn dos_date_time(path: &Path) -> (u16, u16) {
+    let secs = fs::metadata(path).and_then(|m| m.modified()).ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs() as i64);
+    let tm = time::at_utc(time::Timespec::new(secs, 0));
+    // the format starts at 1980
+    if tm.tm_year < 80 {
+        return (0, (1 << 5) | 1);
+    }
+    let time = ((tm.tm_hour as u16) << 11) | ((tm.tm_min as u16) << 5) | ((tm.tm_sec as u16) / 2);
+    let date = (((tm.tm_year - 80) as u16) << 9) | (((tm.tm_mon + 1) as u16) << 5) | (tm.tm_mday as u16);
+    (time, date)
+}
+
+#[cfg(test)]
+fn test_dir(name: &str, files: &[&str]) -> ::test_dir::TestDir {
+    let dir = ::test_dir::TestDir::new(&format!("zip-{}", name));
+    for file in files {
+        dir.file(file, file.as_bytes());
+    }
+    dir
+}
+
+#[test]
+fn test_parse_dir_spec(){
+    assert_eq!(parse_dir_spec("web/src:web-source.zip"), (PathBuf::from("web/src"), "web-source.zip".to_string()));
+    assert_eq!(parse_dir_spec("C:\\work\\src"), (PathBuf::from("C:\\work\\src"), "src.zip".to_string()));
+    assert_eq!(parse_dir_spec("no/such/dir/app"), (PathBuf::from("no/such/dir/app"), "app.zip".to_string()));
+}
+
+#[test]
+fn test_collect_entries(){
+    let dir = test_dir("collect", &[
+        "src/main.js", "src/app.log", "node_modules/left-pad/index.js", ".git/HEAD",
+        ".gitignore", "build/out.js", "build/keep.js", "docs/readme.md",
+    ]);
+    fs::write(dir.join(".gitignore"), "# generated\nbuild/\n!build/keep.js\n*.log\n").unwrap();
+    let names = |options: &ZipOptions| -> Vec<String> {
+        collect_entries(dir.path(), options).unwrap().into_iter().map(|e| e.name).collect()
+    };
+
+    let plain = names(&ZipOptions::default());
+    let excluded = names(&ZipOptions { excludes: vec!["node_modules/**".to_string(), "*.md".to_string()], ..ZipOptions::default() });
+    let gitignored = names(&ZipOptions { use_gitignore: true, ..ZipOptions::default() });
+
+    assert_eq!(plain, vec![".gitignore", "build/keep.js", "build/out.js", "docs/readme.md", "node_modules/left-pad/index.js", "src/app.log", "src/main.js"]);
+    assert_eq!(excluded, vec![".gitignore", "build/keep.js", "build/out.js", "src/app.log", "src/main.js"]);
+    // git can't bring back a file from an ignored directory, and neither can this
+    assert_eq!(gitignored, vec![".gitignore", "docs/readme.md", "node_modules/left-pad/index.js", "src/main.js"]);
+}
+
+#[test]
+fn test_write_zip(){
+    let dir = test_dir("write", &["a.txt", "sub/b.txt"]);
+    let entries = collect_entries(dir.path(), &ZipOptions::default()).unwrap();
+    let mut zip = io::Cursor::new(Vec::new());
+    let summary = write_zip(&entries, &mut zip).unwrap();
+    let zip = zip.into_inner();
+
+    assert_eq!(summary, ZipSummary { files: 2, bytes: 14 });
+    let u16_at = |i: usize| zip[i] as u16 | (zip[i + 1] as u16) << 8;
+    let u32_at = |i: usize| u16_at(i) as u32 | (u16_at(i + 2) as u32) << 16;
+    // the end of central directory record: two entries, and where the directory starts
+    let eocd = zip.len() - 22;
+    assert_eq!(u32_at(eocd), 0x0605_4b50);
+    assert_eq!(u16_at(eocd + 10), 2);
+    let central = u32_at(eocd + 16) as usize;
+    assert_eq!(u32_at(central), 0x0201_4b50);
+    // the first entry: "a.txt", whose CRC-32 is that of its contents ("a.txt")
+    assert_eq!(&zip[central + 46..central + 51], b"a.txt");
+    assert_eq!(u32_at(central + 16), 0xC1EB_F7BA);
+    assert_eq!(u32_at(0), 0x0403_4b50);
+    assert_eq!(u16_at(8), METHOD_DEFLATE);
+    assert_eq!(u32_at(14), 0xC1EB_F7BA);
+    assert_eq!(u32_at(22), 5);
+    assert_eq!(&zip[30..35], b"a.txt");
+    // the contents, deflated
+    let compressed = u32_at(18) as usize;
+    assert_eq!(u32_at(central + 20) as usize, compressed);
+    let mut contents = String::new();
+    deflate::Decoder::new(&zip[35..35 + compressed]).read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "a.txt");
+}
+
+#[test]
+fn test_temp_zip_is_removed(){
+    let dir = test_dir("temp", &["a.txt"]);
+    let zip = TempZip::create(dir.path(), &ZipOptions::default()).unwrap();
+    let path = zip.path.clone();
+    assert!(path.is_file());
+    drop(zip);
+    assert!(!path.exists());
+}
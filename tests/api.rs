@@ -0,0 +1,86 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Using the library the way another program would: only through its public API, against a fake server.
+
+extern crate codedx_client;
+#[macro_use] extern crate serde_json;
+
+use codedx_client::prelude::*;
+use codedx_client::test_server::{TestResponse, TestServer};
+use std::io::Cursor;
+use std::time::Duration;
+
+fn count_projects(api: &CodeDxApi) -> ApiResult<usize> {
+    api.get_projects().map(|projects| projects.len())
+}
+
+#[test]
+fn test_list_projects(){
+    let server = TestServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+        ("POST", "/codedx/api/projects/query") => TestResponse::json(200, json!([
+            { "id": 1, "name": "WebGoat", "parentId": null },
+            { "id": 2, "name": "WebGoat Legacy", "parentId": 1 },
+        ])),
+        _ => TestResponse::status(404),
+    });
+    let client = server.client();
+
+    let projects = client.get_projects().unwrap();
+    assert_eq!(projects.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), vec!["WebGoat", "WebGoat Legacy"]);
+    assert_eq!(projects[1].parent_id, Some(1));
+    // the same operations, through the trait
+    assert_eq!(count_projects(&client).unwrap(), 2);
+    assert!(server.requests().iter().all(|req| req.header("API-Key") == Some("test-key")));
+}
+
+#[test]
+fn test_analyze_and_wait(){
+    let server = TestServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+        ("POST", "/codedx/api/projects/5/analysis") => TestResponse::json(202, json!({ "analysisId": 77, "jobId": "j77" })),
+        ("GET", "/codedx/api/jobs/j77") => TestResponse::json(200, json!({ "jobId": "j77", "status": "completed" })),
+        _ => TestResponse::status(404),
+    });
+    let client = server.client();
+
+    let input = UploadInput::Stream { name: "scan.xml".to_string(), content: Box::new(Cursor::new(b"<report/>".to_vec())) };
+    let started = client.start_analysis_with_inputs(5, vec![input]).unwrap();
+    assert_eq!((started.analysis_id, started.job_id.as_str()), (77, "j77"));
+    assert_eq!(client.poll_job_completion(&started.job_id, Duration::from_millis(1)).unwrap(), JobStatus::Completed);
+
+    let upload = server.requests().into_iter().find(|req| req.method == "POST").unwrap();
+    let body = String::from_utf8_lossy(&upload.body).into_owned();
+    assert!(body.contains("filename=\"scan.xml\"") && body.contains("<report/>"), "{}", body);
+}
+
+#[test]
+fn test_errors(){
+    let server = TestServer::start(|req| match req.path.as_str() {
+        "/codedx/api/projects/9" => TestResponse::json(403, json!({ "error": "You don't have permission to view project 9" })),
+        _ => TestResponse::status(404),
+    });
+    let client = server.client();
+
+    let error = client.get_project(9).unwrap_err();
+    assert_eq!(error.kind(), "non_success");
+    assert_eq!(error.status(), Some(StatusCode::Forbidden));
+    assert!(error.to_string().contains("You don't have permission to view project 9"), "{}", error);
+
+    match client.get_job_status("gone") {
+        Err(ApiError::JobNotFound(ref job_id)) => assert_eq!(job_id, "gone"),
+        other => panic!("expected JobNotFound, got {:?}", other),
+    }
+}